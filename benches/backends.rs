@@ -0,0 +1,88 @@
+//! Compares the storage backends of the `Index` on the `lear.txt` vocabulary.
+//!
+//! Run with `cargo bench --bench backends`.
+
+mod common;
+
+use common::vocabulary;
+use index::hash::IndexHasherBuilder;
+use index::{Backend, Index, Parameters};
+
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 20;
+
+/// Returns the fastest of `ROUNDS` runs of `f`.
+fn best_of<F: FnMut() -> Duration>(mut f: F) -> Duration {
+    (0..ROUNDS).map(|_| f()).min().unwrap()
+}
+
+fn bench(name: &str, params: Parameters<IndexHasherBuilder>, words: &[String], misses: &[String]) {
+    let build = || {
+        let mut index = Index::with_capacity_and_parameters(1, params.clone());
+        for (i, word) in words.iter().enumerate() {
+            index.insert(word.clone(), i);
+        }
+        index
+    };
+
+    let insert = best_of(|| {
+        let start = Instant::now();
+        let index = build();
+        let elapsed = start.elapsed();
+        assert_eq!(index.len(), words.len());
+        elapsed
+    });
+
+    let index = build();
+
+    let hit = best_of(|| {
+        let start = Instant::now();
+        let found = words.iter().filter(|w| index.get(w.as_str()).is_some()).count();
+        let elapsed = start.elapsed();
+        assert_eq!(found, words.len());
+        elapsed
+    });
+
+    let miss = best_of(|| {
+        let start = Instant::now();
+        let found = misses.iter().filter(|w| index.get(w.as_str()).is_some()).count();
+        let elapsed = start.elapsed();
+        assert_eq!(found, 0);
+        elapsed
+    });
+
+    println!(
+        "{:<28} {:>12?} {:>12?} {:>12?} {:>10} {:>6.2}",
+        name, insert, hit, miss, index.capacity(), index.load()
+    );
+}
+
+fn main() {
+    let words = vocabulary();
+    let misses: Vec<String> = words.iter().map(|w| format!("{}#", w)).collect();
+
+    println!("{} distinct words, best of {} rounds\n", words.len(), ROUNDS);
+    println!(
+        "{:<28} {:>12} {:>12} {:>12} {:>10} {:>6}",
+        "backend", "insert", "hit", "miss", "capacity", "load"
+    );
+
+    let open = Parameters::default();
+    bench("open addressing (0.7)", open.clone(), &words, &misses);
+
+    for &max_load in &[0.7, 1.0, 2.0, 4.0] {
+        let params = Parameters { max_load, backend: Backend::SeparateChaining, ..open.clone() };
+        bench(&format!("separate chaining ({})", max_load), params, &words, &misses);
+    }
+
+    for &max_load in &[0.5, 0.7, 0.9] {
+        let params = Parameters { max_load, backend: Backend::Cuckoo, ..open.clone() };
+        bench(&format!("cuckoo ({})", max_load), params, &words, &misses);
+    }
+
+    for &max_load in &[0.7, 0.9] {
+        let params = Parameters { max_load, backend: Backend::Hopscotch, ..open.clone() };
+        bench(&format!("hopscotch ({})", max_load), params, &words, &misses);
+    }
+}
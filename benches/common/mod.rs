@@ -0,0 +1,16 @@
+//! Data sets shared by the benchmarks.
+
+use index::Index;
+
+use std::fs;
+
+/// Distinct lowercase words of `lear.txt`.
+pub fn vocabulary() -> Vec<String> {
+    let text = fs::read_to_string("lear.txt").expect("Error while reading file: `lear.txt`");
+
+    let mut index: Index<String, ()> = Index::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        index.insert(word.to_lowercase(), ());
+    }
+    index.drain().map(|(word, _)| word).collect()
+}
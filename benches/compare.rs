@@ -0,0 +1,215 @@
+//! Compares the `Index` with `std::collections::HashMap` over several key types,
+//! hashers and `Parameters`, timing with `std::time` only.
+//!
+//! Every operation is timed over the whole key set, and the best of `ROUNDS`
+//! runs is reported in nanoseconds per key:
+//!
+//! - `insert`: inserting every key in a table sized for them,
+//! - `grow`: inserting every key from a single bucket, resizing on the way,
+//! - `hit` and `miss`: looking up present and missing keys,
+//! - `iter`: iterating over the entries,
+//! - `drain`: draining the entries.
+//!
+//! Run with `cargo bench --bench compare`.
+
+mod common;
+
+use common::vocabulary;
+use index::hash::IndexHasherBuilder;
+use index::{Index, Parameters, Probing};
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 10;
+const KEYS: usize = 10_000;
+
+/// Operations timed on every table.
+trait Table<K>: Clone {
+    fn insert(&mut self, key: K, value: usize);
+    fn hit(&self, key: &K) -> bool;
+    fn iter_sum(&self) -> usize;
+    fn drain_count(&mut self) -> usize;
+    fn len(&self) -> usize;
+}
+
+impl<K, S> Table<K> for Index<K, usize, S>
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher + Clone,
+{
+    fn insert(&mut self, key: K, value: usize) {
+        Index::insert(self, key, value);
+    }
+
+    fn hit(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    fn iter_sum(&self) -> usize {
+        self.iter().map(|pair| pair.1).sum()
+    }
+
+    fn drain_count(&mut self) -> usize {
+        self.drain().count()
+    }
+
+    fn len(&self) -> usize {
+        Index::len(self)
+    }
+}
+
+impl<K, S> Table<K> for HashMap<K, usize, S>
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher + Clone,
+{
+    fn insert(&mut self, key: K, value: usize) {
+        HashMap::insert(self, key, value);
+    }
+
+    fn hit(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    fn iter_sum(&self) -> usize {
+        self.values().sum()
+    }
+
+    fn drain_count(&mut self) -> usize {
+        self.drain().count()
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+}
+
+/// Returns the fastest of `ROUNDS` runs of `f`.
+fn best_of<F: FnMut() -> Duration>(mut f: F) -> Duration {
+    (0..ROUNDS).map(|_| f()).min().unwrap()
+}
+
+/// Times `f`, checking its result.
+fn timed<T: PartialEq + std::fmt::Debug, F: FnOnce() -> T>(expected: T, f: F) -> Duration {
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    assert_eq!(result, expected);
+    elapsed
+}
+
+/// Times every operation on tables built by `make` with the specified capacity, and prints a row.
+fn bench<K, T, F>(name: &str, make: F, keys: &[K], misses: &[K])
+where
+    K: Clone,
+    T: Table<K>,
+    F: Fn(usize) -> T,
+{
+    let fill = |table: &mut T| {
+        for (i, key) in keys.iter().enumerate() {
+            table.insert(key.clone(), i);
+        }
+        table.len()
+    };
+
+    let insert = best_of(|| {
+        let mut table = make(keys.len());
+        timed(keys.len(), || fill(&mut table))
+    });
+
+    let grow = best_of(|| {
+        let mut table = make(1);
+        timed(keys.len(), || fill(&mut table))
+    });
+
+    let mut table = make(1);
+    fill(&mut table);
+
+    let hit = best_of(|| timed(keys.len(), || keys.iter().filter(|k| table.hit(k)).count()));
+    let miss = best_of(|| timed(0, || misses.iter().filter(|k| table.hit(k)).count()));
+
+    let sum = (0..keys.len()).sum::<usize>();
+    let iter = best_of(|| timed(sum, || table.iter_sum()));
+
+    let drain = best_of(|| {
+        let mut table = table.clone();
+        timed(keys.len(), || table.drain_count())
+    });
+
+    let per_key = |d: Duration| d.as_nanos() as f64 / keys.len() as f64;
+    println!(
+        "{:<36} {:>8.1} {:>8.1} {:>8.1} {:>8.1} {:>8.1} {:>8.1}",
+        name,
+        per_key(insert),
+        per_key(grow),
+        per_key(hit),
+        per_key(miss),
+        per_key(iter),
+        per_key(drain)
+    );
+}
+
+/// Compares the tables on a key set.
+fn compare<K: Hash + Eq + Clone>(title: &str, keys: &[K], misses: &[K]) {
+    println!("\n{} ({} keys, ns per key, best of {} rounds)\n", title, keys.len(), ROUNDS);
+    println!(
+        "{:<36} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "table", "insert", "grow", "hit", "miss", "iter", "drain"
+    );
+
+    bench("HashMap (RandomState)", |n| HashMap::with_capacity_and_hasher(n, RandomState::new()), keys, misses);
+    bench("HashMap (IndexHasherBuilder)", |n| HashMap::with_capacity_and_hasher(n, IndexHasherBuilder), keys, misses);
+
+    let default: Parameters<IndexHasherBuilder> = Parameters::default();
+    let sized = |n: usize, max_load: f64| (n as f64 / max_load) as usize + 1;
+
+    bench(
+        "Index (RandomState)",
+        |n| {
+            let params = Parameters { hasher_builder: RandomState::new(), ..Parameters::default() };
+            Index::with_capacity_and_parameters(sized(n, params.max_load), params)
+        },
+        keys,
+        misses,
+    );
+    bench("Index (IndexHasherBuilder)", |n| Index::with_capacity_and_parameters(sized(n, 0.7), default.clone()), keys, misses);
+
+    for &(probe_name, probe) in &[
+        ("linear", Probing::Linear),
+        ("quadratic", Probing::Quadratic),
+        ("triangular", Probing::Triangular),
+        ("double hashing", Probing::DoubleHashing),
+    ] {
+        for &max_load in &[0.5, 0.7, 0.9] {
+            let params = Parameters { max_load, probe, ..default.clone() };
+            bench(
+                &format!("Index ({}, {})", probe_name, max_load),
+                |n| Index::with_capacity_and_parameters(sized(n, max_load), params.clone()),
+                keys,
+                misses,
+            );
+        }
+    }
+}
+
+/// Scrambles `i` so that the integer keys are not sequential.
+fn scramble(i: u64) -> u64 {
+    i.wrapping_mul(0x9e37_79b9_7f4a_7c15).rotate_left(17)
+}
+
+fn main() {
+    let integers: Vec<u64> = (0..KEYS as u64).map(scramble).collect();
+    let missing: Vec<u64> = (KEYS as u64..2 * KEYS as u64).map(scramble).collect();
+    compare("u64 keys", &integers, &missing);
+
+    let short: Vec<String> = (0..KEYS).map(|i| format!("k{}", i)).collect();
+    let missing: Vec<String> = short.iter().map(|k| format!("{}#", k)).collect();
+    compare("short string keys", &short, &missing);
+
+    let words = vocabulary();
+    let missing: Vec<String> = words.iter().map(|w| format!("{}#", w)).collect();
+    compare("lear.txt words", &words, &missing);
+}
@@ -0,0 +1,9 @@
+//! Decodes values of the `codec` format, checking that they encode back to the input.
+//!
+//! Run with `cargo fuzz run decode`, the inputs of `corpus/decode` are replayed by `cargo test`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| index::fuzzing::decode(data));
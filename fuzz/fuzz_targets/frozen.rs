@@ -0,0 +1,9 @@
+//! Opens and queries a frozen index file through a `FileIndex`.
+//!
+//! Run with `cargo fuzz run frozen`, the inputs of `corpus/frozen` are replayed by `cargo test`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| index::fuzzing::frozen(data));
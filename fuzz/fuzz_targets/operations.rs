@@ -0,0 +1,9 @@
+//! Applies `Parameters` and a sequence of operations to an `Index`, checked against a `HashMap`.
+//!
+//! Run with `cargo fuzz run operations`, the inputs of `corpus/operations` are replayed by `cargo test`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| index::fuzzing::operations(data));
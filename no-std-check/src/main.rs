@@ -0,0 +1,154 @@
+//! `no_std` binary using the `Index` with `alloc` only.
+//!
+//! The binary provides the panic handler and the global allocator (on top of
+//! `malloc`) that the standard library would, so linking fails if the library
+//! pulls the standard library in. It exits with a non-zero status on failure.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::string::String;
+use core::alloc::{GlobalAlloc, Layout};
+use core::time::Duration;
+use core::fmt::Write;
+use core::panic::PanicInfo;
+
+use index::raw::CountingStorage;
+use index::array::ArrayIndex;
+use index::cache::{Budget, Eviction, LruIndex};
+use index::expiry::{ExpiringIndex, ManualClock};
+use index::filter::Filter;
+use index::hash::IndexHasherBuilder;
+use index::perfect::PerfectIndex;
+use index::persistent::PersistentIndex;
+use index::strings::{Interner, StrIndex};
+use index::{Backend, Index, Parameters};
+
+#[link(name = "c")]
+extern "C" {
+    fn aligned_alloc(align: usize, size: usize) -> *mut u8;
+    fn free(ptr: *mut u8);
+    fn write(fd: i32, buf: *const u8, count: usize) -> isize;
+    fn abort() -> !;
+}
+
+struct Malloc;
+
+unsafe impl GlobalAlloc for Malloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // `aligned_alloc` wants a size multiple of the alignment
+        let size = (layout.size() + layout.align() - 1) & !(layout.align() - 1);
+        aligned_alloc(layout.align(), size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        free(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Malloc = Malloc;
+
+fn print(message: &str) {
+    unsafe { write(2, message.as_ptr(), message.len()) };
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut message = String::new();
+    let _ = writeln!(message, "{}", info);
+    print(&message);
+    unsafe { abort() }
+}
+
+/// Never called with `panic = "abort"`, but referenced by the precompiled `alloc`.
+#[no_mangle]
+pub extern "C" fn rust_eh_personality() {}
+
+#[no_mangle]
+pub extern "C" fn main(_argc: i32, _argv: *const *const u8) -> i32 {
+    for &backend in &[Backend::OpenAddressing, Backend::SeparateChaining, Backend::Cuckoo, Backend::Hopscotch] {
+        let storage = CountingStorage::new();
+        let params: Parameters<IndexHasherBuilder> = Parameters { backend, ..Parameters::default() };
+        let mut index = Index::with_capacity_and_parameters_in(1, params, storage.clone());
+
+        for i in 0..1000 {
+            index.insert(i, i * i);
+        }
+
+        assert_eq!(index.len(), 1000);
+        assert!((0..1000).all(|i| *index.get(&i).unwrap() == i * i));
+        assert!(index.validate_invariants().is_ok());
+        assert_eq!(index.drain().count(), 1000);
+
+        drop(index);
+        assert_eq!(storage.bytes(), 0);
+    }
+
+    let mut array: ArrayIndex<u32, u32, 64> = ArrayIndex::new();
+    for i in 0..64 {
+        array.insert(i, i).unwrap();
+    }
+    assert_eq!(array.insert(64, 64), Err((64, 64)));
+
+    let mut words: StrIndex<u32> = StrIndex::new();
+    let mut files = Interner::new();
+    for word in "the king the fool the crown".split(' ') {
+        let count = words.get(word).map_or(0, |count| *count);
+        words.insert(word, count + 1);
+        files.intern("lear.txt");
+    }
+    assert_eq!(*words.get("the").unwrap(), 3);
+    assert_eq!(files.len(), 1);
+
+    let perfect = PerfectIndex::build((0..1000u32).map(|i| (i, i * 2)));
+    assert!((0..1000).all(|i| perfect.get(&i) == Some(&(i * 2))));
+    assert_eq!(perfect.get(&1000), None);
+
+    let params: Parameters<IndexHasherBuilder> = Parameters {
+        filter: Some(Filter::Cuckoo { false_positive_rate: 0.01 }),
+        ..Parameters::default()
+    };
+    let mut filtered = Index::with_capacity_and_parameters(1, params);
+    for i in 0..1000u32 {
+        filtered.insert(i, i);
+    }
+    assert!((0..1000).all(|i| filtered.get(&i).is_some()));
+    assert!((1000..2000).all(|i| filtered.get(&i).is_none()));
+
+    let mut cache = LruIndex::with_policy(Budget::Entries(100), Eviction::Clock);
+    for i in 0..1000u32 {
+        cache.get(&(i / 2));
+        cache.insert(i, i);
+    }
+    assert_eq!(cache.len(), 100);
+    assert!(cache.contains_key(&999) && !cache.contains_key(&0));
+
+    let mut expiring = ExpiringIndex::new(ManualClock::new());
+    for i in 0..1000u64 {
+        expiring.insert_with_ttl(i, i, Duration::from_secs(i % 2 + 1));
+    }
+    expiring.clock().advance(Duration::from_secs(1));
+    assert_eq!(expiring.purge_expired().len(), 500);
+    assert!((0..1000).all(|i| expiring.contains_key(&i) == (i % 2 == 1)));
+
+    let base: PersistentIndex<u32, u32> = (0..1000).map(|i| (i, i)).collect();
+    let next = base.insert(1000, 1000).remove(&0);
+    assert_eq!((base.len(), next.len()), (1000, 1000));
+    assert_eq!(base.diff(&next).len(), 2);
+
+    let (mut evens, mut odds) = (Index::new(), Index::new());
+    for i in 0..1000u32 {
+        evens.insert(i * 2, 1);
+        odds.insert(i * 3, 1);
+    }
+    assert_eq!(evens.diff(&odds).count(), 1332);
+    let merged = evens.merge_with(odds, |_, a, b| a + b);
+    assert_eq!(merged.len(), 1666);
+    assert_eq!(*merged.get(&6).unwrap(), 2);
+
+    print("index works without std\n");
+    0
+}
@@ -0,0 +1,285 @@
+//! Module implementing [`ArrayIndex`], a fixed-capacity hash table
+//! stored inline, which never allocates.
+//!
+//! The `ArrayIndex` holds its `N` buckets in an array and uses the open
+//! addressing lookup of the [`Index`] (same hashing and [`Probing`]
+//! strategies). It never grows: once its probe sequence finds no empty
+//! bucket for a new key, `insert` hands the key-value pair back.
+//!
+//! [`ArrayIndex`]: struct.ArrayIndex.html
+//! [`Index`]: ../struct.Index.html
+//! [`Probing`]: ../enum.Probing.html
+
+use super::hash::*;
+use super::iter::*;
+use super::{find_in, probe_hashes, Bucket, Find, Probing};
+
+use core::borrow::Borrow;
+use core::cell::{Ref, RefCell, RefMut};
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+
+/// Hash table of at most `N` entries stored inline, without allocation.
+///
+/// The default probing strategy is linear probing, which reaches every
+/// bucket so that the table can be filled. With other strategies, insertions
+/// may be rejected before the table is full (see [`Probing::covers`]).
+///
+/// # Example
+///
+/// ```
+/// use index::array::ArrayIndex;
+///
+/// // term frequencies of a document
+/// let mut frequencies: ArrayIndex<&str, u32, 8> = ArrayIndex::new();
+///
+/// for term in "the king the fool the crown".split(' ') {
+///     let count = frequencies.get(term).map_or(0, |count| *count);
+///     frequencies.insert(term, count + 1).unwrap();
+/// }
+///
+/// assert_eq!(*frequencies.get("the").unwrap(), 3);
+/// assert_eq!(frequencies.len(), 4);
+/// ```
+///
+/// [`Probing::covers`]: ../enum.Probing.html#method.covers
+#[derive(Clone)]
+pub struct ArrayIndex<K, V, const N: usize, S = IndexHasherBuilder> {
+    hasher_builder: S,
+    probe: Probing,
+    len: usize,
+    table: [Bucket<K, V>; N],
+}
+
+impl<K, V, const N: usize> ArrayIndex<K, V, N, IndexHasherBuilder>
+where
+    K: Hash + Eq,
+{
+    /// Creates an empty `ArrayIndex` with the default hasher builder and linear probing.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::array::ArrayIndex;
+    ///
+    /// let index: ArrayIndex<u32, u32, 16> = ArrayIndex::new();
+    ///
+    /// assert_eq!(index.capacity(), 16);
+    /// ```
+    pub fn new() -> ArrayIndex<K, V, N, IndexHasherBuilder> {
+        ArrayIndex::with_hasher_and_probing(IndexHasherBuilder, Probing::Linear)
+    }
+}
+
+impl<K, V, const N: usize, S> ArrayIndex<K, V, N, S> {
+    /// Creates an empty `ArrayIndex` with the specified hasher builder and probing strategy.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::hash_map::RandomState;
+    /// use index::Probing;
+    /// use index::array::ArrayIndex;
+    ///
+    /// let mut index: ArrayIndex<u32, &str, 64, _> =
+    ///     ArrayIndex::with_hasher_and_probing(RandomState::new(), Probing::Triangular);
+    ///
+    /// index.insert(1, "one").unwrap();
+    /// ```
+    pub fn with_hasher_and_probing(hasher_builder: S, probe: Probing) -> ArrayIndex<K, V, N, S> {
+        ArrayIndex {
+            hasher_builder,
+            probe,
+            len: 0,
+            table: core::array::from_fn(|_| Bucket::None),
+        }
+    }
+
+    /// Returns the number of buckets of the `ArrayIndex`, `N`.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of elements in the `ArrayIndex`.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the `ArrayIndex` contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the hasher builder used in the `ArrayIndex`.
+    pub fn hasher(&self) -> &S {
+        &self.hasher_builder
+    }
+
+    /// Returns the probing strategy of the `ArrayIndex`.
+    pub fn probe(&self) -> Probing {
+        self.probe
+    }
+
+    /// Clears the `ArrayIndex`, replacing all entries with empty buckets.
+    pub fn clear(&mut self) {
+        for bucket in self.table.iter_mut() {
+            *bucket = Bucket::None;
+        }
+        self.len = 0;
+    }
+
+    /// Returns an iterator over the keys of the `ArrayIndex`.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys::new(&self.table)
+    }
+
+    /// Returns an iterator over the values of the `ArrayIndex`.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values::new(&self.table)
+    }
+
+    /// Returns an iterator over the entries of the `ArrayIndex`.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(&self.table)
+    }
+
+    /// Clears the `ArrayIndex`, returning the entries as an iterator.
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        Drain::new(&mut self.table, &mut self.len)
+    }
+}
+
+impl<K, V, const N: usize, S> ArrayIndex<K, V, N, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Searches the buckets for the entry of `key`.
+    fn find<Q>(&self, key: &Q) -> Find<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hashes = probe_hashes(&self.hasher_builder, self.probe, key);
+        find_in(&self.table, self.probe, hashes, |p| key.eq(p.0.borrow())).0
+    }
+
+    /// Inserts key-value pair in the `ArrayIndex`, returning the replaced entry if the
+    /// key was present.
+    ///
+    /// If the probe sequence of the key finds no empty bucket (the table is full),
+    /// the pair is returned as an error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::array::ArrayIndex;
+    ///
+    /// let mut index: ArrayIndex<&str, i32, 2> = ArrayIndex::new();
+    ///
+    /// assert!(index.insert("one", 1).unwrap().is_none());
+    /// assert!(index.insert("two", 2).unwrap().is_none());
+    ///
+    /// assert_eq!(index.insert("three", 3), Err(("three", 3)));
+    ///
+    /// // replacing a value still works when full
+    /// assert_eq!(index.insert("two", 4).unwrap().unwrap().into_inner(), ("two", 2));
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) -> Result<Bucket<K, V>, (K, V)> {
+        match self.find(&key) {
+            (Some(_), Some(i)) => Ok(self.table[i].replace(RefCell::new((key, value)))),
+            (None, Some(i)) => {
+                self.table[i] = Bucket::Some(RefCell::new((key, value)));
+                self.len += 1;
+                Ok(Bucket::None)
+            }
+            _ => Err((key, value)),
+        }
+    }
+
+    /// Returns a reference to the value associated with the specified key
+    /// if the lookup found a match, else it returns `None`.
+    pub fn get<Q>(&self, key: &Q) -> Option<Ref<'_, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).0.map(|pair| Ref::map(pair.borrow(), |p| &p.1))
+    }
+
+    /// Returns a mutable reference to the value associated with the specified key
+    /// if the lookup found a match, else it returns `None`.
+    pub fn get_mut<Q>(&self, key: &Q) -> Option<RefMut<'_, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).0.map(|pair| RefMut::map(pair.borrow_mut(), |p| &mut p.1))
+    }
+}
+
+impl<K, V, const N: usize, S> fmt::Debug for ArrayIndex<K, V, N, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for pair in self.iter() {
+            map.entry(&pair.0, &pair.1);
+        }
+        map.finish()
+    }
+}
+
+impl<K, V, const N: usize> Default for ArrayIndex<K, V, N, IndexHasherBuilder>
+where
+    K: Hash + Eq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::lear_vocabulary;
+
+    use std::string::String;
+
+    #[test]
+    fn fills_every_bucket() {
+        let words = lear_vocabulary();
+        let mut index: ArrayIndex<String, usize, 512> = ArrayIndex::new();
+
+        for (i, word) in words.iter().take(512).enumerate() {
+            assert!(index.insert(word.clone(), i).unwrap().is_none());
+        }
+        assert_eq!(index.len(), 512);
+
+        let rejected = index.insert(words[512].clone(), 512).unwrap_err();
+        assert_eq!(rejected, (words[512].clone(), 512));
+        assert!(index.get(&words[512]).is_none());
+
+        for (i, word) in words.iter().take(512).enumerate() {
+            assert_eq!(*index.get(word).unwrap(), i);
+        }
+        assert_eq!(index.drain().count(), 512);
+        assert!(index.is_empty());
+    }
+
+    #[test]
+    fn incomplete_probing_rejects_early() {
+        // probing the home bucket only, rejecting the first collision
+        let mut index: ArrayIndex<String, (), 64> =
+            ArrayIndex::with_hasher_and_probing(IndexHasherBuilder, Probing::Custom(|hash, _| hash));
+
+        let words = lear_vocabulary();
+        let inserted = words.iter().take_while(|word| index.insert((*word).clone(), ()).is_ok()).count();
+
+        assert!(index.len() < 64);
+        assert_eq!(index.len(), inserted);
+        assert!(words[..inserted].iter().all(|word| index.get(word).is_some()));
+    }
+}
@@ -0,0 +1,686 @@
+//! Module implementing [`LruIndex`], a hash table bounded by a budget of
+//! entries or bytes, evicting its least recently used entries.
+//!
+//! The entries live in open addressing buckets, using the lookup of the
+//! [`Index`] with linear probing, and are linked from the most to the least
+//! recent in a doubly linked list whose links are bucket positions, so that
+//! recency tracking allocates nothing. Linear probing lets removals shift the
+//! following entries back instead of leaving tombstones, relinking the moved
+//! entries.
+//!
+//! [`LruIndex`]: struct.LruIndex.html
+//! [`Index`]: ../struct.Index.html
+
+use super::hash::*;
+use super::{find_in, Bucket, Find, Probing, DEFAULT_MAX_LOAD};
+
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cell::{Ref, RefCell};
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::mem;
+
+/// End of the recency list.
+const NIL: usize = usize::MAX;
+
+/// Initial number of buckets of an `LruIndex` with a byte budget.
+const INITIAL_CAPACITY: usize = 8;
+
+/// Limit on the entries of an [`LruIndex`].
+///
+/// [`LruIndex`]: struct.LruIndex.html
+pub enum Budget<K, V> {
+    /// At most this number of entries.
+    Entries(usize),
+
+    /// At most this number of bytes, as measured by `weigh` when the entries are inserted.
+    Bytes { bytes: usize, weigh: fn(&K, &V) -> usize },
+}
+
+impl<K, V> Budget<K, V> {
+    fn limit(&self) -> usize {
+        match *self {
+            Budget::Entries(entries) => entries,
+            Budget::Bytes { bytes, .. } => bytes,
+        }
+    }
+
+    fn weigh(&self, key: &K, value: &V) -> usize {
+        match self {
+            Budget::Entries(_) => 1,
+            Budget::Bytes { weigh, .. } => weigh(key, value),
+        }
+    }
+}
+
+impl<K, V> Clone for Budget<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for Budget<K, V> {}
+
+impl<K, V> fmt::Debug for Budget<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Budget::Entries(entries) => write!(f, "Entries({})", entries),
+            Budget::Bytes { bytes, .. } => write!(f, "Bytes({})", bytes),
+        }
+    }
+}
+
+/// Choice of the entry evicted when an [`LruIndex`] exceeds its budget.
+///
+/// [`LruIndex`]: struct.LruIndex.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// Evicts the least recently used entry, every `get` moving its entry to the
+    /// front of the recency list.
+    Lru,
+
+    /// Evicts the oldest entry not used since it was last considered: `get` only
+    /// flags its entry, and a flagged entry reaching the end of the list gets a
+    /// second chance at its front. Cheaper hits than `Lru`, for read-heavy caches.
+    Clock,
+}
+
+/// Entry of an `LruIndex` bucket, with its links in the recency list.
+#[derive(Clone)]
+struct Node<V> {
+    value: V,
+    hash: usize,
+    weight: usize,
+    prev: usize, // more recent
+    next: usize, // less recent
+    referenced: bool,
+}
+
+/// Hash table bounded by a [`Budget`], evicting entries according to an
+/// [`Eviction`] policy, least recently used by default.
+///
+/// # Example
+///
+/// ```
+/// use index::cache::{Budget, LruIndex};
+///
+/// let mut cache = LruIndex::new(Budget::Entries(2));
+///
+/// cache.insert("king", 1);
+/// cache.insert("fool", 2);
+///
+/// assert_eq!(cache.get("king"), Some(&1)); // "fool" is now the least recent
+/// assert_eq!(cache.insert("crown", 3), vec![("fool", 2)]);
+///
+/// assert_eq!(*cache.peek("king").unwrap(), 1); // without promoting "king"
+/// assert_eq!(cache.insert("storm", 4), vec![("king", 1)]);
+/// ```
+///
+/// [`Budget`]: enum.Budget.html
+/// [`Eviction`]: enum.Eviction.html
+#[derive(Clone)]
+pub struct LruIndex<K, V, S = IndexHasherBuilder> {
+    hasher_builder: S,
+    budget: Budget<K, V>,
+    policy: Eviction,
+    weight: usize,
+    len: usize,
+    head: usize, // most recent
+    tail: usize, // least recent
+    table: Vec<Bucket<K, Node<V>>>,
+}
+
+impl<K, V> LruIndex<K, V, IndexHasherBuilder>
+where
+    K: Hash + Eq,
+{
+    /// Creates an empty `LruIndex` with the specified budget, evicting the least recently
+    /// used entries, with the default hasher builder.
+    pub fn new(budget: Budget<K, V>) -> LruIndex<K, V, IndexHasherBuilder> {
+        LruIndex::with_policy(budget, Eviction::Lru)
+    }
+
+    /// Creates an empty `LruIndex` with the specified budget and eviction policy,
+    /// with the default hasher builder.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::cache::{Budget, Eviction, LruIndex};
+    ///
+    /// let mut cache = LruIndex::with_policy(Budget::Entries(2), Eviction::Clock);
+    ///
+    /// cache.insert("king", 1);
+    /// cache.insert("fool", 2);
+    /// cache.get("king");
+    ///
+    /// // "king" is the oldest entry, but was used since its insertion
+    /// assert_eq!(cache.insert("crown", 3), vec![("fool", 2)]);
+    /// ```
+    pub fn with_policy(budget: Budget<K, V>, policy: Eviction) -> LruIndex<K, V, IndexHasherBuilder> {
+        LruIndex::with_policy_and_hasher(budget, policy, IndexHasherBuilder)
+    }
+}
+
+impl<K, V, S> LruIndex<K, V, S> {
+    /// Creates an empty `LruIndex` with the specified budget, eviction policy and hasher builder.
+    ///
+    /// With an entry budget, the buckets are allocated up front and never grow.
+    pub fn with_policy_and_hasher(budget: Budget<K, V>, policy: Eviction, hasher_builder: S) -> LruIndex<K, V, S> {
+        let capacity = match budget {
+            Budget::Entries(entries) => (entries as f64 / DEFAULT_MAX_LOAD) as usize + 1,
+            Budget::Bytes { .. } => INITIAL_CAPACITY,
+        };
+
+        LruIndex {
+            hasher_builder,
+            budget,
+            policy,
+            weight: 0,
+            len: 0,
+            head: NIL,
+            tail: NIL,
+            table: (0..capacity).map(|_| Bucket::None).collect(),
+        }
+    }
+
+    /// Returns the number of elements in the `LruIndex`.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the `LruIndex` contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of buckets of the `LruIndex`.
+    pub fn capacity(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Returns the part of the budget used by the entries: their number, or
+    /// their total weight in bytes.
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// Returns the budget of the `LruIndex`.
+    pub fn budget(&self) -> Budget<K, V> {
+        self.budget
+    }
+
+    /// Returns the eviction policy of the `LruIndex`.
+    pub fn policy(&self) -> Eviction {
+        self.policy
+    }
+
+    /// Returns a reference to the hasher builder used in the `LruIndex`.
+    pub fn hasher(&self) -> &S {
+        &self.hasher_builder
+    }
+
+    /// Clears the `LruIndex`, replacing all entries with empty buckets.
+    pub fn clear(&mut self) {
+        for bucket in self.table.iter_mut() {
+            *bucket = Bucket::None;
+        }
+        self.head = NIL;
+        self.tail = NIL;
+        self.weight = 0;
+        self.len = 0;
+    }
+
+    /// Returns an iterator over the entries of the `LruIndex`, from the most
+    /// to the least recent.
+    pub fn iter(&self) -> LruIter<'_, K, V> {
+        LruIter { table: &self.table, next: self.head }
+    }
+
+    /// Returns the entry of an occupied bucket.
+    fn node_mut(&mut self, i: usize) -> &mut Node<V> {
+        &mut self.table[i].as_mut().unwrap().get_mut().1
+    }
+
+    /// Removes the entry of bucket `i` from the recency list.
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = {
+            let node = self.node_mut(i);
+            (node.prev, node.next)
+        };
+
+        match prev {
+            NIL => self.head = next,
+            _ => self.node_mut(prev).next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            _ => self.node_mut(next).prev = prev,
+        }
+    }
+
+    /// Links the entry of bucket `i` at the front of the recency list.
+    fn push_front(&mut self, i: usize) {
+        let head = self.head;
+        let node = self.node_mut(i);
+        node.prev = NIL;
+        node.next = head;
+
+        match head {
+            NIL => self.tail = i,
+            _ => self.node_mut(head).prev = i,
+        }
+        self.head = i;
+    }
+
+    /// Points the neighbors of the entry moved to bucket `i` to its new bucket.
+    fn relink(&mut self, i: usize) {
+        let (prev, next) = {
+            let node = self.node_mut(i);
+            (node.prev, node.next)
+        };
+
+        match prev {
+            NIL => self.head = i,
+            _ => self.node_mut(prev).next = i,
+        }
+        match next {
+            NIL => self.tail = i,
+            _ => self.node_mut(next).prev = i,
+        }
+    }
+
+    /// Places an unlinked entry in the first empty bucket of its probe sequence.
+    fn place(&mut self, key: K, node: Node<V>) -> usize {
+        let home = node.hash % self.table.len();
+        let (_, i) = find_in(&self.table, Probing::Linear, (home, 0), |_| false).0;
+        let i = i.expect("the load of an LruIndex stays below 1");
+
+        self.table[i] = Bucket::Some(RefCell::new((key, node)));
+        i
+    }
+
+    /// Empties bucket `i`, shifting back the following entries of the cluster
+    /// which may take its place, so that no lookup stops early.
+    fn remove_at(&mut self, i: usize) -> (K, V) {
+        self.unlink(i);
+        let (key, node) = self.table[i].take().unwrap().into_inner();
+        self.weight -= node.weight;
+        self.len -= 1;
+
+        let capacity = self.table.len();
+        let (mut hole, mut j) = (i, i);
+        loop {
+            j = (j + 1) % capacity;
+            let home = match &self.table[j] {
+                Some(pair) => pair.borrow().1.hash % capacity,
+                None => break,
+            };
+
+            // the entry may move if the hole lies between its home bucket and itself
+            let movable = if home <= j { home <= hole && hole < j } else { home <= hole || hole < j };
+            if movable {
+                self.table[hole] = self.table[j].take();
+                self.relink(hole);
+                hole = j;
+            }
+        }
+
+        (key, node.value)
+    }
+
+    /// Moves every entry to a table of `capacity` buckets, keeping their order.
+    fn resize(&mut self, capacity: usize) {
+        let mut old = mem::replace(&mut self.table, (0..capacity).map(|_| Bucket::None).collect());
+
+        let mut i = self.tail;
+        self.head = NIL;
+        self.tail = NIL;
+
+        while i != NIL {
+            let (key, node) = old[i].take().unwrap().into_inner();
+            i = node.prev;
+            let j = self.place(key, node);
+            self.push_front(j);
+        }
+    }
+
+    /// Removes and returns the entry chosen by the eviction policy, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::cache::{Budget, LruIndex};
+    ///
+    /// let mut cache = LruIndex::new(Budget::Entries(8));
+    ///
+    /// cache.insert("king", 1);
+    /// cache.insert("fool", 2);
+    ///
+    /// assert_eq!(cache.evict(), Some(("king", 1)));
+    /// assert_eq!(cache.len(), 1);
+    /// ```
+    pub fn evict(&mut self) -> Option<(K, V)> {
+        while self.tail != NIL {
+            let tail = self.tail;
+            let clock = self.policy == Eviction::Clock;
+            let node = self.node_mut(tail);
+
+            if clock && node.referenced {
+                node.referenced = false; // second chance
+                self.unlink(tail);
+                self.push_front(tail);
+            } else {
+                return Some(self.remove_at(tail));
+            }
+        }
+
+        None
+    }
+}
+
+impl<K, V, S> LruIndex<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Searches the buckets for the entry of `key`.
+    fn find<Q>(&self, key: &Q) -> Find<'_, K, Node<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let home = make_hash(&self.hasher_builder, key) as usize % self.table.len();
+        find_in(&self.table, Probing::Linear, (home, 0), |p| key.eq(p.0.borrow())).0
+    }
+
+    /// Returns the bucket holding the entry of `key`.
+    fn position<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.find(key) {
+            (Some(_), i) => i,
+            _ => None,
+        }
+    }
+
+    /// Marks the entry of bucket `i` as used, according to the eviction policy.
+    fn touch(&mut self, i: usize) {
+        match self.policy {
+            Eviction::Lru => {
+                if self.head != i {
+                    self.unlink(i);
+                    self.push_front(i);
+                }
+            }
+            Eviction::Clock => self.node_mut(i).referenced = true,
+        }
+    }
+
+    /// Inserts key-value pair as the most recent entry of the `LruIndex`,
+    /// returning the pairs leaving it: the replaced pair if the key was present,
+    /// then the entries evicted to stay within the budget. Nothing is allocated
+    /// when no pair leaves.
+    ///
+    /// A pair weighing more than the whole budget is returned without being inserted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::cache::{Budget, LruIndex};
+    ///
+    /// // query results, weighing the bytes of the query and of its result
+    /// let budget = Budget::Bytes { bytes: 16, weigh: |query: &String, result: &Vec<u32>| query.len() + 4 * result.len() };
+    /// let mut cache = LruIndex::new(budget);
+    ///
+    /// assert!(cache.insert(String::from("king"), vec![1, 4]).is_empty()); // 12 bytes
+    ///
+    /// let evicted = cache.insert(String::from("fool"), vec![7]); // 8 bytes
+    /// assert_eq!(evicted, vec![(String::from("king"), vec![1, 4])]);
+    /// assert_eq!(cache.weight(), 8);
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) -> Vec<(K, V)> {
+        let mut leaving = Vec::new();
+
+        if let Some(i) = self.position(&key) {
+            leaving.push(self.remove_at(i));
+        }
+
+        let weight = self.budget.weigh(&key, &value);
+        if weight > self.budget.limit() {
+            leaving.push((key, value));
+            return leaving;
+        }
+
+        while self.weight + weight > self.budget.limit() {
+            leaving.extend(self.evict());
+        }
+
+        if (self.len + 1) as f64 > self.table.len() as f64 * DEFAULT_MAX_LOAD {
+            self.resize(self.table.len() * 2);
+        }
+
+        let hash = make_hash(&self.hasher_builder, &key) as usize;
+        let i = self.place(key, Node { value, hash, weight, prev: NIL, next: NIL, referenced: false });
+        self.push_front(i);
+        self.weight += weight;
+        self.len += 1;
+
+        leaving
+    }
+
+    /// Returns a reference to the value associated with the specified key,
+    /// marking the entry as the most recently used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.position(key)?;
+        self.touch(i);
+        Some(&self.node_mut(i).value)
+    }
+
+    /// Returns a mutable reference to the value associated with the specified key,
+    /// marking the entry as the most recently used. The weight of the entry is not
+    /// measured again.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.position(key)?;
+        self.touch(i);
+        Some(&mut self.node_mut(i).value)
+    }
+
+    /// Returns a reference to the value associated with the specified key,
+    /// without marking the entry as used.
+    pub fn peek<Q>(&self, key: &Q) -> Option<Ref<'_, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).0.map(|pair| Ref::map(pair.borrow(), |p| &p.1.value))
+    }
+
+    /// Returns `true` if the `LruIndex` contains the specified key, without
+    /// marking the entry as used.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.position(key).is_some()
+    }
+
+    /// Removes the entry of the specified key, returning the pair if it was present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.position(key)?;
+        Some(self.remove_at(i))
+    }
+}
+
+impl<K, V, S> fmt::Debug for LruIndex<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (key, value) in self.iter() {
+            map.entry(&*key, &*value);
+        }
+        map.finish()
+    }
+}
+
+/// Iterator over the entries of an [`LruIndex`], from the most to the least recent.
+///
+/// [`LruIndex`]: struct.LruIndex.html
+pub struct LruIter<'a, K, V> {
+    table: &'a [Bucket<K, Node<V>>],
+    next: usize,
+}
+
+impl<'a, K, V> Iterator for LruIter<'a, K, V> {
+    type Item = (Ref<'a, K>, Ref<'a, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == NIL {
+            return None;
+        }
+
+        let pair = self.table[self.next].as_ref().unwrap().borrow();
+        self.next = pair.1.next;
+        Some(Ref::map_split(pair, |p| (&p.0, &p.1.value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::lear_vocabulary;
+
+    use std::string::String;
+    use std::vec;
+    use std::vec::Vec;
+
+    /// Checks the links of the recency list and that every entry can be found.
+    fn check<V>(cache: &LruIndex<String, V>) {
+        let order: Vec<String> = cache.iter().map(|(key, _)| key.clone()).collect();
+        assert_eq!(order.len(), cache.len());
+        assert!(order.iter().all(|key| cache.contains_key(key)));
+
+        let mut backward = Vec::new();
+        let mut i = cache.tail;
+        while i != NIL {
+            let pair = cache.table[i].as_ref().unwrap().borrow();
+            backward.push(pair.0.clone());
+            i = pair.1.prev;
+        }
+        backward.reverse();
+        assert_eq!(backward, order);
+    }
+
+    #[test]
+    fn agrees_with_model() {
+        let words = lear_vocabulary();
+        let mut cache = LruIndex::new(Budget::Entries(64));
+        let mut model: Vec<(String, usize)> = Vec::new(); // most recent first
+
+        for step in 0..20_000usize {
+            let word = &words[step.wrapping_mul(7919) % 200];
+
+            match step % 5 {
+                0 | 1 => {
+                    let expected = model.iter().position(|(key, _)| key == word).map(|i| model.remove(i));
+                    let expected = expected.into_iter().chain(if model.len() == 64 { model.pop() } else { None });
+                    assert_eq!(cache.insert(word.clone(), step), expected.collect::<Vec<_>>());
+                    model.insert(0, (word.clone(), step));
+                }
+                2 | 3 => {
+                    let expected = model.iter().position(|(key, _)| key == word).map(|i| model.remove(i));
+                    assert_eq!(cache.get(word), expected.as_ref().map(|(_, value)| value));
+                    model.splice(0..0, expected);
+                }
+                _ => {
+                    let expected = model.iter().position(|(key, _)| key == word).map(|i| model.remove(i));
+                    assert_eq!(cache.remove(word), expected);
+                }
+            }
+        }
+
+        check(&cache);
+        assert!(cache.iter().map(|(key, value)| (key.clone(), *value)).eq(model.into_iter()));
+        assert_eq!(cache.capacity(), 92); // never grew
+    }
+
+    #[test]
+    fn byte_budget_grows() {
+        let words = lear_vocabulary();
+        let budget = Budget::Bytes { bytes: 4096, weigh: |key: &String, _: &()| key.len() };
+        let mut cache = LruIndex::new(budget);
+
+        let mut evicted = Vec::new();
+        for word in &words {
+            evicted.extend(cache.insert(word.clone(), ()).into_iter().map(|(key, _)| key));
+            assert!(cache.weight() <= 4096);
+        }
+
+        check(&cache);
+        assert_eq!(evicted.len() + cache.len(), words.len());
+        assert_eq!(evicted[..], words[..evicted.len()]); // in insertion order
+        assert!(cache.capacity() > INITIAL_CAPACITY);
+
+        let large = "x".repeat(5000);
+        assert_eq!(cache.insert(large.clone(), ()), vec![(large, ())]);
+    }
+
+    #[test]
+    fn single_entry_budget() {
+        let mut cache = LruIndex::new(Budget::Entries(1));
+        assert_eq!(cache.evict(), None);
+
+        assert!(cache.insert(String::from("king"), 0).is_empty());
+        assert_eq!(cache.insert(String::from("fool"), 1), vec![(String::from("king"), 0)]);
+
+        // replacing the only entry evicts nothing else
+        assert_eq!(cache.insert(String::from("fool"), 2), vec![(String::from("fool"), 1)]);
+        assert_eq!(cache.get("king"), None);
+        assert_eq!(cache.remove("king"), None);
+        assert_eq!(cache.len(), 1);
+
+        check(&cache);
+        assert_eq!(cache.remove("fool"), Some((String::from("fool"), 2)));
+        assert!(cache.is_empty());
+        assert_eq!(cache.weight(), 0);
+        assert_eq!(cache.evict(), None);
+    }
+
+    #[test]
+    fn clock_second_chance() {
+        let mut cache = LruIndex::with_policy(Budget::Entries(3), Eviction::Clock);
+
+        for (i, word) in ["king", "fool", "crown"].iter().enumerate() {
+            cache.insert(String::from(*word), i);
+        }
+        cache.get("king");
+        cache.get("crown");
+
+        // "king" gets a second chance, "fool" goes
+        assert_eq!(cache.insert(String::from("storm"), 3), vec![(String::from("fool"), 1)]);
+        // "crown" was used too and moves to the front, "king" lost its flag
+        assert_eq!(cache.insert(String::from("heath"), 4), vec![(String::from("king"), 0)]);
+
+        check(&cache);
+        assert_eq!(cache.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>(), ["heath", "crown", "storm"]);
+    }
+}
@@ -0,0 +1,129 @@
+//! Module implementing the separate chaining storage
+//! of an [`Index`] hash table.
+//!
+//! The table of the `Index` is used as a slab holding the entries, and
+//! every bucket of the `heads` array starts a chain of entries linked by
+//! their positions in the slab. Chains need no allocation per node, and
+//! the table can hold more entries than there are buckets (load factors
+//! above `1.0`). The links are allocated from the storage of the table.
+//!
+//! [`Index`]: ../struct.Index.html
+
+use super::hash::mix;
+use super::raw::{RawStorage, RawTable};
+use super::Bucket;
+
+use core::cell::{Ref, RefCell};
+
+/// End of a chain.
+const NIL: usize = usize::MAX;
+
+/// Chain links of a separate chaining `Index`.
+#[derive(Debug, Clone)]
+pub(crate) struct Chains<A: RawStorage> {
+    /// First slab position of the chain of every bucket.
+    heads: RawTable<usize, A>,
+
+    /// Next slab position in the chain of every slab entry.
+    next: RawTable<usize, A>,
+
+    /// Free slab positions, reused before growing the slab.
+    free: RawTable<usize, A>,
+}
+
+impl<A: RawStorage> Chains<A> {
+    /// Creates empty chains for the specified number of buckets, allocated from `storage`.
+    pub(crate) fn new(capacity: usize, storage: &A) -> Chains<A> {
+        Chains {
+            heads: RawTable::from_elem_in(NIL, capacity, storage.clone()),
+            next: RawTable::with_capacity_in(capacity, storage.clone()), // as the slab
+            free: RawTable::with_capacity_in(0, storage.clone()),
+        }
+    }
+
+    /// Unlinks every entry, considering all `slab_len` slab positions as free.
+    pub(crate) fn reset(&mut self, slab_len: usize) {
+        self.heads.iter_mut().for_each(|head| *head = NIL);
+        self.next.iter_mut().for_each(|next| *next = NIL);
+        self.free.clear();
+        for i in (0..slab_len).rev() {
+            self.free.push(i);
+        }
+    }
+
+    /// Returns the number of buckets (of chains).
+    pub(crate) fn buckets(&self) -> usize {
+        self.heads.len()
+    }
+
+    /// Returns the bucket of `hash`. The hash is mixed before reduction:
+    /// the low bits of FNV-1 only depend on the low bits of the bytes of the key.
+    fn bucket(&self, hash: usize) -> usize {
+        mix(hash as u64) as usize % self.heads.len()
+    }
+
+    /// Returns the slab positions of the chain of the bucket of `hash`.
+    pub(crate) fn chain(&self, hash: usize) -> ChainIter<'_> {
+        self.bucket_chain(self.bucket(hash))
+    }
+
+    /// Returns the slab positions of the chain of the bucket `bucket`.
+    pub(crate) fn bucket_chain(&self, bucket: usize) -> ChainIter<'_> {
+        ChainIter {
+            next: &self.next,
+            current: self.heads[bucket],
+        }
+    }
+
+    /// Searches the chain of `hash` for an entry matching the discriminating closure,
+    /// and returns its slab position.
+    pub(crate) fn find<K, V, F>(&self, slab: &[Bucket<K, V>], hash: usize, f: F) -> Option<usize>
+    where
+        F: Fn(Ref<(K, V)>) -> bool,
+    {
+        self.chain(hash)
+            .find(|&i| matches!(&slab[i], Some(pair) if f(pair.borrow())))
+    }
+
+    /// Stores a new entry in the slab, links it at the head of the chain of `hash`
+    /// and returns its position.
+    pub(crate) fn push<K, V>(&mut self, slab: &mut RawTable<Bucket<K, V>, A>, hash: usize, pair: (K, V)) -> usize {
+        let bucket = self.bucket(hash);
+
+        let i = match self.free.pop() {
+            Some(i) => {
+                slab[i] = Some(RefCell::new(pair));
+                i
+            }
+            None => {
+                slab.push(Some(RefCell::new(pair)));
+                self.next.push(NIL);
+                slab.len() - 1
+            }
+        };
+
+        self.next[i] = self.heads[bucket];
+        self.heads[bucket] = i;
+        i
+    }
+}
+
+/// Iterator over the slab positions of a chain.
+pub(crate) struct ChainIter<'a> {
+    next: &'a [usize],
+    current: usize,
+}
+
+impl Iterator for ChainIter<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.current == NIL {
+            None
+        } else {
+            let i = self.current;
+            self.current = self.next[i];
+            Some(i)
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! Module implementing the little-endian binary encoding used
+//! by the on-disk formats of the crate (see [`frozen`]).
+//!
+//! Integers are written in little-endian order, `usize` is always
+//! widened to 64 bits, and sequences (strings, vectors, slices) are
+//! prefixed by their length as a `u64`. Borrowed and owned forms
+//! (`str` and `String`, `[T]` and `Vec<T>`) share the same encoding, so
+//! a file written with `String` keys can be queried with `&str`.
+//!
+//! [`frozen`]: ../frozen/index.html
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::io;
+
+/// Error returned when bytes cannot be decoded into a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ended before the value was complete.
+    UnexpectedEof,
+
+    /// The input contained a value that is invalid for the decoded type.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEof => write!(f, "unexpected end of input"),
+            DecodeError::Invalid(what) => write!(f, "invalid data: {}", what),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for DecodeError {}
+
+#[cfg(feature = "std")]
+impl From<DecodeError> for io::Error {
+    fn from(err: DecodeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Types that can be written in the binary format of the crate.
+///
+/// # Example
+///
+/// ```
+/// use index::codec::Encode;
+///
+/// let mut buf = Vec::new();
+/// "abc".encode(&mut buf);
+///
+/// assert_eq!(buf, [3, 0, 0, 0, 0, 0, 0, 0, b'a', b'b', b'c']);
+/// ```
+pub trait Encode {
+    /// Appends the encoded form of `self` to `buf`.
+    fn encode(&self, buf: &mut Vec<u8>);
+}
+
+/// Types that can be read back from the binary format of the crate.
+///
+/// # Example
+///
+/// ```
+/// use index::codec::{Decode, Encode};
+///
+/// let mut buf = Vec::new();
+/// vec![(1u32, String::from("one"))].encode(&mut buf);
+///
+/// let mut input = &buf[..];
+/// let decoded: Vec<(u32, String)> = Decode::decode(&mut input).unwrap();
+///
+/// assert_eq!(decoded, vec![(1, String::from("one"))]);
+/// assert!(input.is_empty());
+/// ```
+pub trait Decode: Sized {
+    /// Reads a value from the front of `input`, advancing it past the consumed bytes.
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError>;
+}
+
+/// Encodes a value into a new buffer.
+pub fn to_bytes<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    value.encode(&mut buf);
+    buf
+}
+
+/// Decodes a value that must span the whole `bytes` slice.
+pub fn from_bytes<T: Decode>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let mut input = bytes;
+    let value = T::decode(&mut input)?;
+
+    if input.is_empty() {
+        Ok(value)
+    } else {
+        Err(DecodeError::Invalid("trailing bytes"))
+    }
+}
+
+/// Splits `n` bytes off the front of `input`.
+pub(crate) fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < n {
+        return Err(DecodeError::UnexpectedEof);
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+}
+
+/// Reads a sequence length and checks it against the remaining input,
+/// so a corrupted length cannot trigger a huge allocation.
+fn decode_len(input: &mut &[u8]) -> Result<usize, DecodeError> {
+    let len = u64::decode(input)?;
+    if len > input.len() as u64 {
+        return Err(DecodeError::UnexpectedEof);
+    }
+    Ok(len as usize)
+}
+
+macro_rules! impl_int {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                    let mut bytes = [0; core::mem::size_of::<$t>()];
+                    bytes.copy_from_slice(take(input, core::mem::size_of::<$t>())?);
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl Encode for usize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode(buf);
+    }
+}
+
+impl Decode for usize {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let value = u64::decode(input)?;
+        if value > usize::MAX as u64 {
+            return Err(DecodeError::Invalid("usize overflow"));
+        }
+        Ok(value as usize)
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::Invalid("bool")),
+        }
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_str().encode(buf);
+    }
+}
+
+impl Decode for String {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::Invalid("utf-8"))
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        for item in self {
+            item.encode(buf);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_slice().encode(buf);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        let mut v = Vec::with_capacity(len);
+        for _ in 0..len {
+            v.push(T::decode(input)?);
+        }
+        Ok(v)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                buf.push(1);
+                value.encode(buf);
+            }
+            None => buf.push(0),
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(input)?)),
+            _ => Err(DecodeError::Invalid("option tag")),
+        }
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf);
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok((A::decode(input)?, B::decode(input)?))
+    }
+}
+
+impl<A: Encode, B: Encode, C: Encode> Encode for (A, B, C) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+        self.2.encode(buf);
+    }
+}
+
+impl<A: Decode, B: Decode, C: Decode> Decode for (A, B, C) {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok((A::decode(input)?, B::decode(input)?, C::decode(input)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec;
+
+    #[test]
+    fn round_trip() {
+        let value: Vec<(usize, String)> = vec![(1, "lear.txt".into()), (42, String::new())];
+        let bytes = to_bytes(&value);
+
+        assert_eq!(from_bytes::<Vec<(usize, String)>>(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn str_and_string_match() {
+        assert_eq!(to_bytes("word"), to_bytes(&String::from("word")));
+    }
+
+    #[test]
+    fn corrupted_length() {
+        let mut bytes = to_bytes(&vec![1u8, 2, 3]);
+        bytes[0] = 0xff;
+
+        assert_eq!(from_bytes::<Vec<u8>>(&bytes), Err(DecodeError::UnexpectedEof));
+    }
+}
@@ -0,0 +1,320 @@
+//! Module implementing the cuckoo hashing storage
+//! of an [`Index`] hash table.
+//!
+//! The buckets of the table are grouped by pairs, and every key can only live
+//! in one of the two pairs chosen by two hash functions derived from the hasher
+//! builder with seeds (see [`make_seeded_hash`]). A lookup therefore reads at
+//! most four buckets. An insertion into two full pairs evicts an entry to its
+//! other pair, and so on for a bounded number of evictions, after which the
+//! table is rebuilt with new seeds, or grown if rebuilding keeps failing.
+//!
+//! [`Index`]: ../struct.Index.html
+//! [`make_seeded_hash`]: ../hash/fn.make_seeded_hash.html
+
+use super::hash::*;
+use super::observer::{IndexObserver, NoObserver, Resizes};
+use super::raw::RawStorage;
+use super::{Bucket, Index, Storage};
+
+use core::borrow::Borrow;
+use core::cell::RefCell;
+use core::hash::{BuildHasher, Hash};
+
+/// Number of buckets in a pair.
+const PAIR: usize = 2;
+
+/// Maximum number of evictions of an insertion before rebuilding the table.
+const MAX_EVICTIONS: usize = 64;
+
+/// Maximum number of rebuilds with new seeds before growing the table.
+const MAX_REHASHES: usize = 4;
+
+/// Seeds of the hash functions of a cuckoo hashing `Index`.
+#[derive(Debug, Clone)]
+pub(crate) struct Cuckoo {
+    seed: u64,
+    rehashes: usize,
+}
+
+impl Cuckoo {
+    pub(crate) fn new() -> Cuckoo {
+        Cuckoo { seed: 0, rehashes: 0 }
+    }
+
+    /// Next seeds, for a rebuild after a failed insertion.
+    fn next(&self) -> Cuckoo {
+        Cuckoo {
+            seed: self.seed.wrapping_add(0x9e37_79b9_7f4a_7c15),
+            rehashes: self.rehashes + 1,
+        }
+    }
+}
+
+/// Returns the positions of the buckets of the pair `pair`.
+fn pair_buckets(pair: usize, capacity: usize) -> impl Iterator<Item = usize> {
+    (pair * PAIR..(pair + 1) * PAIR).filter(move |&i| i < capacity)
+}
+
+impl<K, V, S, O, A> Index<K, V, S, O, A>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+    O: IndexObserver,
+    A: RawStorage,
+{
+    fn cuckoo(&self) -> &Cuckoo {
+        match &self.storage {
+            Storage::Cuckoo(cuckoo) => cuckoo,
+            _ => unreachable!("not a cuckoo hashing index"),
+        }
+    }
+
+    /// Returns the pair of buckets chosen for `key` by the `n`-th hash function.
+    /// The hash is mixed before reduction: the low bits of FNV-1 only depend on
+    /// the low bits of the bytes of the key, whatever the seed.
+    fn cuckoo_pair<Q: Hash + ?Sized>(&self, key: &Q, n: u64) -> usize {
+        let pairs = self.capacity.div_ceil(PAIR);
+        let hash = make_seeded_hash(&self.params.hasher_builder, self.cuckoo().seed.wrapping_add(n), key);
+        mix(hash) as usize % pairs
+    }
+
+    /// Returns the position of the bucket holding `key`, reading at most four buckets.
+    pub(crate) fn cuckoo_find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let matching = |i: &usize| matches!(&self.table[*i], Some(pair) if key.eq(pair.borrow().0.borrow()));
+
+        pair_buckets(self.cuckoo_pair(key, 0), self.capacity)
+            .find(matching)
+            .or_else(|| pair_buckets(self.cuckoo_pair(key, 1), self.capacity).find(matching))
+    }
+
+    /// Returns the positions of the buckets of both pairs of `key`, in lookup order.
+    pub(crate) fn cuckoo_buckets<Q: Hash + ?Sized>(&self, key: &Q) -> impl Iterator<Item = usize> {
+        pair_buckets(self.cuckoo_pair(key, 0), self.capacity).chain(pair_buckets(self.cuckoo_pair(key, 1), self.capacity))
+    }
+
+    /// Average number of buckets read by a lookup for a missing key: both pairs,
+    /// the last one of which may be shorter.
+    pub(crate) fn cuckoo_miss_length(&self) -> f64 {
+        2.0 * self.capacity as f64 / self.capacity.div_ceil(PAIR) as f64
+    }
+
+    /// Returns an empty bucket of the pair `pair`.
+    fn cuckoo_empty(&self, pair: usize) -> Option<usize> {
+        pair_buckets(pair, self.capacity).find(|&i| self.table[i].is_none())
+    }
+
+    /// Inserts key-value pair in a cuckoo hashing `Index`, evicting entries
+    /// to their other pair of buckets when both pairs of the key are full.
+    /// Returns the position of the entry along with the replaced bucket.
+    pub(crate) fn cuckoo_insert(&mut self, key: K, value: V) -> (usize, Bucket<K, V>) {
+        if let Some(i) = self.cuckoo_find(&key) {
+            return (i, self.table[i].replace(RefCell::new((key, value))));
+        }
+
+        let first = self.cuckoo_pair(&key, 0);
+        let second = self.cuckoo_pair(&key, 1);
+
+        let mut homeless = RefCell::new((key, value));
+        let mut pair = first;
+
+        if pair_buckets(first, self.capacity).any(|i| self.table[i].is_some()) {
+            self.observer.on_collision();
+        }
+
+        if let Some(i) = self.cuckoo_empty(first).or_else(|| self.cuckoo_empty(second)) {
+            self.table[i] = Some(homeless);
+            self.len += 1;
+            return (i, Bucket::None);
+        }
+
+        let mut inserted = None; // position of the new entry, unless it is the homeless one
+        for eviction in 0..MAX_EVICTIONS {
+            // swapping the homeless entry with one of the pair, alternating between buckets
+            let victim = pair * PAIR + eviction % PAIR.min(self.capacity - pair * PAIR);
+            homeless = self.table[victim].replace(homeless).unwrap();
+            inserted = match inserted {
+                None => Some(victim),
+                Some(i) if i == victim => None,
+                placed => placed,
+            };
+
+            let (first, second) = {
+                let evicted = homeless.borrow();
+                (self.cuckoo_pair(&evicted.0, 0), self.cuckoo_pair(&evicted.0, 1))
+            };
+            pair = if first == pair { second } else { first };
+
+            if let Some(i) = self.cuckoo_empty(pair) {
+                self.table[i] = Some(homeless);
+                self.len += 1;
+                return (inserted.unwrap_or(i), Bucket::None);
+            }
+        }
+
+        // probable cycle: rebuilding with new seeds, or a larger capacity,
+        // the new entry taking back its bucket to be inserted last
+        if let Some(i) = inserted {
+            homeless = self.table[i].replace(homeless).unwrap();
+        }
+        let (key, value) = homeless.into_inner();
+
+        let cuckoo = self.cuckoo();
+        let i = if cuckoo.rehashes < MAX_REHASHES {
+            let next = cuckoo.next();
+            self.rebuild(self.capacity, Storage::Cuckoo(next), key, value)
+        } else {
+            let new_cap = self.grown_capacity();
+            self.rebuild(new_cap, Storage::Cuckoo(Cuckoo::new()), key, value)
+        };
+
+        (i, Bucket::None)
+    }
+
+    /// Moves every entry, and the extra key-value pair, to a new table
+    /// of the specified capacity and storage, and returns the position of the extra pair.
+    ///
+    /// The rebuilds of the new table, when refilling it fails, are reported to the
+    /// observer before this one.
+    fn rebuild(&mut self, capacity: usize, storage: Storage<A>, key: K, value: V) -> usize {
+        let (old_capacity, moved) = (self.capacity, self.len);
+
+        let mut new_index = Index::with_capacity_and_parameters_in(capacity, self.params.clone(), self.table.storage().clone());
+        new_index.storage = storage;
+        new_index.counters = self.counters.rebuilt(self.len);
+
+        let (drain, observer) = self.drain_observed();
+        let mut new_index = new_index.with_observer(Resizes(observer));
+        for (k, v) in drain {
+            new_index.insert_pair(k, v);
+        }
+        let (i, _) = new_index.insert_pair(key, value);
+
+        let new_index = new_index.with_observer(NoObserver); // releasing the observer
+        self.replace_table(new_index);
+        self.observer.on_resize(old_capacity, self.capacity, moved);
+        self.debug_validate();
+        i
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::lear_vocabulary;
+    use crate::{Backend, Parameters};
+
+    use std::cell::Cell;
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+
+    fn cuckoo_index(max_load: f64) -> Index<String, usize> {
+        let params: Parameters<IndexHasherBuilder> = Parameters { max_load, backend: Backend::Cuckoo, ..Parameters::default() };
+        Index::with_capacity_and_parameters(1, params)
+    }
+
+    #[test]
+    fn lear_vocabulary_lookups() {
+        let words = lear_vocabulary();
+
+        for &max_load in &[0.5, 0.7, 0.9] {
+            let mut index = cuckoo_index(max_load);
+            for (i, word) in words.iter().enumerate() {
+                assert!(index.insert(word.clone(), i).is_none());
+            }
+
+            assert_eq!(index.len(), words.len());
+            assert_eq!(index.iter().count(), words.len());
+            assert!(index.load() < max_load);
+
+            for (i, word) in words.iter().enumerate() {
+                assert_eq!(*index.get(word.as_str()).unwrap(), i);
+
+                // the entry lives in one of the two pairs of its key
+                let pos = index.cuckoo_find(word.as_str()).unwrap();
+                let pairs = [index.cuckoo_pair(word.as_str(), 0), index.cuckoo_pair(word.as_str(), 1)];
+                assert!(pairs.contains(&(pos / PAIR)));
+            }
+            assert!(index.get("rustacean").is_none());
+
+            assert_eq!(index.insert(words[3].clone(), 0).unwrap().into_inner().1, 3);
+            assert_eq!(index.len(), words.len());
+        }
+    }
+
+    #[test]
+    fn rebuild_with_new_seeds() {
+        let params: Parameters<IndexHasherBuilder> = Parameters { max_load: 1.0, backend: Backend::Cuckoo, ..Parameters::default() };
+        let mut index = Index::with_capacity_and_parameters(64, params);
+
+        // a completely full table is out of reach of cuckoo hashing with pairs of buckets,
+        // the insertions end up in eviction cycles triggering rebuilds, then growth
+        let mut reseeded = false;
+        for i in 0..64 {
+            index.insert(i.to_string(), i);
+            reseeded |= index.capacity() == 64 && index.cuckoo().seed != 0;
+        }
+
+        assert!(reseeded);
+        assert!(index.capacity() > 64);
+        assert_eq!(index.len(), 64);
+        for i in 0..64 {
+            assert_eq!(*index.get(i.to_string().as_str()).unwrap(), i);
+        }
+    }
+
+    #[test]
+    fn keys_sharing_low_bits() {
+        let params: Parameters<IndexHasherBuilder> = Parameters { backend: Backend::Cuckoo, ..Parameters::default() };
+
+        // every byte of the keys is 0 or 128, so their raw hashes agree on the low bits
+        let keys: Vec<u32> = (0..16u32).map(|i| (0..4).map(|b| (i >> b & 1) << (8 * b + 7)).sum()).collect();
+        let mut index = Index::with_capacity_and_parameters(256, params.clone());
+        for &key in &keys {
+            index.insert(key, key);
+        }
+        assert_eq!(index.capacity(), 256);
+        for key in &keys {
+            assert_eq!(*index.get(key).unwrap(), *key);
+        }
+
+        let mut index = Index::with_capacity_and_parameters(64, params);
+        for key in (0..16u32).map(|i| i * 32) {
+            index.insert(key, key);
+        }
+        assert_eq!(index.capacity(), 64);
+        for key in (0..16u32).map(|i| i * 32) {
+            assert_eq!(*index.get(&key).unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn collisions_in_first_pair() {
+        struct Collisions(Cell<usize>);
+
+        impl IndexObserver for Collisions {
+            fn on_collision(&self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let params: Parameters<IndexHasherBuilder> = Parameters { backend: Backend::Cuckoo, small_capacity: 0, ..Parameters::default() };
+        let collisions = Collisions(Cell::new(0));
+        let mut index = Index::with_capacity_and_parameters(1024, params).with_observer(&collisions);
+
+        // a key colliding in its first pair is reported even when that pair still has room
+        let mut expected = 0;
+        for (i, word) in lear_vocabulary().into_iter().take(500).enumerate() {
+            let first = index.cuckoo_pair(word.as_str(), 0);
+            expected += pair_buckets(first, index.capacity).any(|j| index.table[j].is_some()) as usize;
+            index.insert(word, i);
+        }
+
+        assert_eq!(index.capacity(), 1024);
+        assert!(expected > 0);
+        assert_eq!(collisions.0.get(), expected);
+    }
+}
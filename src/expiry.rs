@@ -0,0 +1,443 @@
+//! Module implementing [`ExpiringIndex`], an [`Index`] whose entries may
+//! expire after a time to live.
+//!
+//! Time is read from a [`Clock`], so that tests can drive a [`ManualClock`]
+//! instead of waiting. Expired entries are treated as missing by lookups
+//! right away, but keep their buckets until [`purge_expired`] sweeps them:
+//! the `Index` cannot empty a bucket without breaking the probe sequences
+//! going through it, so the sweep rebuilds the table from the live entries.
+//!
+//! [`ExpiringIndex`]: struct.ExpiringIndex.html
+//! [`Index`]: ../struct.Index.html
+//! [`Clock`]: trait.Clock.html
+//! [`ManualClock`]: struct.ManualClock.html
+//! [`purge_expired`]: struct.ExpiringIndex.html#method.purge_expired
+
+use super::hash::*;
+use super::iter::Iter;
+use super::{Index, Parameters};
+
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cell::{Cell, Ref, RefMut};
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::time::Duration;
+
+/// Source of the current time of an [`ExpiringIndex`], as the duration elapsed
+/// since an arbitrary origin, which must never go backward.
+///
+/// [`ExpiringIndex`]: struct.ExpiringIndex.html
+pub trait Clock {
+    /// Returns the time elapsed since the origin of the clock.
+    fn now(&self) -> Duration;
+}
+
+/// Clock standing still until it is advanced, for tests and simulations.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use index::expiry::{Clock, ManualClock};
+///
+/// let clock = ManualClock::new();
+/// clock.advance(Duration::from_secs(2));
+///
+/// assert_eq!(clock.now(), Duration::from_secs(2));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Cell<Duration>,
+}
+
+impl ManualClock {
+    /// Creates a `ManualClock` at its origin.
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+/// Monotonic clock of the system, whose origin is its creation.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    origin: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl SystemClock {
+    /// Creates a `SystemClock` starting now.
+    pub fn new() -> SystemClock {
+        SystemClock { origin: std::time::Instant::now() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// Value of an `ExpiringIndex` entry, with its deadline if it expires.
+#[derive(Clone)]
+struct Timed<V> {
+    value: V,
+    deadline: Option<Duration>,
+}
+
+impl<V> Timed<V> {
+    fn is_live(&self, now: Duration) -> bool {
+        self.deadline.map_or(true, |deadline| now < deadline)
+    }
+}
+
+/// Hash table whose entries may be given a time to live, after which they
+/// are treated as missing.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use index::expiry::{ExpiringIndex, ManualClock};
+///
+/// let mut sessions = ExpiringIndex::new(ManualClock::new());
+///
+/// sessions.insert_with_ttl("alice", 1, Duration::from_secs(60));
+/// sessions.insert("admin", 0); // never expires
+///
+/// sessions.clock().advance(Duration::from_secs(90));
+///
+/// assert!(sessions.get("alice").is_none());
+/// assert_eq!(*sessions.get("admin").unwrap(), 0);
+///
+/// assert_eq!(sessions.len(), 2); // until swept
+/// assert_eq!(sessions.purge_expired(), vec![("alice", 1)]);
+/// assert_eq!(sessions.len(), 1);
+/// ```
+#[derive(Clone)]
+pub struct ExpiringIndex<K, V, C, S = IndexHasherBuilder> {
+    index: Index<K, Timed<V>, S>,
+    clock: C,
+}
+
+impl<K, V, C> ExpiringIndex<K, V, C, IndexHasherBuilder>
+where
+    K: Hash + Eq,
+    C: Clock,
+{
+    /// Creates an empty `ExpiringIndex` reading the time from `clock`, with
+    /// the default parameters.
+    pub fn new(clock: C) -> ExpiringIndex<K, V, C, IndexHasherBuilder> {
+        ExpiringIndex { index: Index::new(), clock }
+    }
+}
+
+impl<K, V, C, S> ExpiringIndex<K, V, C, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+    C: Clock,
+{
+    /// Creates an empty `ExpiringIndex` with the specified capacity and parameters,
+    /// reading the time from `clock`.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Index::with_capacity_and_parameters`].
+    ///
+    /// [`Index::with_capacity_and_parameters`]: ../struct.Index.html#method.with_capacity_and_parameters
+    pub fn with_capacity_and_parameters(capacity: usize, params: Parameters<S>, clock: C) -> ExpiringIndex<K, V, C, S> {
+        ExpiringIndex { index: Index::with_capacity_and_parameters(capacity, params), clock }
+    }
+
+    /// Returns the entry of `key` if it is present and not expired.
+    fn live<Q>(&self, key: &Q) -> Option<Ref<'_, Timed<V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+        self.index.get(key).filter(|timed| timed.is_live(now))
+    }
+
+    /// Inserts key-value pair in the `ExpiringIndex` without deadline,
+    /// returning the replaced pair if the key was present and live.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        self.insert_timed(key, Timed { value, deadline: None })
+    }
+
+    /// Inserts key-value pair in the `ExpiringIndex`, expiring once `ttl` has
+    /// elapsed, and returns the replaced pair if the key was present and live.
+    /// A `ttl` too large for the clock never expires.
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<(K, V)> {
+        let deadline = self.clock.now().checked_add(ttl);
+        self.insert_timed(key, Timed { value, deadline })
+    }
+
+    fn insert_timed(&mut self, key: K, timed: Timed<V>) -> Option<(K, V)> {
+        let now = self.clock.now();
+        let (key, old) = self.index.insert(key, timed)?.into_inner();
+        if old.is_live(now) { Some((key, old.value)) } else { None }
+    }
+
+    /// Returns a reference to the value associated with the specified key
+    /// if it is present and not expired, else it returns `None`.
+    pub fn get<Q>(&self, key: &Q) -> Option<Ref<'_, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.live(key).map(|timed| Ref::map(timed, |timed| &timed.value))
+    }
+
+    /// Returns a mutable reference to the value associated with the specified key
+    /// if it is present and not expired, else it returns `None`.
+    pub fn get_mut<Q>(&self, key: &Q) -> Option<RefMut<'_, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let timed = self.index.get_mut(key).filter(|timed| timed.is_live(self.clock.now()))?;
+        Some(RefMut::map(timed, |timed| &mut timed.value))
+    }
+
+    /// Returns `true` if the `ExpiringIndex` contains the specified key, not expired.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.live(key).is_some()
+    }
+
+    /// Returns the time left before the entry of the specified key expires,
+    /// `None` if it is missing, expired or never expires.
+    pub fn time_to_live<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let deadline = self.live(key)?.deadline?;
+        deadline.checked_sub(self.clock.now())
+    }
+
+    /// Removes the expired entries, returning them.
+    ///
+    /// As the `Index` cannot remove entries in place, the live entries are
+    /// inserted again in the emptied table, which keeps its capacity.
+    pub fn purge_expired(&mut self) -> Vec<(K, V)> {
+        let now = self.clock.now();
+        if self.index.values().all(|timed| timed.is_live(now)) {
+            return Vec::new();
+        }
+
+        let entries: Vec<(K, Timed<V>)> = self.index.drain().collect();
+        let mut expired = Vec::new();
+        for (key, timed) in entries {
+            if timed.is_live(now) {
+                self.index.insert(key, timed);
+            } else {
+                expired.push((key, timed.value));
+            }
+        }
+
+        expired
+    }
+}
+
+impl<K, V, C, S> ExpiringIndex<K, V, C, S> {
+    /// Returns the number of entries in the `ExpiringIndex`, counting the
+    /// expired entries not purged yet.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns `true` if the `ExpiringIndex` contains no entries, expired or not.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the number of buckets of the `ExpiringIndex`.
+    pub fn capacity(&self) -> usize {
+        self.index.capacity()
+    }
+
+    /// Returns a reference to the clock of the `ExpiringIndex`.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Clears the `ExpiringIndex`, removing all entries.
+    pub fn clear(&mut self) {
+        self.index.clear();
+    }
+}
+
+impl<K, V, C: Clock, S> ExpiringIndex<K, V, C, S> {
+    /// Returns an iterator over the entries of the `ExpiringIndex` which are
+    /// not expired.
+    pub fn iter(&self) -> ExpiringIter<'_, K, V> {
+        ExpiringIter { iter: self.index.iter(), now: self.clock.now() }
+    }
+}
+
+impl<K, V, C, S> fmt::Debug for ExpiringIndex<K, V, C, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+    C: Clock,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (key, value) in self.iter() {
+            map.entry(&*key, &*value);
+        }
+        map.finish()
+    }
+}
+
+/// Iterator over the entries of an [`ExpiringIndex`] which are not expired.
+///
+/// [`ExpiringIndex`]: struct.ExpiringIndex.html
+pub struct ExpiringIter<'a, K, V> {
+    iter: Iter<'a, K, Timed<V>>,
+    now: Duration,
+}
+
+impl<'a, K, V> Iterator for ExpiringIter<'a, K, V> {
+    type Item = (Ref<'a, K>, Ref<'a, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let now = self.now;
+        let pair = self.iter.find(|pair| pair.1.is_live(now))?;
+        Some(Ref::map_split(pair, |p| (&p.0, &p.1.value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{each_backend, lear_vocabulary};
+    use crate::Probing;
+
+    use std::string::String;
+    use std::vec::Vec;
+
+    #[test]
+    fn lazy_expiry_and_purge() {
+        let words = lear_vocabulary();
+        let mut index = ExpiringIndex::new(ManualClock::new());
+
+        // the i-th word lives for i % 10 seconds, every tenth forever
+        for (i, word) in words.iter().enumerate() {
+            match i % 10 {
+                0 => index.insert(word.clone(), i),
+                ttl => index.insert_with_ttl(word.clone(), i, Duration::from_secs(ttl as u64)),
+            };
+        }
+
+        index.clock().advance(Duration::from_millis(4500));
+        let live = |i: usize| i % 10 == 0 || i % 10 > 4;
+        for (i, word) in words.iter().enumerate() {
+            assert_eq!(index.get(word).map(|value| *value), Some(i).filter(|&i| live(i)));
+        }
+        assert_eq!(index.iter().count(), words.iter().enumerate().filter(|&(i, _)| live(i)).count());
+        assert_eq!(index.time_to_live(&words[5]), Some(Duration::from_millis(500)));
+        assert_eq!(index.time_to_live(&words[10]), None);
+
+        // an expired key is absent for insertions too
+        assert_eq!(index.insert(words[1].clone(), 1), None);
+
+        let capacity = index.capacity();
+        let expired: Vec<usize> = index.purge_expired().into_iter().map(|(_, i)| i).collect();
+        assert!(expired.iter().all(|&i| !live(i) && i != 1));
+        assert_eq!(expired.len() + index.len(), words.len());
+        assert_eq!(index.capacity(), capacity);
+
+        for (i, word) in words.iter().enumerate() {
+            assert_eq!(index.get(word).is_some(), live(i) || i == 1);
+        }
+        assert!(index.purge_expired().is_empty());
+    }
+
+    #[test]
+    fn deadlines_and_refresh() {
+        let mut index = ExpiringIndex::new(ManualClock::new());
+
+        // an entry expires at its deadline, and at once with a zero ttl
+        index.insert_with_ttl("king", 0, Duration::from_secs(1));
+        index.insert_with_ttl("storm", 5, Duration::from_secs(1));
+        index.insert_with_ttl("fool", 1, Duration::from_secs(0));
+        index.insert_with_ttl("crown", 2, Duration::MAX); // never expires
+        assert!(index.get("fool").is_none());
+
+        index.clock().advance(Duration::from_millis(999));
+        assert_eq!(index.time_to_live("king"), Some(Duration::from_millis(1)));
+
+        // inserting a live key again replaces its deadline
+        assert_eq!(index.insert_with_ttl("king", 3, Duration::from_secs(1)), Some(("king", 0)));
+        index.clock().advance(Duration::from_millis(1));
+        assert!(index.get("storm").is_none());
+        assert_eq!(*index.get("king").unwrap(), 3);
+        assert_eq!(index.insert("king", 4), Some(("king", 3)));
+        assert_eq!(index.time_to_live("king"), None);
+
+        index.clock().advance(Duration::from_secs(1000));
+        assert_eq!(*index.get("king").unwrap(), 4);
+        assert_eq!(*index.get("crown").unwrap(), 2);
+        let mut expired = index.purge_expired();
+        expired.sort();
+        assert_eq!(expired, [("fool", 1), ("storm", 5)]);
+    }
+
+    #[test]
+    fn every_backend() {
+        let words: Vec<String> = lear_vocabulary().into_iter().take(500).collect();
+
+        each_backend(|params| {
+            let params = Parameters { probe: Probing::Linear, ..params };
+            let mut index = ExpiringIndex::with_capacity_and_parameters(1, params, ManualClock::new());
+
+            // the i-th word lives for 1 to 3 seconds
+            for (i, word) in words.iter().enumerate() {
+                index.insert_with_ttl(word.clone(), i, Duration::from_secs(i as u64 % 3 + 1));
+            }
+
+            for second in 1..=3 {
+                *index.get_mut(&words[2]).unwrap() += 1;
+                index.clock().advance(Duration::from_secs(1));
+
+                let purged = index.purge_expired();
+                assert!(purged.iter().all(|(word, _)| words.iter().position(|w| w == word).unwrap() % 3 + 1 == second));
+                assert_eq!(index.len(), words.iter().enumerate().filter(|&(i, _)| i % 3 + 1 > second).count());
+
+                // the live entries stay reachable through the rebuilt table
+                assert!(index.iter().all(|(word, _)| index.contains_key(&*word)));
+                assert_eq!(index.iter().count(), index.len());
+            }
+
+            assert!(index.is_empty());
+            assert!(index.get_mut(&words[2]).is_none());
+        });
+    }
+}
@@ -0,0 +1,784 @@
+//! Module implementing probabilistic membership filters: a [`BloomFilter`]
+//! and a [`CuckooFilter`], answering "maybe present" or "certainly absent"
+//! in a fraction of the memory of the keys.
+//!
+//! Both filters hash their items with the hasher builders of the [`hash`]
+//! module, seeded apart from the hash of an [`Index`], and are sized for an
+//! expected number of items and false positive rate. A [`Filter`] can also be
+//! attached to an `Index` through its [`Parameters`], so that lookups of
+//! absent keys are answered before any probing.
+//!
+//! [`BloomFilter`]: struct.BloomFilter.html
+//! [`CuckooFilter`]: struct.CuckooFilter.html
+//! [`Filter`]: enum.Filter.html
+//! [`hash`]: ../hash/index.html
+//! [`Index`]: ../struct.Index.html
+//! [`Parameters`]: ../struct.Parameters.html
+
+use super::codec::{Decode, DecodeError, Encode};
+use super::hash::*;
+use super::raw::{GlobalStorage, RawStorage, RawTable};
+
+use alloc::vec::Vec;
+use core::f64::consts::LN_2;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+#[cfg(feature = "std")]
+use std::error::Error;
+
+const FILTER_SEED: u64 = 0x5851_f42d_4c95_7f2d;
+const SECOND_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Number of fingerprints in a bucket of a `CuckooFilter`.
+const BUCKET_SIZE: usize = 4;
+
+/// Number of fingerprints a `CuckooFilter` relocates before giving up on an insertion.
+const MAX_KICKS: usize = 500;
+
+/// Hashes an item for a filter.
+pub(crate) fn filter_hash<Q: Hash + ?Sized>(hasher_builder: &impl BuildHasher, item: &Q) -> u64 {
+    make_seeded_hash(hasher_builder, FILTER_SEED, item)
+}
+
+/// Panics if the false positive rate is not in `(0, 1)`.
+fn check_rate(false_positive_rate: f64) {
+    assert!(
+        false_positive_rate > 0.0 && false_positive_rate < 1.0,
+        "invalid false positive rate: {}",
+        false_positive_rate
+    );
+}
+
+/// Membership filter attached to an [`Index`] (see [`Parameters`]).
+///
+/// The filter is sized for the capacity of the `Index` and rebuilt when it
+/// grows. A lookup first asks the filter, and only probes the buckets of the
+/// keys it may hold, so that most misses cost a single hash. Hits pay for that
+/// hash on top of the lookup. Small tables, below the `small_capacity` of the
+/// parameters, don't use the filter.
+///
+/// # Example
+///
+/// ```
+/// use index::{Index, Parameters};
+/// use index::filter::Filter;
+/// use index::hash::IndexHasherBuilder;
+///
+/// let params: Parameters<IndexHasherBuilder> = Parameters {
+///     filter: Some(Filter::Bloom { false_positive_rate: 0.01 }),
+///     ..Parameters::default()
+/// };
+/// let mut index = Index::with_capacity_and_parameters(64, params);
+///
+/// index.insert("fool", 1);
+///
+/// assert_eq!(*index.get("fool").unwrap(), 1);
+/// assert!(index.get("crown").is_none()); // most likely answered by the filter
+/// ```
+///
+/// [`Index`]: ../struct.Index.html
+/// [`Parameters`]: ../struct.Parameters.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Filter {
+    /// A [`BloomFilter`], the smallest for false positive rates above a few percents.
+    ///
+    /// [`BloomFilter`]: struct.BloomFilter.html
+    Bloom { false_positive_rate: f64 },
+
+    /// A [`CuckooFilter`], the smallest for lower false positive rates.
+    ///
+    /// [`CuckooFilter`]: struct.CuckooFilter.html
+    Cuckoo { false_positive_rate: f64 },
+}
+
+impl Filter {
+    /// Returns the false positive rate the filter is sized for.
+    pub fn false_positive_rate(&self) -> f64 {
+        match *self {
+            Filter::Bloom { false_positive_rate } | Filter::Cuckoo { false_positive_rate } => false_positive_rate,
+        }
+    }
+}
+
+/// Error returned when an item cannot be inserted in a full [`CuckooFilter`].
+///
+/// [`CuckooFilter`]: struct.CuckooFilter.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterFull;
+
+impl fmt::Display for FilterFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the cuckoo filter is full")
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for FilterFull {}
+
+/// Bloom filter: a bit array in which every item sets the bits chosen by
+/// `k` hash functions, derived from a single hash of the item.
+///
+/// # Example
+///
+/// ```
+/// use index::filter::BloomFilter;
+///
+/// let mut filter = BloomFilter::with_rate(1000, 0.01);
+///
+/// filter.insert("king");
+///
+/// assert!(filter.contains("king"));
+/// assert_eq!(filter.hashes(), 7);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BloomFilter<S = IndexHasherBuilder, A: RawStorage = GlobalStorage> {
+    hasher_builder: S,
+    hashes: u32,
+    len: usize,
+    bits: RawTable<u64, A>,
+}
+
+impl BloomFilter<IndexHasherBuilder> {
+    /// Creates an empty `BloomFilter` holding `items` items with the specified false
+    /// positive rate, with the default hasher builder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the false positive rate is not in `(0, 1)`.
+    pub fn with_rate(items: usize, false_positive_rate: f64) -> BloomFilter<IndexHasherBuilder> {
+        BloomFilter::with_rate_and_hasher(items, false_positive_rate, IndexHasherBuilder)
+    }
+}
+
+impl<S> BloomFilter<S> {
+    /// Creates an empty `BloomFilter` holding `items` items with the specified false
+    /// positive rate and hasher builder.
+    ///
+    /// The filter uses `k = ceil(log2(1 / rate))` hash functions and `k / ln(2)` bits per item.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the false positive rate is not in `(0, 1)`.
+    pub fn with_rate_and_hasher(items: usize, false_positive_rate: f64, hasher_builder: S) -> BloomFilter<S> {
+        BloomFilter::with_rate_and_hasher_in(items, false_positive_rate, hasher_builder, GlobalStorage)
+    }
+}
+
+impl<S, A: RawStorage> BloomFilter<S, A> {
+    /// Creates an empty `BloomFilter` like [`with_rate_and_hasher`], allocating
+    /// its bits from `storage` (see [`RawStorage`]).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the false positive rate is not in `(0, 1)`.
+    ///
+    /// [`with_rate_and_hasher`]: #method.with_rate_and_hasher
+    /// [`RawStorage`]: ../raw/trait.RawStorage.html
+    pub fn with_rate_and_hasher_in(items: usize, false_positive_rate: f64, hasher_builder: S, storage: A) -> BloomFilter<S, A> {
+        check_rate(false_positive_rate);
+
+        let mut hashes = 0;
+        let mut rate = 1.0;
+        while rate > false_positive_rate {
+            rate /= 2.0;
+            hashes += 1;
+        }
+
+        let bits = (items.max(1) as f64 * hashes as f64 / LN_2) as usize + 1;
+        BloomFilter { hasher_builder, hashes, len: 0, bits: RawTable::from_elem_in(0, bits.div_ceil(64), storage) }
+    }
+
+    /// Returns the number of items inserted in the `BloomFilter`.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no item was inserted in the `BloomFilter`.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bits of the `BloomFilter`.
+    pub fn bits(&self) -> usize {
+        self.bits.len() * 64
+    }
+
+    /// Returns the number of hash functions of the `BloomFilter`.
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    /// Returns a reference to the hasher builder used in the `BloomFilter`.
+    pub fn hasher(&self) -> &S {
+        &self.hasher_builder
+    }
+
+    /// Clears the `BloomFilter`.
+    pub fn clear(&mut self) {
+        for word in self.bits.iter_mut() {
+            *word = 0;
+        }
+        self.len = 0;
+    }
+
+    /// Returns the positions of the bits of an item of hash `hash`.
+    fn positions(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let (h1, h2) = (mix(hash), mix(hash ^ SECOND_SEED) | 1);
+        let bits = self.bits() as u64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+
+    pub(crate) fn insert_hash(&mut self, hash: u64) {
+        for bit in self.positions(hash) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+        self.len += 1;
+    }
+
+    pub(crate) fn contains_hash(&self, hash: u64) -> bool {
+        self.positions(hash).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+impl<S: BuildHasher, A: RawStorage> BloomFilter<S, A> {
+    /// Inserts an item in the `BloomFilter`.
+    pub fn insert<Q: Hash + ?Sized>(&mut self, item: &Q) {
+        self.insert_hash(filter_hash(&self.hasher_builder, item));
+    }
+
+    /// Returns `false` if the item was certainly not inserted in the `BloomFilter`.
+    pub fn contains<Q: Hash + ?Sized>(&self, item: &Q) -> bool {
+        self.contains_hash(filter_hash(&self.hasher_builder, item))
+    }
+}
+
+/// Cuckoo filter: buckets of `4` fingerprints, every item living in one of
+/// the two buckets derived from its hash and fingerprint, so that items can
+/// also be removed.
+///
+/// Fingerprints are at most 16 bits long, which bounds the false positive
+/// rate around `0.0001`.
+///
+/// # Example
+///
+/// ```
+/// use index::filter::CuckooFilter;
+///
+/// let mut filter = CuckooFilter::with_rate(1000, 0.001);
+///
+/// filter.insert("king").unwrap();
+/// assert!(filter.contains("king"));
+///
+/// assert!(filter.remove("king"));
+/// assert!(!filter.contains("king"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CuckooFilter<S = IndexHasherBuilder, A: RawStorage = GlobalStorage> {
+    hasher_builder: S,
+    fingerprint_bits: u32,
+    len: usize,
+    buckets: RawTable<[u16; BUCKET_SIZE], A>,
+    victim: Option<(usize, u16)>, // last relocated fingerprint, when the filter is full
+}
+
+impl CuckooFilter<IndexHasherBuilder> {
+    /// Creates an empty `CuckooFilter` holding `items` items with the specified false
+    /// positive rate, with the default hasher builder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the false positive rate is not in `(0, 1)`.
+    pub fn with_rate(items: usize, false_positive_rate: f64) -> CuckooFilter<IndexHasherBuilder> {
+        CuckooFilter::with_rate_and_hasher(items, false_positive_rate, IndexHasherBuilder)
+    }
+}
+
+impl<S> CuckooFilter<S> {
+    /// Creates an empty `CuckooFilter` holding `items` items with the specified false
+    /// positive rate and hasher builder.
+    ///
+    /// The filter uses fingerprints of `ceil(log2(8 / rate))` bits (at most 16),
+    /// in a power of two number of buckets filled up to 95%.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the false positive rate is not in `(0, 1)`.
+    pub fn with_rate_and_hasher(items: usize, false_positive_rate: f64, hasher_builder: S) -> CuckooFilter<S> {
+        CuckooFilter::with_rate_and_hasher_in(items, false_positive_rate, hasher_builder, GlobalStorage)
+    }
+}
+
+impl<S, A: RawStorage> CuckooFilter<S, A> {
+    /// Creates an empty `CuckooFilter` like [`with_rate_and_hasher`], allocating
+    /// its buckets from `storage` (see [`RawStorage`]).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the false positive rate is not in `(0, 1)`.
+    ///
+    /// [`with_rate_and_hasher`]: #method.with_rate_and_hasher
+    /// [`RawStorage`]: ../raw/trait.RawStorage.html
+    pub fn with_rate_and_hasher_in(items: usize, false_positive_rate: f64, hasher_builder: S, storage: A) -> CuckooFilter<S, A> {
+        check_rate(false_positive_rate);
+
+        let mut fingerprint_bits = 1;
+        while fingerprint_bits < 16 && (2 * BUCKET_SIZE) as f64 / (1u32 << fingerprint_bits) as f64 > false_positive_rate {
+            fingerprint_bits += 1;
+        }
+
+        let buckets = (items * 100).div_ceil(95 * BUCKET_SIZE).max(1).next_power_of_two();
+        CuckooFilter::with_buckets(buckets, fingerprint_bits, hasher_builder, storage)
+    }
+
+    fn with_buckets(buckets: usize, fingerprint_bits: u32, hasher_builder: S, storage: A) -> CuckooFilter<S, A> {
+        let buckets = RawTable::from_elem_in([0; BUCKET_SIZE], buckets, storage);
+        CuckooFilter { hasher_builder, fingerprint_bits, len: 0, buckets, victim: None }
+    }
+
+    /// Returns the number of items in the `CuckooFilter`.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the `CuckooFilter` holds no item.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of fingerprints the `CuckooFilter` can hold.
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_SIZE
+    }
+
+    /// Returns the number of bits of the fingerprints.
+    pub fn fingerprint_bits(&self) -> u32 {
+        self.fingerprint_bits
+    }
+
+    /// Returns a reference to the hasher builder used in the `CuckooFilter`.
+    pub fn hasher(&self) -> &S {
+        &self.hasher_builder
+    }
+
+    /// Clears the `CuckooFilter`.
+    pub fn clear(&mut self) {
+        for bucket in self.buckets.iter_mut() {
+            *bucket = [0; BUCKET_SIZE];
+        }
+        self.victim = None;
+        self.len = 0;
+    }
+
+    /// Returns an empty `CuckooFilter` with twice the buckets.
+    pub(crate) fn grown(&self) -> CuckooFilter<S, A>
+    where
+        S: Clone,
+    {
+        let storage = self.buckets.storage().clone();
+        CuckooFilter::with_buckets(self.buckets.len() * 2, self.fingerprint_bits, self.hasher_builder.clone(), storage)
+    }
+
+    /// Returns the fingerprint and first bucket of an item of hash `hash`.
+    fn locate(&self, hash: u64) -> (u16, usize) {
+        let hash = mix(hash);
+        let mask = (1u32 << self.fingerprint_bits) - 1;
+        let fingerprint = ((hash >> 48) as u32 & mask).max(1) as u16; // zero marks empty slots
+        (fingerprint, hash as usize & (self.buckets.len() - 1))
+    }
+
+    /// Returns the other bucket of a fingerprint living in `bucket`.
+    fn alternate(&self, bucket: usize, fingerprint: u16) -> usize {
+        (bucket ^ mix(fingerprint as u64) as usize) & (self.buckets.len() - 1)
+    }
+
+    /// Stores the fingerprint in a free slot of the bucket, if any.
+    fn put(&mut self, bucket: usize, fingerprint: u16) -> bool {
+        match self.buckets[bucket].iter_mut().find(|slot| **slot == 0) {
+            Some(slot) => {
+                *slot = fingerprint;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn insert_hash(&mut self, hash: u64) -> Result<(), FilterFull> {
+        if self.victim.is_some() {
+            return Err(FilterFull);
+        }
+
+        let (mut fingerprint, first) = self.locate(hash);
+        let second = self.alternate(first, fingerprint);
+        self.len += 1;
+
+        if self.put(first, fingerprint) || self.put(second, fingerprint) {
+            return Ok(());
+        }
+
+        // relocating fingerprints to their other bucket, until one finds a free slot
+        let mut bucket = if fingerprint & 1 == 0 { first } else { second };
+        for kick in 0..MAX_KICKS {
+            let slot = (fingerprint as usize ^ kick) % BUCKET_SIZE;
+            core::mem::swap(&mut self.buckets[bucket][slot], &mut fingerprint);
+
+            bucket = self.alternate(bucket, fingerprint);
+            if self.put(bucket, fingerprint) {
+                return Ok(());
+            }
+        }
+
+        self.victim = Some((bucket, fingerprint)); // still found by `contains`
+        Ok(())
+    }
+
+    pub(crate) fn contains_hash(&self, hash: u64) -> bool {
+        let (fingerprint, first) = self.locate(hash);
+        let second = self.alternate(first, fingerprint);
+
+        self.buckets[first].contains(&fingerprint)
+            || self.buckets[second].contains(&fingerprint)
+            || self.victim.is_some_and(|(bucket, victim)| victim == fingerprint && (bucket == first || bucket == second))
+    }
+
+    pub(crate) fn remove_hash(&mut self, hash: u64) -> bool {
+        let (fingerprint, first) = self.locate(hash);
+        let second = self.alternate(first, fingerprint);
+
+        if self.victim.is_some_and(|(bucket, victim)| victim == fingerprint && (bucket == first || bucket == second)) {
+            self.victim = None;
+            self.len -= 1;
+            return true;
+        }
+
+        for &bucket in &[first, second] {
+            if let Some(slot) = self.buckets[bucket].iter_mut().find(|slot| **slot == fingerprint) {
+                *slot = 0;
+                self.len -= 1;
+
+                // the freed slot may take the victim back
+                if let Some((bucket, victim)) = self.victim {
+                    let other = self.alternate(bucket, victim);
+                    if self.put(bucket, victim) || self.put(other, victim) {
+                        self.victim = None;
+                    }
+                }
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+impl<S: BuildHasher, A: RawStorage> CuckooFilter<S, A> {
+    /// Inserts an item in the `CuckooFilter`, or returns an error if the filter is full.
+    ///
+    /// An item inserted twice is stored twice, and must be removed twice.
+    pub fn insert<Q: Hash + ?Sized>(&mut self, item: &Q) -> Result<(), FilterFull> {
+        self.insert_hash(filter_hash(&self.hasher_builder, item))
+    }
+
+    /// Returns `false` if the item is certainly not in the `CuckooFilter`.
+    pub fn contains<Q: Hash + ?Sized>(&self, item: &Q) -> bool {
+        self.contains_hash(filter_hash(&self.hasher_builder, item))
+    }
+
+    /// Removes an item from the `CuckooFilter`, returning `false` if it was not found.
+    ///
+    /// Only items which were inserted may be removed: removing another item
+    /// sharing their fingerprint would remove them instead.
+    pub fn remove<Q: Hash + ?Sized>(&mut self, item: &Q) -> bool {
+        self.remove_hash(filter_hash(&self.hasher_builder, item))
+    }
+}
+
+/// Membership filter of an `Index`, which hashes the keys itself.
+#[derive(Debug, Clone)]
+pub(crate) enum Membership<A: RawStorage> {
+    Bloom(BloomFilter<(), A>),
+    Cuckoo(CuckooFilter<(), A>),
+}
+
+impl<A: RawStorage> Membership<A> {
+    /// Creates an empty filter for `items` keys, allocated from the storage of the table.
+    pub(crate) fn new(filter: Filter, items: usize, storage: &A) -> Membership<A> {
+        match filter {
+            Filter::Bloom { false_positive_rate } => {
+                Membership::Bloom(BloomFilter::with_rate_and_hasher_in(items, false_positive_rate, (), storage.clone()))
+            }
+            Filter::Cuckoo { false_positive_rate } => {
+                Membership::Cuckoo(CuckooFilter::with_rate_and_hasher_in(items, false_positive_rate, (), storage.clone()))
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        match self {
+            Membership::Bloom(bloom) => bloom.clear(),
+            Membership::Cuckoo(cuckoo) => cuckoo.clear(),
+        }
+    }
+
+    /// Returns an empty filter with room for more keys.
+    pub(crate) fn grown(&self) -> Membership<A> {
+        match self {
+            Membership::Bloom(bloom) => {
+                let bits = RawTable::from_elem_in(0, bloom.bits.len() * 2, bloom.bits.storage().clone());
+                Membership::Bloom(BloomFilter { hasher_builder: (), hashes: bloom.hashes, len: 0, bits })
+            }
+            Membership::Cuckoo(cuckoo) => Membership::Cuckoo(cuckoo.grown()),
+        }
+    }
+
+    pub(crate) fn insert_hash(&mut self, hash: u64) -> Result<(), FilterFull> {
+        match self {
+            Membership::Bloom(bloom) => {
+                bloom.insert_hash(hash);
+                Ok(())
+            }
+            Membership::Cuckoo(cuckoo) => cuckoo.insert_hash(hash),
+        }
+    }
+
+    pub(crate) fn contains_hash(&self, hash: u64) -> bool {
+        match self {
+            Membership::Bloom(bloom) => bloom.contains_hash(hash),
+            Membership::Cuckoo(cuckoo) => cuckoo.contains_hash(hash),
+        }
+    }
+}
+
+/// Writes the number of hash functions, the number of items and the bit array.
+impl<S, A: RawStorage> Encode for BloomFilter<S, A> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.hashes.encode(buf);
+        self.len.encode(buf);
+        self.bits.encode(buf);
+    }
+}
+
+/// Reads a `BloomFilter` back with the default hasher builder of `S`.
+impl<S: Default> Decode for BloomFilter<S> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let hashes = u32::decode(input)?;
+        let len = usize::decode(input)?;
+        let bits: Vec<u64> = Decode::decode(input)?;
+
+        if hashes == 0 || bits.is_empty() {
+            return Err(DecodeError::Invalid("bloom filter size"));
+        }
+        let mut filter = BloomFilter { hasher_builder: S::default(), hashes, len, bits: RawTable::from_elem_in(0, bits.len(), GlobalStorage) };
+        filter.bits.copy_from_slice(&bits);
+        Ok(filter)
+    }
+}
+
+/// Writes the fingerprint size, the number of items, the fingerprints by
+/// bucket and the victim.
+///
+/// # Example
+///
+/// ```
+/// use index::codec;
+/// use index::filter::CuckooFilter;
+///
+/// let mut filter = CuckooFilter::with_rate(100, 0.01);
+/// filter.insert("fool").unwrap();
+///
+/// let decoded: CuckooFilter = codec::from_bytes(&codec::to_bytes(&filter)).unwrap();
+///
+/// assert!(decoded.contains("fool"));
+/// ```
+impl<S, A: RawStorage> Encode for CuckooFilter<S, A> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.fingerprint_bits.encode(buf);
+        self.len.encode(buf);
+        self.buckets.iter().flatten().copied().collect::<Vec<u16>>().encode(buf);
+        self.victim.encode(buf);
+    }
+}
+
+/// Reads a `CuckooFilter` back with the default hasher builder of `S`.
+impl<S: Default> Decode for CuckooFilter<S> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let fingerprint_bits = u32::decode(input)?;
+        let len = usize::decode(input)?;
+        let fingerprints: Vec<u16> = Decode::decode(input)?;
+        let victim: Option<(usize, u16)> = Decode::decode(input)?;
+
+        if fingerprint_bits == 0 || fingerprint_bits > 16 {
+            return Err(DecodeError::Invalid("cuckoo filter fingerprint size"));
+        }
+        let buckets = fingerprints.len() / BUCKET_SIZE;
+        if fingerprints.len() % BUCKET_SIZE != 0 || !buckets.is_power_of_two() {
+            return Err(DecodeError::Invalid("cuckoo filter buckets"));
+        }
+        let mask = (1u32 << fingerprint_bits) - 1;
+        let valid = |fingerprint: u16| fingerprint as u32 <= mask;
+        if !fingerprints.iter().all(|&fingerprint| valid(fingerprint))
+            || victim.is_some_and(|(bucket, fingerprint)| bucket >= buckets || fingerprint == 0 || !valid(fingerprint))
+        {
+            return Err(DecodeError::Invalid("cuckoo filter fingerprint"));
+        }
+
+        let mut filter = CuckooFilter::with_buckets(buckets, fingerprint_bits, S::default(), GlobalStorage);
+        for (bucket, chunk) in filter.buckets.iter_mut().zip(fingerprints.chunks(BUCKET_SIZE)) {
+            bucket.copy_from_slice(chunk);
+        }
+        filter.len = len;
+        filter.victim = victim;
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{from_bytes, to_bytes};
+    use crate::observer::IndexObserver;
+    use crate::tests::{each_backend, lear_vocabulary};
+    use crate::{Backend, Index, Parameters};
+
+    use std::cell::Cell;
+    use std::format;
+    use std::string::String;
+    use std::vec::Vec;
+
+    /// Words absent from the vocabulary: its uppercased words and its words
+    /// prefixed or suffixed by punctuation.
+    fn absent(words: &[String]) -> Vec<String> {
+        let uppercased = words.iter().map(|word| word.to_uppercase()).filter(|upper| upper != &upper.to_lowercase());
+        let punctuated = words.iter().flat_map(|word| [format!("{}?", word), format!("'{}", word)]);
+        uppercased.chain(punctuated).collect()
+    }
+
+    /// Share of the absent words accepted by `contains`.
+    fn false_positives(words: &[String], contains: impl Fn(&str) -> bool) -> f64 {
+        let absent = absent(words);
+        absent.iter().filter(|word| contains(word)).count() as f64 / absent.len() as f64
+    }
+
+    /// Observer counting the buckets visited by the lookups.
+    #[derive(Default)]
+    struct Probes(Cell<usize>);
+
+    impl IndexObserver for Probes {
+        fn on_probe(&self, len: usize) {
+            self.0.set(self.0.get() + len);
+        }
+    }
+
+    #[test]
+    fn bloom_rate() {
+        let words = lear_vocabulary();
+
+        for &rate in &[0.1, 0.01, 0.001] {
+            let mut filter = BloomFilter::with_rate(words.len(), rate);
+            for word in &words {
+                filter.insert(word.as_str());
+            }
+
+            assert!(words.iter().all(|word| filter.contains(word.as_str())));
+            assert!(false_positives(&words, |word| filter.contains(word)) < rate * 2.0);
+
+            let decoded: BloomFilter = from_bytes(&to_bytes(&filter)).unwrap();
+            assert!(words.iter().all(|word| decoded.contains(word.as_str())));
+        }
+    }
+
+    #[test]
+    fn cuckoo_rate_and_removal() {
+        let words = lear_vocabulary();
+
+        for &rate in &[0.1, 0.01, 0.001] {
+            let mut filter = CuckooFilter::with_rate(words.len(), rate);
+            for word in &words {
+                filter.insert(word.as_str()).unwrap();
+            }
+
+            assert_eq!(filter.len(), words.len());
+            assert!(words.iter().all(|word| filter.contains(word.as_str())));
+            assert!(false_positives(&words, |word| filter.contains(word)) < rate * 2.0);
+
+            let decoded: CuckooFilter = from_bytes(&to_bytes(&filter)).unwrap();
+            assert!(words.iter().all(|word| decoded.contains(word.as_str())));
+
+            for word in words.iter().step_by(2) {
+                assert!(filter.remove(word.as_str()));
+            }
+            assert!(words.iter().skip(1).step_by(2).all(|word| filter.contains(word.as_str())));
+        }
+    }
+
+    #[test]
+    fn cuckoo_full() {
+        let mut filter = CuckooFilter::with_rate(8, 0.01);
+        let capacity = filter.capacity();
+
+        let inserted = (0..capacity * 2).take_while(|i| filter.insert(i).is_ok()).count();
+
+        // the filter keeps every fingerprint inserted before reporting it is full
+        assert!(inserted <= capacity + 1);
+        assert!((0..inserted).all(|i| filter.contains(&i)));
+        assert_eq!(filter.insert(&inserted), Err(FilterFull));
+    }
+
+    #[test]
+    fn duplicates_and_clear() {
+        let mut bloom = BloomFilter::with_rate(0, 0.01);
+        assert!(!bloom.contains("lear"));
+        bloom.insert("lear");
+        assert!(bloom.contains("lear"));
+        bloom.clear();
+        assert!(bloom.is_empty() && !bloom.contains("lear"));
+
+        let mut cuckoo = CuckooFilter::with_rate(0, 0.01);
+        assert!(!cuckoo.remove("lear"));
+        cuckoo.insert("lear").unwrap();
+        cuckoo.insert("lear").unwrap();
+
+        // an item inserted twice is removed twice
+        assert!(cuckoo.remove("lear") && cuckoo.contains("lear"));
+        assert!(cuckoo.remove("lear") && !cuckoo.contains("lear"));
+        assert!(!cuckoo.remove("lear"));
+        assert!(cuckoo.is_empty());
+
+        cuckoo.insert("fool").unwrap();
+        cuckoo.clear();
+        assert!(cuckoo.is_empty() && !cuckoo.contains("fool"));
+    }
+
+    #[test]
+    fn attached_to_index() {
+        let words = lear_vocabulary();
+        let absent = absent(&words);
+
+        for &filter in &[Filter::Bloom { false_positive_rate: 0.01 }, Filter::Cuckoo { false_positive_rate: 0.01 }] {
+            each_backend(|params| {
+                let backend = params.backend;
+                let params = Parameters { filter: Some(filter), ..params };
+                let mut index = Index::with_capacity_and_parameters(1, params).with_observer(Probes::default());
+
+                for (i, word) in words.iter().enumerate() {
+                    index.insert(word.clone(), i);
+                }
+                assert!(index.validate_invariants().is_ok());
+                assert!(words.iter().enumerate().all(|(i, word)| *index.get(word).unwrap() == i));
+
+                index.observer().0.set(0);
+                assert!(absent.iter().all(|word| index.get(word).is_none()));
+                if backend == Backend::OpenAddressing {
+                    // only the false positives probe the buckets
+                    assert!(index.observer().0.get() < absent.len() / 20);
+                }
+
+                index.clear();
+                assert!(words.iter().all(|word| index.get(word).is_none()));
+                index.insert(words[0].clone(), 0);
+                assert_eq!(*index.get(&words[0]).unwrap(), 0);
+            });
+        }
+    }
+}
@@ -0,0 +1,533 @@
+//! Module implementing a frozen, read-only on-disk layout of an
+//! [`Index`] hash table, and the [`FileIndex`] reader that queries
+//! it without loading the file in memory, through positioned reads.
+//!
+//! # Layout
+//!
+//! All integers are little-endian.
+//!
+//! ```text
+//! header   magic "INDXFRZ2" | capacity: u64 | len: u64 | probing: u64 | hasher: u64
+//! slots    capacity * [hash: u64 | offset: u64 | key_len: u32 | value_len: u32]
+//! heap     encoded keys and values, each value following its key
+//! ```
+//!
+//! The slot array mirrors the bucket array of the frozen `Index`, so the
+//! reader finds a key by walking the same probe sequence over the slots.
+//! Tables using another [`Backend`] are laid out in open addressing buckets
+//! with their probing parameter when frozen, which fails with `InvalidInput`
+//! if that probing cannot place the entries.
+//! Empty slots have their offset set to `u64::MAX`. Keys and values are
+//! written with the [`codec`] encoding.
+//!
+//! The `probing` and `hasher` fields identify the probing strategy and the
+//! hasher builder of the frozen table (the probe sequence and the hash of
+//! fixed inputs), so that opening the file with others fails instead of
+//! missing every key.
+//!
+//! [`Index`]: ../struct.Index.html
+//! [`FileIndex`]: struct.FileIndex.html
+//! [`codec`]: ../codec/index.html
+//! [`Backend`]: ../enum.Backend.html
+
+use super::codec::{self, Decode, Encode};
+use super::hash::*;
+use super::raw::RawStorage;
+use super::{second_hash, Index, Probing, Storage, DEFAULT_MAX_LOAD, DEFAULT_PROBING};
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cell::RefCell;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"INDXFRZ2";
+const HEADER_SIZE: u64 = 40;
+const SLOT_SIZE: u64 = 24;
+const EMPTY: u64 = u64::MAX;
+
+/// Number of times the open addressing layout of another backend doubles its
+/// capacity before giving up on a probing which cannot place the entries.
+const MAX_LAYOUT_DOUBLINGS: u32 = 8;
+
+/// Fixed-width slot of the frozen bucket array.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    hash: u64,
+    offset: u64,
+    key_len: u32,
+    value_len: u32,
+}
+
+impl Slot {
+    const EMPTY: Slot = Slot { hash: 0, offset: EMPTY, key_len: 0, value_len: 0 };
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        self.hash.encode(buf);
+        self.offset.encode(buf);
+        self.key_len.encode(buf);
+        self.value_len.encode(buf);
+    }
+
+    fn read(mut bytes: &[u8]) -> Result<Slot, codec::DecodeError> {
+        Ok(Slot {
+            hash: u64::decode(&mut bytes)?,
+            offset: u64::decode(&mut bytes)?,
+            key_len: u32::decode(&mut bytes)?,
+            value_len: u32::decode(&mut bytes)?,
+        })
+    }
+}
+
+/// Bucket of the open addressing layout of a frozen table: an entry and the hash of its key.
+type LaidOut<'a, K, V> = Option<(&'a RefCell<(K, V)>, u64)>;
+
+/// Key hashed to identify the hasher builder of a frozen table.
+const FINGERPRINT_KEY: &str = "index frozen layout";
+
+/// Identifies a probing strategy by its variant and the start of its probe
+/// sequence, which also tells `Custom` functions apart.
+fn probing_tag(probe: Probing) -> u64 {
+    let variant = match probe {
+        Probing::Linear => 0,
+        Probing::Quadratic => 1,
+        Probing::Triangular => 2,
+        Probing::DoubleHashing => 3,
+        Probing::Custom(_) => 4,
+        Probing::CustomDouble(_) => 5,
+    };
+    (0..8).fold(variant, |tag, i| mix(tag ^ probe.probe(0x1234_5678, 0x9abc_def1, i) as u64))
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<K, V, S, O, A> Index<K, V, S, O, A>
+where
+    K: Hash + Eq + Encode,
+    V: Encode,
+    S: BuildHasher + Clone,
+    A: RawStorage,
+{
+    /// Writes the `Index` to `path` in the frozen on-disk layout,
+    /// to be queried later through a [`FileIndex`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    /// use index::frozen::FileIndex;
+    ///
+    /// let mut index = Index::new();
+    ///
+    /// index.insert(String::from("fool"), vec![1usize, 4, 12]);
+    /// index.insert(String::from("crown"), vec![7]);
+    ///
+    /// let path = std::env::temp_dir().join("index-freeze-doc.idx");
+    /// index.freeze(&path).unwrap();
+    ///
+    /// let file_index: FileIndex<String, Vec<usize>> = FileIndex::open(&path).unwrap();
+    ///
+    /// assert_eq!(file_index.get("crown").unwrap(), Some(vec![7]));
+    /// assert_eq!(file_index.get("king").unwrap(), None);
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    ///
+    /// [`FileIndex`]: frozen/struct.FileIndex.html
+    pub fn freeze<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.freeze_to(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Writes the `Index` in the frozen on-disk layout to any writer.
+    pub fn freeze_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let buckets = self.open_layout()?;
+        let capacity = buckets.len();
+
+        let heap_start = HEADER_SIZE + SLOT_SIZE * capacity as u64;
+        let mut slots = Vec::with_capacity(SLOT_SIZE as usize * capacity);
+        let mut heap = Vec::new();
+
+        for bucket in buckets {
+            let slot = match bucket {
+                Some((pair, hash)) => {
+                    let pair = pair.borrow();
+                    let offset = heap_start + heap.len() as u64;
+
+                    pair.0.encode(&mut heap);
+                    let key_len = heap.len() as u64 + heap_start - offset;
+                    pair.1.encode(&mut heap);
+                    let value_len = heap.len() as u64 + heap_start - offset - key_len;
+
+                    if key_len > u32::MAX as u64 || value_len > u32::MAX as u64 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput, "entry too large to freeze"));
+                    }
+
+                    Slot {
+                        hash,
+                        offset,
+                        key_len: key_len as u32,
+                        value_len: value_len as u32,
+                    }
+                }
+                None => Slot::EMPTY,
+            };
+            slot.write(&mut slots);
+        }
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(MAGIC);
+        (capacity as u64).encode(&mut header);
+        (self.len as u64).encode(&mut header);
+        probing_tag(self.params.probe).encode(&mut header);
+        make_hash(&self.params.hasher_builder, FINGERPRINT_KEY).encode(&mut header);
+
+        writer.write_all(&header)?;
+        writer.write_all(&slots)?;
+        writer.write_all(&heap)
+    }
+
+    /// Returns the open addressing buckets of the `Index` along with the hashes of
+    /// their keys: its own table, or for other backends a table rebuilt by probing
+    /// with the parameters of the `Index`.
+    ///
+    /// Fails with `InvalidInput` if the probing cannot place the entries, even in a
+    /// table `2^MAX_LAYOUT_DOUBLINGS` times larger than needed.
+    fn open_layout(&self) -> io::Result<Vec<LaidOut<'_, K, V>>> {
+        let hash_of = |pair: &RefCell<(K, V)>| make_hash(&self.params.hasher_builder, &pair.borrow().0);
+
+        if let Storage::Open = self.storage {
+            return Ok(self.table.iter().map(|bucket| bucket.as_ref().map(|pair| (pair, hash_of(pair)))).collect());
+        }
+
+        let probe = self.params.probe;
+        let hashes: Vec<(u64, usize)> = self
+            .table
+            .iter()
+            .flatten()
+            .map(|pair| (hash_of(pair), second_hash(&self.params.hasher_builder, probe, &pair.borrow().0)))
+            .collect();
+        let mut capacity = ((self.len as f64 / DEFAULT_MAX_LOAD) as usize + 1).next_power_of_two();
+
+        'layout: for _ in 0..=MAX_LAYOUT_DOUBLINGS {
+            let mut buckets = vec![None; capacity];
+
+            for (pair, &(hash, hash2)) in self.table.iter().flatten().zip(&hashes) {
+                let empty = (0..capacity)
+                    .map(|i| probe.probe(hash as usize, hash2, i) % capacity)
+                    .find(|&i| buckets[i].is_none());
+
+                match empty {
+                    Some(i) => buckets[i] = Some((pair, hash)),
+                    None => { // the probing does not reach every bucket, retrying larger
+                        capacity *= 2;
+                        continue 'layout;
+                    }
+                }
+            }
+
+            return Ok(buckets);
+        }
+
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "probing cannot lay out the entries to freeze"))
+    }
+}
+
+/// Read-only [`Index`] stored in a file written by [`Index::freeze`].
+///
+/// The file is not memory-mapped: lookups only use positioned reads of the
+/// slots and entries they need (`pread` on unix, seek and read elsewhere),
+/// so opening the file costs nothing regardless of its size. The hasher builder and probing
+/// function must be the ones used by the frozen `Index`, opening the file
+/// fails with `InvalidData` otherwise.
+///
+/// [`Index`]: ../struct.Index.html
+/// [`Index::freeze`]: ../struct.Index.html#method.freeze
+#[derive(Debug)]
+pub struct FileIndex<K, V, S = IndexHasherBuilder> {
+    file: File,
+    file_len: u64,
+    capacity: usize,
+    len: usize,
+    hasher_builder: S,
+    probe: Probing,
+    _marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> FileIndex<K, V, IndexHasherBuilder> {
+    /// Opens a frozen `Index` built with the default hasher builder and probing.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FileIndex<K, V, IndexHasherBuilder>> {
+        Self::open_with(path, IndexHasherBuilder, DEFAULT_PROBING)
+    }
+}
+
+impl<K, V, S> FileIndex<K, V, S> {
+    /// Opens a frozen `Index` built with the specified hasher builder and probing function.
+    pub fn open_with<P: AsRef<Path>>(
+        path: P,
+        hasher_builder: S,
+        probe: Probing,
+    ) -> io::Result<FileIndex<K, V, S>>
+    where
+        S: BuildHasher,
+    {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+
+        let mut header = [0; HEADER_SIZE as usize];
+        read_at(&file, &mut header, 0).map_err(|_| invalid("truncated frozen index header"))?;
+
+        let mut input = &header[..];
+        if codec::take(&mut input, MAGIC.len())? != MAGIC {
+            return Err(invalid("not a frozen index file"));
+        }
+        let capacity = usize::decode(&mut input)?;
+        let len = usize::decode(&mut input)?;
+        if u64::decode(&mut input)? != probing_tag(probe) {
+            return Err(invalid("frozen index written with another probing strategy"));
+        }
+        if u64::decode(&mut input)? != make_hash(&hasher_builder, FINGERPRINT_KEY) {
+            return Err(invalid("frozen index written with another hasher"));
+        }
+
+        let slots_end = (capacity as u64)
+            .checked_mul(SLOT_SIZE)
+            .and_then(|s| s.checked_add(HEADER_SIZE));
+        if capacity == 0 || len > capacity || slots_end.map_or(true, |end| end > file_len) {
+            return Err(invalid("corrupted frozen index header"));
+        }
+
+        Ok(FileIndex {
+            file,
+            file_len,
+            capacity,
+            len,
+            hasher_builder,
+            probe,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns the number of slots of the frozen table.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries of the frozen table.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the frozen table contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn slot(&self, i: usize) -> io::Result<Slot> {
+        let mut bytes = [0; SLOT_SIZE as usize];
+        read_at(&self.file, &mut bytes, HEADER_SIZE + SLOT_SIZE * i as u64)?;
+        Ok(Slot::read(&bytes)?)
+    }
+
+    fn entry(&self, slot: &Slot) -> io::Result<Vec<u8>> {
+        let size = slot.key_len as u64 + slot.value_len as u64;
+        if slot.offset.checked_add(size).map_or(true, |end| end > self.file_len) {
+            return Err(invalid("frozen index slot out of bounds"));
+        }
+
+        let mut bytes = vec![0; size as usize];
+        read_at(&self.file, &mut bytes, slot.offset)?;
+        Ok(bytes)
+    }
+}
+
+impl<K, V, S> FileIndex<K, V, S>
+where
+    S: BuildHasher,
+{
+    /// Walks the probe sequence of `key` and returns the raw entry bytes
+    /// of the matching slot along with its key length.
+    fn find<Q>(&self, key: &Q) -> io::Result<Option<(Vec<u8>, usize)>>
+    where
+        Q: Hash + Encode + ?Sized,
+    {
+        let hash = make_hash(&self.hasher_builder, key);
+        let hash2 = second_hash(&self.hasher_builder, self.probe, key);
+        let encoded = codec::to_bytes(key);
+
+        for i in 0..self.capacity {
+            let probe = self.probe.probe(hash as usize, hash2, i) % self.capacity;
+            let slot = self.slot(probe)?;
+
+            if slot.offset == EMPTY {
+                return Ok(None);
+            }
+            if slot.hash == hash && slot.key_len as usize == encoded.len() {
+                let bytes = self.entry(&slot)?;
+                if bytes[..encoded.len()] == encoded[..] {
+                    return Ok(Some((bytes, encoded.len())));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the value associated with the specified key if it is present in the file.
+    ///
+    /// The key is compared through its encoded form, which is the same for borrowed
+    /// and owned types (`&str` for `String` keys for instance).
+    pub fn get<Q>(&self, key: &Q) -> io::Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Encode + ?Sized,
+        V: Decode,
+    {
+        match self.find(key)? {
+            Some((bytes, key_len)) => Ok(Some(codec::from_bytes(&bytes[key_len..])?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns `true` if the file contains an entry for the specified key.
+    pub fn contains_key<Q>(&self, key: &Q) -> io::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Encode + ?Sized,
+    {
+        Ok(self.find(key)?.is_some())
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buf, offset)
+}
+
+#[cfg(not(unix))]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut file = file;
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Backend, Parameters};
+
+    use std::format;
+    use std::fs::File;
+    use std::io::{BufRead, BufReader};
+    use std::string::{String, ToString};
+
+    #[test]
+    fn freeze_lear() {
+        let mut index: Index<String, Vec<usize>> = Index::new();
+
+        let reader = BufReader::new(File::open("lear.txt").unwrap());
+        for (i, line) in reader.lines().enumerate() {
+            for word in line.unwrap().split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+                let word = word.to_lowercase();
+                let res = index.get_mut(&word);
+                match res {
+                    Some(mut v) => v.push(i + 1),
+                    None => {
+                        drop(res);
+                        index.insert(word, vec![i + 1]);
+                    }
+                }
+            }
+        }
+
+        let path = std::env::temp_dir().join(format!("index-freeze-lear-{}.idx", std::process::id()));
+        index.freeze(&path).unwrap();
+
+        let file_index: FileIndex<String, Vec<usize>> = FileIndex::open(&path).unwrap();
+
+        assert_eq!(file_index.len(), index.len());
+        assert_eq!(file_index.capacity(), index.capacity());
+        for pair in index.iter() {
+            assert_eq!(file_index.get(pair.0.as_str()).unwrap().as_ref(), Some(&pair.1));
+        }
+        assert!(!file_index.contains_key("rustacean").unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn freeze_chained() {
+        let params: Parameters<IndexHasherBuilder> = Parameters { max_load: 3.0, backend: Backend::SeparateChaining, ..Parameters::default() };
+        let mut index = Index::with_capacity_and_parameters(8, params);
+        for i in 0..100u32 {
+            index.insert(i.to_string(), i);
+        }
+        assert_eq!(index.capacity(), 64);
+
+        let path = std::env::temp_dir().join(format!("index-freeze-chained-{}.idx", std::process::id()));
+        index.freeze(&path).unwrap();
+
+        let file_index: FileIndex<String, u32> = FileIndex::open(&path).unwrap();
+
+        assert_eq!(file_index.len(), 100);
+        for i in 0..100u32 {
+            assert_eq!(file_index.get(i.to_string().as_str()).unwrap(), Some(i));
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reject_stuck_probing() {
+        // chaining ignores the probing, which can't place two keys when freezing
+        let params: Parameters<IndexHasherBuilder> =
+            Parameters { backend: Backend::SeparateChaining, probe: Probing::Custom(|_, _| 0), ..Parameters::default() };
+        let mut index = Index::with_capacity_and_parameters(16, params);
+        index.insert(String::from("fool"), 1u32);
+        index.insert(String::from("crown"), 2u32);
+
+        let mut frozen = Vec::new();
+        assert_eq!(index.freeze_to(&mut frozen).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn reject_garbage() {
+        let path = std::env::temp_dir().join(format!("index-freeze-garbage-{}.idx", std::process::id()));
+        std::fs::write(&path, b"definitely not an index").unwrap();
+
+        let res: io::Result<FileIndex<String, u32>> = FileIndex::open(&path);
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reject_other_probing_and_hasher() {
+        let mut index = Index::new();
+        index.insert(String::from("fool"), 1u32);
+
+        let path = std::env::temp_dir().join(format!("index-freeze-mismatch-{}.idx", std::process::id()));
+        index.freeze(&path).unwrap();
+
+        let other_probing: io::Result<FileIndex<String, u32>> = FileIndex::open_with(&path, IndexHasherBuilder, Probing::Linear);
+        assert_eq!(other_probing.unwrap_err().to_string(), "frozen index written with another probing strategy");
+
+        let custom: io::Result<FileIndex<String, u32>> = FileIndex::open_with(&path, IndexHasherBuilder, Probing::Custom(|hash, i| hash + i + i * i));
+        assert_eq!(custom.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let other_hasher: io::Result<FileIndex<String, u32, _>> =
+            FileIndex::open_with(&path, std::collections::hash_map::RandomState::new(), DEFAULT_PROBING);
+        assert_eq!(other_hasher.unwrap_err().to_string(), "frozen index written with another hasher");
+
+        assert!(FileIndex::<String, u32>::open(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+//! Module implementing the harnesses of the fuzz targets of the crate,
+//! available with the `fuzzing` feature.
+//!
+//! Every harness takes arbitrary bytes and panics on a misbehavior. The
+//! `fuzz/` directory wraps them in `cargo fuzz` targets, and the tests of this
+//! module replay the inputs checked in under `fuzz/corpus/<target>/`:
+//!
+//! - [`operations`]: `Parameters` followed by a sequence of operations,
+//!   applied to an `Index` and checked against `std::collections::HashMap`,
+//! - [`decode`]: values of the [`codec`] format, re-encoded when valid,
+//! - [`frozen`]: a frozen index file opened and queried with a [`FileIndex`].
+//!
+//! [`operations`]: fn.operations.html
+//! [`decode`]: fn.decode.html
+//! [`frozen`]: fn.frozen.html
+//! [`codec`]: ../codec/index.html
+//! [`FileIndex`]: ../frozen/struct.FileIndex.html
+
+use super::codec::{self, Decode, Encode};
+use super::filter::Filter;
+use super::frozen::FileIndex;
+use super::hash::IndexHasherBuilder;
+use super::{Backend, Index, Parameters, Probing, MAX_SMALL_CAPACITY};
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::format;
+use std::string::String;
+use std::vec::Vec;
+
+/// Reader of the fuzzer input, yielding zeros once exhausted so that every input is valid.
+struct Input<'a>(&'a [u8]);
+
+impl Input<'_> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn u8(&mut self) -> u8 {
+        match self.0.split_first() {
+            Some((&byte, rest)) => {
+                self.0 = rest;
+                byte
+            }
+            None => 0,
+        }
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes([self.u8(), self.u8()])
+    }
+}
+
+/// `Probing::Custom` function stepping over 3 buckets at a time.
+fn probe_odd_steps(hash: usize, i: usize) -> usize {
+    hash.wrapping_add(3 * i)
+}
+
+/// `Probing::Custom` function with cubic steps.
+fn probe_cubic(hash: usize, i: usize) -> usize {
+    hash.wrapping_add(i.wrapping_mul(i).wrapping_mul(i))
+}
+
+/// `Probing::CustomDouble` function adding quadratic steps to double hashing.
+fn probe_double_squares(hash: usize, hash2: usize, i: usize) -> usize {
+    hash.wrapping_add(hash2.wrapping_mul(i)).wrapping_add(i.wrapping_mul(i))
+}
+
+/// Reads a capacity and `Parameters` (9 bytes), or `None` for parameters
+/// rejected by `Index::with_capacity_and_parameters`, or growing too fast
+/// for the memory of a fuzzer.
+///
+/// The bytes are the backend, the probing, the capacity (2), the maximum load (2),
+/// the growth policy, the small capacity, and the filter along with its false
+/// positive rate.
+fn parameters(input: &mut Input<'_>) -> Option<(usize, Parameters<IndexHasherBuilder>)> {
+    let backend = match input.u8() % 4 {
+        0 => Backend::OpenAddressing,
+        1 => Backend::SeparateChaining,
+        2 => Backend::Cuckoo,
+        _ => Backend::Hopscotch,
+    };
+    let probe = match input.u8() % 7 {
+        0 => Probing::Linear,
+        1 => Probing::Quadratic,
+        2 => Probing::Triangular,
+        3 => Probing::DoubleHashing,
+        4 => Probing::Custom(probe_odd_steps),
+        5 => Probing::Custom(probe_cubic),
+        _ => Probing::CustomDouble(probe_double_squares),
+    };
+    let capacity = input.u16() as usize % 1024;
+    let max_load = input.u16() as f64 / 4096.0; // [0, 16)
+    let growth_policy = 1.0 + input.u8() as f64 / 32.0; // [1, 9)
+    let small_capacity = input.u8() as usize % (MAX_SMALL_CAPACITY + 1);
+    let filter = input.u8();
+    let false_positive_rate = (1 + filter / 3) as f64 / 128.0; // (0, 0.68]
+    let filter = match filter % 3 {
+        0 => None,
+        1 => Some(Filter::Bloom { false_positive_rate }),
+        _ => Some(Filter::Cuckoo { false_positive_rate }),
+    };
+
+    if max_load < 1.0 / 64.0 || growth_policy <= 1.0 {
+        return None;
+    }
+
+    let params = Parameters { max_load, growth_policy, probe, backend, small_capacity, filter, ..Parameters::default() };
+    Some((capacity, params))
+}
+
+/// Returns an error if the `Index` and the model hold different entries, looking up
+/// every key below 256 (the keys of the harness).
+fn check(index: &Index<u16, u32>, model: &HashMap<u16, u32>) -> Result<(), String> {
+    if index.len() != model.len() || index.is_empty() != model.is_empty() {
+        return Err(format!("len is {}, expected {}", index.len(), model.len()));
+    }
+
+    let mut entries: Vec<(u16, u32)> = index.iter().map(|pair| *pair).collect();
+    entries.sort_unstable();
+    let mut expected: Vec<(u16, u32)> = model.iter().map(|(&k, &v)| (k, v)).collect();
+    expected.sort_unstable();
+    if entries != expected {
+        return Err(format!("iter yielded {:?}, expected {:?}", entries, expected));
+    }
+
+    for k in 0..256 {
+        if index.get(&k).map(|v| *v).as_ref() != model.get(&k) {
+            return Err(format!("get({}) disagrees with iter", k));
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies `Parameters` and operations read from the bytes to an `Index` and to a `HashMap`,
+/// and panics if they disagree.
+pub fn operations(data: &[u8]) {
+    let mut input = Input(data);
+    let (capacity, params) = match parameters(&mut input) {
+        Some(parameters) => parameters,
+        None => return,
+    };
+
+    let mut index: Index<u16, u32> = Index::with_capacity_and_parameters(capacity, params);
+    let mut model = HashMap::new();
+
+    while !input.is_empty() {
+        let key = input.u8() as u16;
+        match input.u8() % 10 {
+            0..=3 => {
+                let value = input.u8() as u32;
+                let old = index.insert(key, value).map(|pair| pair.into_inner());
+                assert_eq!(old, model.insert(key, value).map(|v| (key, v)));
+            }
+            4 => assert_eq!(index.get(&key).map(|v| *v).as_ref(), model.get(&key)),
+            5 => {
+                let value = input.u8() as u32;
+                let old = index.get_mut(&key).map(|mut v| std::mem::replace(&mut *v, value));
+                assert_eq!(old, model.get_mut(&key).map(|v| std::mem::replace(v, value)));
+            }
+            6 => {
+                index.clear();
+                model.clear();
+            }
+            7 => {
+                // dropping the drain partway through
+                for (k, v) in index.drain().take(key as usize % 16) {
+                    assert_eq!(model.get(&k), Some(&v));
+                }
+                model.clear();
+            }
+            8 => {
+                for mut value in index.values_mut() {
+                    *value = value.wrapping_add(1);
+                }
+                model.values_mut().for_each(|v| *v = v.wrapping_add(1));
+                assert_eq!(index.keys().count(), model.len());
+                assert_eq!(index.values().map(|v| *v as u64).sum::<u64>(), model.values().map(|&v| v as u64).sum::<u64>());
+            }
+            _ => {
+                let stats = index.stats();
+                assert_eq!(stats.probe_lengths.iter().sum::<usize>(), model.len());
+                let layout = index.layout();
+                let occupied = layout.lines().map(|line| line.split(" | ").nth(1).unwrap().matches(|c| c != '.').count());
+                assert_eq!(occupied.sum::<usize>(), model.len());
+            }
+        }
+
+        if let Err(err) = check(&index, &model) {
+            panic!("{}", err);
+        }
+    }
+}
+
+/// Re-encodes a value decoded from `data`, and panics if it differs from `data`.
+fn round_trip<T: Decode + Encode + Debug>(data: &[u8]) {
+    if let Ok(value) = codec::from_bytes::<T>(data) {
+        assert_eq!(codec::to_bytes(&value), data, "{:?}", value);
+    }
+}
+
+/// Decodes values of the `codec` format from the bytes (the first one selecting the type),
+/// and panics if decoding a value does not give it back when encoded.
+pub fn decode(data: &[u8]) {
+    let (&selector, data) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    match selector % 6 {
+        0 => round_trip::<String>(data),
+        1 => round_trip::<Vec<(u32, String)>>(data),
+        2 => round_trip::<Option<Vec<u64>>>(data),
+        3 => round_trip::<(bool, i16, usize)>(data),
+        4 => round_trip::<Vec<Vec<u8>>>(data),
+        _ => round_trip::<(String, Vec<usize>, Option<bool>)>(data),
+    }
+}
+
+/// Opens the bytes as a frozen index file of `String` keys and `Vec<u32>` values,
+/// and queries it. Errors are expected, panics are not.
+pub fn frozen(data: &[u8]) {
+    let path = std::env::temp_dir().join(format!("index-fuzz-frozen-{}.idx", std::process::id()));
+    std::fs::write(&path, data).unwrap();
+
+    if let Ok(file_index) = FileIndex::<String, Vec<u32>>::open(&path) {
+        assert!(file_index.len() <= file_index.capacity());
+        for key in &["", "fool", "crown", "lear"] {
+            let _ = file_index.get(*key);
+            let _ = file_index.contains_key(*key);
+        }
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::panic;
+    use std::path::Path;
+
+    /// Runs the harness on every input of the corpus of the target, returning their number.
+    fn replay(target: &str, harness: fn(&[u8])) -> usize {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus").join(target);
+
+        let mut inputs = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let data = fs::read(&path).unwrap();
+            let replayed = panic::catch_unwind(|| harness(&data));
+            assert!(replayed.is_ok(), "{} failed on {}", target, path.display());
+            inputs += 1;
+        }
+        inputs
+    }
+
+    #[test]
+    fn replay_operations() {
+        assert!(replay("operations", operations) > 0);
+    }
+
+    #[test]
+    fn replay_decode() {
+        assert!(replay("decode", decode) > 0);
+    }
+
+    #[test]
+    fn replay_frozen() {
+        assert!(replay("frozen", frozen) > 0);
+    }
+}
@@ -4,7 +4,7 @@
 //!
 //! [`Index`]: struct.Index.html
 
-use std::hash::{BuildHasher, Hash, Hasher};
+use core::hash::{BuildHasher, Hash, Hasher};
 
 /// Hashes a `value` using a specified `hasher_builder`.
 ///
@@ -21,11 +21,44 @@ use std::hash::{BuildHasher, Hash, Hasher};
 /// assert_eq!(hashed, 0xf1b59cbd9867ed1);
 /// ```
 pub fn make_hash<K: Hash + ?Sized>(hasher_builder: &impl BuildHasher, value: &K) -> u64 {
+    hasher_builder.hash_one(value)
+}
+
+/// Hashes a `value` using a specified `hasher_builder`, after feeding `seed`
+/// to the hasher, so that a single hasher builder provides a family of
+/// hash functions indexed by their seed.
+///
+/// # Example
+///
+/// ```
+/// use index::hash::{make_hash, make_seeded_hash, IndexHasherBuilder};
+///
+/// let val = String::from("Hash this !");
+///
+/// let h1 = make_seeded_hash(&IndexHasherBuilder, 1, &val);
+/// let h2 = make_seeded_hash(&IndexHasherBuilder, 2, &val);
+///
+/// assert_ne!(h1, h2);
+/// assert_ne!(h1, make_hash(&IndexHasherBuilder, &val));
+/// ```
+pub fn make_seeded_hash<K: Hash + ?Sized>(hasher_builder: &impl BuildHasher, seed: u64, value: &K) -> u64 {
     let mut hasher = hasher_builder.build_hasher();
+    hasher.write_u64(seed);
     value.hash(&mut hasher);
     hasher.finish()
 }
 
+/// Finalizer of MurmurHash3, spreading every bit of `hash` over the whole word,
+/// for the structures deriving several indices from one hash, since the FNV
+/// hasher barely mixes the last bytes of its input.
+pub(crate) fn mix(mut hash: u64) -> u64 {
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
 /// Simple hasher using the 64-bit [FNV-1 hash function](https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function)
 /// with 64-bit FNV offset basis: `0xcbf29ce484222325`
 /// and 64-bit FNV prime: `0x100000001b3`.
@@ -70,7 +103,8 @@ impl Clone for IndexHasher {
 /// Builder for [`IndexHasher`].
 ///
 /// [`IndexHasher`]: struct.IndexHasher.html
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IndexHasherBuilder;
 
 impl BuildHasher for IndexHasherBuilder {
@@ -85,6 +119,8 @@ impl BuildHasher for IndexHasherBuilder {
 mod tests {
     use super::*;
 
+    use std::string::String;
+
     #[test]
     fn hash_string() {
         let val = String::from("Hash this !");
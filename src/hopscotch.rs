@@ -0,0 +1,195 @@
+//! Module implementing the hopscotch hashing storage
+//! of an [`Index`] hash table.
+//!
+//! Every key lives in the neighborhood of its home bucket, made of the
+//! [`NEIGHBORHOOD`] buckets following it, and every home bucket keeps a
+//! bitmap of the buckets of its neighborhood holding its keys. A lookup
+//! only reads the buckets flagged in one bitmap, which are close in memory.
+//!
+//! An insertion takes the first empty bucket after the home bucket, and
+//! moves it back into the neighborhood by displacing entries of earlier home
+//! buckets further (within their own neighborhoods). When no entry can be
+//! displaced the table has to grow. This keeps lookups efficient at load
+//! factors around `0.9`. The bitmaps are allocated from the storage of the table.
+//!
+//! [`Index`]: ../struct.Index.html
+//! [`NEIGHBORHOOD`]: constant.NEIGHBORHOOD.html
+
+use super::hash::mix;
+use super::raw::{RawStorage, RawTable};
+use super::Bucket;
+
+use core::cell::{Ref, RefCell};
+
+/// Number of buckets of a neighborhood, the size of a bitmap.
+const NEIGHBORHOOD: usize = 32;
+
+/// Neighborhood bitmaps of a hopscotch hashing `Index`.
+#[derive(Debug, Clone)]
+pub(crate) struct Hops<A: RawStorage> {
+    /// Bit `j` of the bitmap of bucket `i` is set if bucket `i + j` holds a key of home bucket `i`.
+    bitmaps: RawTable<u32, A>,
+}
+
+impl<A: RawStorage> Hops<A> {
+    /// Creates empty bitmaps for the specified number of buckets, allocated from `storage`.
+    pub(crate) fn new(capacity: usize, storage: &A) -> Hops<A> {
+        Hops { bitmaps: RawTable::from_elem_in(0, capacity, storage.clone()) }
+    }
+
+    /// Clears every bitmap.
+    pub(crate) fn reset(&mut self) {
+        self.bitmaps.iter_mut().for_each(|bitmap| *bitmap = 0);
+    }
+
+    fn capacity(&self) -> usize {
+        self.bitmaps.len()
+    }
+
+    /// Distance from bucket `from` to bucket `to`, wrapping around the end of the table.
+    fn distance(&self, from: usize, to: usize) -> usize {
+        (to + self.capacity() - from) % self.capacity()
+    }
+
+    /// Returns the home bucket of `hash`. The hash is mixed before reduction:
+    /// the low bits of FNV-1 only depend on the low bits of the bytes of the key.
+    fn home(&self, hash: usize) -> usize {
+        mix(hash as u64) as usize % self.capacity()
+    }
+
+    /// Returns the positions of the buckets holding keys of the home bucket of `hash`.
+    pub(crate) fn neighbors(&self, hash: usize) -> impl Iterator<Item = usize> + '_ {
+        self.home_neighbors(self.home(hash))
+    }
+
+    /// Returns the positions of the buckets holding keys of the home bucket `home`.
+    pub(crate) fn home_neighbors(&self, home: usize) -> impl Iterator<Item = usize> + '_ {
+        let bitmap = self.bitmaps[home];
+        (0..NEIGHBORHOOD)
+            .filter(move |j| bitmap & (1 << j) != 0)
+            .map(move |j| (home + j) % self.capacity())
+    }
+
+    /// Searches the neighborhood of `hash` for an entry matching the discriminating closure,
+    /// and returns its position.
+    pub(crate) fn find<K, V, F>(&self, table: &[Bucket<K, V>], hash: usize, f: F) -> Option<usize>
+    where
+        F: Fn(Ref<(K, V)>) -> bool,
+    {
+        self.neighbors(hash)
+            .find(|&i| matches!(&table[i], Some(pair) if f(pair.borrow())))
+    }
+
+    /// Stores a new entry in the neighborhood of `hash` and returns its position,
+    /// or gives it back if the table has no room for it there.
+    pub(crate) fn insert<K, V>(&mut self, table: &mut [Bucket<K, V>], hash: usize, pair: (K, V)) -> Result<usize, (K, V)> {
+        let capacity = self.capacity();
+        let home = self.home(hash);
+
+        let mut empty = match (0..capacity).map(|i| (home + i) % capacity).find(|&i| table[i].is_none()) {
+            Some(empty) => empty,
+            None => return Err(pair),
+        };
+
+        // hopping the empty bucket back until it reaches the neighborhood
+        while self.distance(home, empty) >= NEIGHBORHOOD.min(capacity) {
+            match self.displace(table, empty) {
+                Some(closer) => empty = closer,
+                None => return Err(pair),
+            }
+        }
+
+        table[empty] = Some(RefCell::new(pair));
+        self.bitmaps[home] |= 1 << self.distance(home, empty);
+        Ok(empty)
+    }
+
+    /// Moves into the `empty` bucket the entry of an earlier home bucket which is
+    /// the furthest away from `empty` while having it in its neighborhood,
+    /// and returns the position the entry was moved from.
+    fn displace<K, V>(&mut self, table: &mut [Bucket<K, V>], empty: usize) -> Option<usize> {
+        let capacity = self.capacity();
+
+        for back in (1..NEIGHBORHOOD).rev() {
+            let home = (empty + capacity - back) % capacity;
+            let bitmap = self.bitmaps[home];
+
+            // first entry of `home` between `home` and `empty`
+            if let Some(j) = (0..back).find(|j| bitmap & (1 << j) != 0) {
+                let from = (home + j) % capacity;
+
+                table[empty] = table[from].take();
+                self.bitmaps[home] = bitmap & !(1 << j) | (1 << back);
+                return Some(from);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hash::{make_hash, IndexHasherBuilder};
+    use crate::tests::lear_vocabulary;
+    use crate::{Backend, Index, Parameters};
+
+    use std::vec::Vec;
+
+    #[test]
+    fn lear_vocabulary_high_load() {
+        let words = lear_vocabulary();
+
+        for &(capacity, max_load) in &[(1, 0.7), (4480, 0.9), (4360, 0.93)] {
+            let params: Parameters<IndexHasherBuilder> = Parameters { max_load, backend: Backend::Hopscotch, ..Parameters::default() };
+            let mut index = Index::with_capacity_and_parameters(capacity, params);
+            for (i, word) in words.iter().enumerate() {
+                assert!(index.insert(word.clone(), i).is_none());
+            }
+
+            assert_eq!(index.len(), words.len());
+            assert_eq!(index.iter().count(), words.len());
+            assert!(index.load() < max_load);
+            if capacity > 1 {
+                assert_eq!(index.capacity(), capacity); // no growth needed up to the maximum load
+            }
+
+            for (i, word) in words.iter().enumerate() {
+                assert_eq!(*index.get(word.as_str()).unwrap(), i);
+            }
+            assert!(index.get("rustacean").is_none());
+
+            // every entry is flagged once, in the neighborhood of its home bucket
+            let hops = match &index.storage {
+                crate::Storage::Hopscotch(hops) => hops,
+                _ => unreachable!(),
+            };
+            let flagged: u32 = hops.bitmaps.iter().map(|b| b.count_ones()).sum();
+            assert_eq!(flagged as usize, words.len());
+            for word in words.iter() {
+                let hash = make_hash(&IndexHasherBuilder, word.as_str()) as usize;
+                assert!(hops.find(&index.table, hash, |p| p.0 == *word).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn keys_sharing_low_bits() {
+        let params: Parameters<IndexHasherBuilder> = Parameters { backend: Backend::Hopscotch, ..Parameters::default() };
+
+        // every byte of the keys is 0 or 128, so their raw hashes agree on the low bits
+        let keys: Vec<u32> = (0..16u32).map(|i| (0..4).map(|b| (i >> b & 1) << (8 * b + 7)).sum()).collect();
+        let mut index = Index::with_capacity_and_parameters(256, params);
+        for &key in &keys {
+            index.insert(key, key);
+        }
+
+        let stats = index.stats();
+        assert_eq!(stats.capacity, 256);
+        assert!(stats.longest_cluster <= 3, "{}", stats);
+        assert!(stats.max_probe_length <= 3, "{}", stats);
+        for key in &keys {
+            assert_eq!(*index.get(key).unwrap(), *key);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Module implementing the internal consistency checks
+//! of an [`Index`] hash table.
+//!
+//! [`Index::validate_invariants`] walks the table and reports every
+//! [`Violation`] it finds. With the `check-invariants` feature, debug builds
+//! check the entry and length of every insertion, run the full walk after every
+//! resize, check that cleared and drained tables are empty, and panic on the
+//! first inconsistency.
+//!
+//! The walk after a resize is the only full one: a duplicate key or an
+//! unreachable entry elsewhere in the table is caught by the next resize. The
+//! `check-invariants-full` feature also runs the full walk after every insertion,
+//! which makes filling a table quadratic.
+//!
+//! [`Index`]: ../struct.Index.html
+//! [`Index::validate_invariants`]: ../struct.Index.html#method.validate_invariants
+//! [`Violation`]: enum.Violation.html
+
+use super::hash::*;
+use super::filter::filter_hash;
+use super::observer::IndexObserver;
+use super::raw::RawStorage;
+use super::{probe_hashes, Index, Storage};
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+#[cfg(feature = "std")]
+use std::error::Error;
+
+/// Inconsistency found in the table of an `Index`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The `len` of the `Index` differs from the number of occupied buckets.
+    LenMismatch { len: usize, occupied: usize },
+
+    /// The key stored in `bucket` cannot be found from its home bucket
+    /// through the probe sequence (or chain, pair, neighborhood) of the `Index`.
+    Unreachable { bucket: usize },
+
+    /// The buckets `first` and `second` hold equal keys.
+    DuplicateKey { first: usize, second: usize },
+
+    /// The capacity of the `Index` differs from the number of buckets of its table.
+    CapacityMismatch { capacity: usize, buckets: usize },
+
+    /// The key stored in `bucket` is rejected by the membership filter of the `Index`.
+    FilteredOut { bucket: usize },
+
+    /// The `len` of the `Index` after an insertion differs from the `expected` one.
+    /// Only reported by the checks of the `check-invariants` feature.
+    UnexpectedLen { len: usize, expected: usize },
+
+    /// The `bucket` an insertion stored its entry in is empty.
+    /// Only reported by the checks of the `check-invariants` feature.
+    MissingEntry { bucket: usize },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::LenMismatch { len, occupied } => {
+                write!(f, "len is {} but {} buckets are occupied", len, occupied)
+            }
+            Violation::Unreachable { bucket } => {
+                write!(f, "key of bucket {} is unreachable from its home bucket", bucket)
+            }
+            Violation::DuplicateKey { first, second } => {
+                write!(f, "buckets {} and {} hold the same key", first, second)
+            }
+            Violation::CapacityMismatch { capacity, buckets } => {
+                write!(f, "capacity is {} but the table has {} buckets", capacity, buckets)
+            }
+            Violation::FilteredOut { bucket } => {
+                write!(f, "key of bucket {} is rejected by the membership filter", bucket)
+            }
+            Violation::UnexpectedLen { len, expected } => {
+                write!(f, "len is {} but {} was expected after the insertion", len, expected)
+            }
+            Violation::MissingEntry { bucket } => {
+                write!(f, "bucket {} of the inserted entry is empty", bucket)
+            }
+        }
+    }
+}
+
+/// Error returned by [`Index::validate_invariants`], listing every violation found.
+///
+/// [`Index::validate_invariants`]: ../struct.Index.html#method.validate_invariants
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantError {
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invariant violation(s)", self.violations.len())?;
+        for violation in &self.violations {
+            write!(f, "\n - {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for InvariantError {}
+
+impl<K, V, S, O, A> Index<K, V, S, O, A>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+    O: IndexObserver,
+    A: RawStorage,
+{
+    /// Walks the table and checks that:
+    ///
+    /// - `len` matches the number of occupied buckets,
+    /// - every key is reachable from its home bucket through the probe sequence
+    ///   (or the chain, pairs or neighborhood of the backend),
+    /// - no key is stored twice,
+    /// - the membership filter, if any, accepts every key,
+    /// - the capacity matches the number of buckets.
+    ///
+    /// Returns every violation found, meant for debugging.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::new();
+    ///
+    /// for i in 0..100 {
+    ///     index.insert(i, i);
+    /// }
+    ///
+    /// assert!(index.validate_invariants().is_ok());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if an entry is mutably borrowed.
+    pub fn validate_invariants(&self) -> Result<(), InvariantError> {
+        let mut violations = Vec::new();
+
+        let buckets = match &self.storage {
+            Storage::Chained(chains) => chains.buckets(),
+            _ => self.table.len(),
+        };
+        if buckets != self.capacity {
+            violations.push(Violation::CapacityMismatch { capacity: self.capacity, buckets });
+        }
+
+        let occupied = self.table.iter().filter(|bucket| bucket.is_some()).count();
+        if occupied != self.len {
+            violations.push(Violation::LenMismatch { len: self.len, occupied });
+        }
+
+        // sorting the occupied buckets by hash, so that equal keys are neighbors
+        let mut hashed: Vec<(u64, usize)> = self
+            .table
+            .iter()
+            .enumerate()
+            .filter_map(|(i, bucket)| bucket.as_ref().map(|pair| (make_hash(self.hasher(), &pair.borrow().0), i)))
+            .collect();
+        hashed.sort_unstable();
+
+        for (j, &(hash, i)) in hashed.iter().enumerate() {
+            let key = &self.table[i].as_ref().unwrap().borrow().0;
+
+            if !self.reaches(key, i) {
+                violations.push(Violation::Unreachable { bucket: i });
+            }
+            if self.filter.as_ref().is_some_and(|filter| !filter.contains_hash(filter_hash(self.hasher(), key))) {
+                violations.push(Violation::FilteredOut { bucket: i });
+            }
+
+            for &(_, other) in hashed[j + 1..].iter().take_while(|(h, _)| *h == hash) {
+                if self.table[other].as_ref().unwrap().borrow().0 == *key {
+                    violations.push(Violation::DuplicateKey { first: i.min(other), second: i.max(other) });
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(InvariantError { violations })
+        }
+    }
+
+    /// Returns `true` if a lookup for `key` reads the bucket at position `bucket`.
+    fn reaches(&self, key: &K, bucket: usize) -> bool {
+        match &self.storage {
+            Storage::Small => bucket < self.len,
+            Storage::Open => {
+                let probe = self.params.probe;
+                let (hash, hash2) = probe_hashes(self.hasher(), probe, key);
+                (0..self.capacity)
+                    .map(|i| probe.probe(hash, hash2, i) % self.capacity)
+                    .find(|&i| i == bucket || self.table[i].is_none()) // a lookup stops at an empty bucket
+                    == Some(bucket)
+            }
+            Storage::Chained(chains) => chains.chain(make_hash(self.hasher(), key) as usize).any(|i| i == bucket),
+            Storage::Cuckoo(_) => self.cuckoo_buckets(key).any(|i| i == bucket),
+            Storage::Hopscotch(hops) => hops.neighbors(make_hash(self.hasher(), key) as usize).any(|i| i == bucket),
+        }
+    }
+
+    /// Panics on the first inconsistency of the table, in debug builds
+    /// with the `check-invariants` feature (does nothing otherwise).
+    #[inline]
+    pub(crate) fn debug_validate(&self) {
+        #[cfg(all(debug_assertions, feature = "check-invariants"))]
+        {
+            if let Err(err) = self.validate_invariants() {
+                panic!("{}", err);
+            }
+        }
+    }
+
+    /// Checks the entry just stored at position `bucket` and the `len` expected after
+    /// storing it, in debug builds with the `check-invariants` feature (does nothing otherwise).
+    ///
+    /// Unlike [`debug_validate`], this only costs a lookup, so that it can run after
+    /// every insertion: it neither counts the occupied buckets nor looks for duplicate
+    /// keys, which the full walk after every resize does. With the `check-invariants-full`
+    /// feature, the full walk follows.
+    ///
+    /// [`debug_validate`]: #method.debug_validate
+    #[inline]
+    #[allow(unused_variables)]
+    pub(crate) fn debug_validate_entry(&self, bucket: usize, len: usize) {
+        #[cfg(all(debug_assertions, feature = "check-invariants"))]
+        {
+            let mut violations = Vec::new();
+
+            if self.len != len {
+                violations.push(Violation::UnexpectedLen { len: self.len, expected: len });
+            }
+            match self.table.get(bucket) {
+                Some(Some(pair)) => {
+                    let key = &pair.borrow().0;
+                    if !self.reaches(key, bucket) {
+                        violations.push(Violation::Unreachable { bucket });
+                    }
+                    if self.filter.as_ref().is_some_and(|filter| !filter.contains_hash(filter_hash(self.hasher(), key))) {
+                        violations.push(Violation::FilteredOut { bucket });
+                    }
+                }
+                _ => violations.push(Violation::MissingEntry { bucket }),
+            }
+
+            if !violations.is_empty() {
+                panic!("{}", InvariantError { violations });
+            }
+
+            #[cfg(feature = "check-invariants-full")]
+            self.debug_validate();
+        }
+    }
+}
+
+impl<K, V, S, O, A: RawStorage> Index<K, V, S, O, A> {
+    /// Panics if the table of a cleared `Index` is not empty or lost buckets, in debug
+    /// builds with the `check-invariants` feature (does nothing otherwise).
+    #[inline]
+    pub(crate) fn debug_validate_empty(&self) {
+        #[cfg(all(debug_assertions, feature = "check-invariants"))]
+        {
+            let mut violations = Vec::new();
+
+            let buckets = match &self.storage {
+                Storage::Chained(chains) => chains.buckets(),
+                _ => self.table.len(),
+            };
+            if buckets != self.capacity {
+                violations.push(Violation::CapacityMismatch { capacity: self.capacity, buckets });
+            }
+
+            let occupied = self.table.iter().filter(|bucket| bucket.is_some()).count();
+            if self.len != 0 || occupied != 0 {
+                violations.push(Violation::LenMismatch { len: self.len, occupied });
+            }
+
+            if !violations.is_empty() {
+                panic!("{}", InvariantError { violations });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{each_backend, lear_vocabulary};
+
+    use std::cell::RefCell;
+    use std::string::ToString;
+
+    #[test]
+    fn every_backend() {
+        let words = lear_vocabulary();
+
+        each_backend(|params| {
+            let mut index = Index::with_capacity_and_parameters(1, params);
+            for (i, word) in words.iter().enumerate() {
+                index.insert(word.clone(), i);
+            }
+            assert_eq!(index.validate_invariants(), Ok(()));
+
+            index.drain().take(3).count();
+            assert_eq!(index.validate_invariants(), Ok(()));
+        });
+    }
+
+    #[test]
+    fn corrupted_table() {
+        let mut index: Index<u32, u32> = Index::with_capacity(64);
+        for i in 0..10 {
+            index.insert(i, i);
+        }
+
+        // copying an entry to the first empty bucket, and adding a bucket to the table
+        let (from, pair) = index.table.iter().enumerate().find_map(|(i, b)| b.as_ref().map(|p| (i, *p.borrow()))).unwrap();
+        let empty = index.table.iter().position(|b| b.is_none()).unwrap();
+        index.table[empty] = Some(RefCell::new(pair));
+        index.table.push(None);
+
+        let err = index.validate_invariants().unwrap_err();
+
+        assert!(err.violations.contains(&Violation::CapacityMismatch { capacity: 64, buckets: 65 }));
+        assert!(err.violations.contains(&Violation::LenMismatch { len: 10, occupied: 11 }));
+        assert!(err.violations.contains(&Violation::DuplicateKey { first: from.min(empty), second: from.max(empty) }));
+        assert!(err.to_string().contains("capacity is 64 but the table has 65 buckets"));
+    }
+
+    #[test]
+    #[cfg(all(debug_assertions, feature = "check-invariants"))]
+    #[should_panic(expected = "capacity is 64 but the table has 65 buckets")]
+    fn clear_checks_capacity() {
+        let mut index: Index<u32, u32> = Index::with_capacity(64);
+        index.insert(1, 1);
+        index.table.push(None);
+
+        index.clear();
+    }
+
+    #[test]
+    #[cfg(all(debug_assertions, feature = "check-invariants-full"))]
+    #[should_panic(expected = "hold the same key")]
+    fn insert_walks_whole_table() {
+        let mut index: Index<u32, u32> = Index::with_capacity(64);
+        for i in 0..10 {
+            index.insert(i, i);
+        }
+
+        // duplicating an entry away from the next insertion, which the entry check misses
+        let pair = index.table.iter().find_map(|b| b.as_ref().map(|p| *p.borrow())).unwrap();
+        let empty = index.table.iter().rposition(|b| b.is_none()).unwrap();
+        index.table[empty] = Some(RefCell::new(pair));
+        index.len += 1;
+
+        index.insert(100, 100);
+    }
+}
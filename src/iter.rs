@@ -3,9 +3,12 @@
 //!
 //! [`Index`]: struct.Index.html
 
-use super::Bucket;
+use super::observer::IndexObserver;
+use super::raw::RawStorage;
+use super::{Bucket, Change, Index};
 
-use std::cell::{Ref, RefMut};
+use core::cell::{Ref, RefMut};
+use core::hash::{BuildHasher, Hash};
 
 /// Iterator over the entries of an [`Index`] hash table.
 /// 
@@ -22,7 +25,7 @@ pub struct Iter<'a, K, V> {
 impl<K, V> Iter<'_, K, V> {
     /// Creates a new iterator over the buckets in the 
     /// slice passed as an immutable reference.
-    pub fn new(buckets: &[Bucket<K, V>]) -> Iter<K, V> {
+    pub fn new(buckets: &[Bucket<K, V>]) -> Iter<'_, K, V> {
         Iter {
             buckets,
             buckets_len: buckets.len(),
@@ -35,21 +38,16 @@ impl<'a, K, V> Iterator for Iter<'a, K, V> {
     type Item = Ref<'a, (K, V)>; // immutable reference from a RefCell
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.counter < self.buckets_len {
-            match &self.buckets[self.counter] {
-                Bucket::Some(pair) => { // returns borrowed pair
-                    self.counter += 1;
-                    Some(pair.borrow())
-                }
+        while self.counter < self.buckets_len {
+            let bucket = &self.buckets[self.counter];
+            self.counter += 1;
 
-                Bucket::None => { // ignores empty bucket
-                    self.counter += 1;
-                    self.next()
-                }
+            if let Bucket::Some(pair) = bucket { // returns borrowed pair, ignoring empty buckets
+                return Some(pair.borrow());
             }
-        } else { // end of iterator
-            None
         }
+
+        None // end of iterator
     }
 }
 
@@ -69,7 +67,7 @@ impl<K, V> IterMut<'_, K, V> {
     /// Creates a new iterator over the buckets in the 
     /// slice passed as an immutable reference. The interior mutability
     /// of the `RefCell`s is taken advantage of inside the `next` method.
-    pub fn new(buckets: &[Bucket<K, V>]) -> IterMut<K, V> {
+    pub fn new(buckets: &[Bucket<K, V>]) -> IterMut<'_, K, V> {
         IterMut {
             buckets,
             buckets_len: buckets.len(),
@@ -82,21 +80,16 @@ impl<'a, K, V> Iterator for IterMut<'a, K, V> {
     type Item = RefMut<'a, (K, V)>; // mutable reference from a RefCell
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.counter < self.buckets_len {
-            match &self.buckets[self.counter] {
-                Bucket::Some(pair) => { // returns mutably borrowed pair
-                    self.counter += 1;
-                    Some(pair.borrow_mut())
-                }
+        while self.counter < self.buckets_len {
+            let bucket = &self.buckets[self.counter];
+            self.counter += 1;
 
-                Bucket::None => { // ignoring empty bucket
-                    self.counter += 1;
-                    self.next()
-                }
+            if let Bucket::Some(pair) = bucket { // returns mutably borrowed pair, ignoring empty buckets
+                return Some(pair.borrow_mut());
             }
-        } else { // end of iterator
-            None
         }
+
+        None // end of iterator
     }
 }
 
@@ -113,7 +106,7 @@ pub struct Keys<'a, K, V> {
 impl<K, V> Keys<'_, K, V> {
     /// Creates a new iterator over the keys in the 
     /// slice passed as an immutable reference.
-    pub fn new(buckets: &[Bucket<K, V>]) -> Keys<K, V> {
+    pub fn new(buckets: &[Bucket<K, V>]) -> Keys<'_, K, V> {
         Keys {
             inner: Iter::new(buckets),
         }
@@ -124,11 +117,7 @@ impl<'a, K, V> Iterator for Keys<'a, K, V> {
     type Item = Ref<'a, K>; // immutable reference from a RefCell
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(pair) = self.inner.next() { // returns borrowed key
-            Some(Ref::map(pair, |t| &t.0))
-        } else { // end of iterator
-            None
-        }
+        self.inner.next().map(|pair| Ref::map(pair, |t| &t.0)) // returns borrowed key
     }
 }
 
@@ -145,7 +134,7 @@ pub struct Values<'a, K, V> {
 impl<K, V> Values<'_, K, V> {
     /// Creates a new iterator over the values in the 
     /// slice passed as an immutable reference.
-    pub fn new(buckets: &[Bucket<K, V>]) -> Values<K, V> {
+    pub fn new(buckets: &[Bucket<K, V>]) -> Values<'_, K, V> {
         Values {
             inner: Iter::new(buckets),
         }
@@ -156,11 +145,7 @@ impl<'a, K, V> Iterator for Values<'a, K, V> {
     type Item = Ref<'a, V>; // immutable reference from a RefCell
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(pair) = self.inner.next() { // returns borrowed value
-            Some(Ref::map(pair, |t| &t.1))
-        } else { // end of iterator
-            None
-        }
+        self.inner.next().map(|pair| Ref::map(pair, |t| &t.1)) // returns borrowed value
     }
 }
 
@@ -179,7 +164,7 @@ impl<K, V> ValuesMut<'_, K, V> {
     /// Creates a new iterator over the values in the 
     /// slice passed as an immutable reference. The interior mutability
     /// of the `RefCell`s is taken advantage of inside the `next` method.
-    pub fn new(buckets: &[Bucket<K, V>]) -> ValuesMut<K, V> {
+    pub fn new(buckets: &[Bucket<K, V>]) -> ValuesMut<'_, K, V> {
         ValuesMut {
             inner: IterMut::new(buckets),
         }
@@ -190,11 +175,7 @@ impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
     type Item = RefMut<'a, V>; // mutable reference from a RefCell
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(pair) = self.inner.next() { // returns mutably borrowed value
-            Some(RefMut::map(pair, |t| &mut t.1))
-        } else { // end of iterator
-            None
-        }
+        self.inner.next().map(|pair| RefMut::map(pair, |t| &mut t.1)) // returns mutably borrowed value
     }
 }
 
@@ -204,7 +185,8 @@ impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
 /// out of their `Bucket:Some(_)` options and `RefCell`s.
 /// 
 /// The `Drain` also updates the `len` field of the [`Index`] as it moves 
-/// out it's content.
+/// out it's content. Dropping the `Drain` before the end drops the remaining
+/// entries, so the [`Index`] is always left empty.
 /// 
 /// [`Index`]: struct.Index.html
 pub struct Drain<'a, K, V> {
@@ -237,23 +219,94 @@ impl<'a, K, V> Iterator for Drain<'a, K, V> {
     type Item = (K, V); // moved out key-value pair
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.counter < self.buckets_len {
-            match &self.buckets[self.counter] {
-                Bucket::Some(_) => { // returns moved out entry
-                    let removed = std::mem::replace(&mut self.buckets[self.counter], Bucket::None); // replacing with empty bucket
-                    let removed = removed.unwrap(); // safe because we know from match that it's a Some option
-                    self.counter += 1;
-                    *self.index_len -= 1; // updating len field of index
-                    Some(removed.into_inner()) // moving pair out of the RefCell
-                }
+        while self.counter < self.buckets_len {
+            let removed = self.buckets[self.counter].take(); // replacing with empty bucket
+            self.counter += 1;
 
-                Bucket::None => { // ignores empty bucket
-                    self.counter += 1;
-                    self.next()
+            if let Some(removed) = removed { // returns moved out entry, ignoring empty buckets
+                *self.index_len -= 1; // updating len field of index
+                return Some(removed.into_inner()); // moving pair out of the RefCell
+            }
+        }
+
+        None // end of iterator
+    }
+}
+
+impl<K, V> Drop for Drain<'_, K, V> {
+    fn drop(&mut self) {
+        self.for_each(drop); // moving out and dropping the entries left
+
+        // the storage of the index was reset when the drain was created
+        #[cfg(all(debug_assertions, feature = "check-invariants"))]
+        {
+            assert_eq!(*self.index_len, 0, "len of a drained index");
+            assert!(self.buckets.iter().all(|bucket| bucket.is_none()), "occupied bucket in a drained index");
+        }
+    }
+}
+
+/// Iterator over the changes between two [`Index`] hash tables, created by
+/// [`Index::diff`].
+///
+/// The entries of the old table are looked up in the new one first, yielding
+/// `Removed` and `Changed` items, then the entries of the new table missing
+/// from the old one are yielded as `Added` items.
+///
+/// [`Index`]: struct.Index.html
+/// [`Index::diff`]: struct.Index.html#method.diff
+pub struct Diff<'a, K, V, S, O, A: RawStorage> {
+    old: &'a Index<K, V, S, O, A>,
+    new: &'a Index<K, V, S, O, A>,
+    old_entries: Iter<'a, K, V>,
+    new_entries: Iter<'a, K, V>,
+}
+
+impl<'a, K, V, S, O, A: RawStorage> Diff<'a, K, V, S, O, A> {
+    /// Creates a new iterator over the changes turning `old` into `new`.
+    pub fn new(old: &'a Index<K, V, S, O, A>, new: &'a Index<K, V, S, O, A>) -> Diff<'a, K, V, S, O, A> {
+        Diff {
+            old,
+            new,
+            old_entries: old.iter(),
+            new_entries: new.iter(),
+        }
+    }
+}
+
+impl<'a, K, V, S, O, A> Iterator for Diff<'a, K, V, S, O, A>
+where
+    K: Hash + Eq,
+    V: PartialEq,
+    S: BuildHasher + Clone,
+    O: IndexObserver,
+    A: RawStorage,
+{
+    type Item = Change<Ref<'a, K>, Ref<'a, V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for pair in &mut self.old_entries {
+            // looked up without notifying the observers, which only see the diff itself
+            match self.new.lookup_unobserved(&pair.0).map(|new| Ref::map(new.borrow(), |p| &p.1)) {
+                None => {
+                    let (key, value) = Ref::map_split(pair, |p| (&p.0, &p.1));
+                    return Some(Change::Removed(key, value));
+                }
+                Some(new_value) if *new_value != pair.1 => {
+                    let (key, old_value) = Ref::map_split(pair, |p| (&p.0, &p.1));
+                    return Some(Change::Changed(key, old_value, new_value));
                 }
+                Some(_) => {}
             }
-        } else { // end of iterator
-            None
         }
+
+        for pair in &mut self.new_entries {
+            if self.old.lookup_unobserved(&pair.0).is_none() {
+                let (key, value) = Ref::map_split(pair, |p| (&p.0, &p.1));
+                return Some(Change::Added(key, value));
+            }
+        }
+
+        None // end of iterator
     }
 }
@@ -1,20 +1,67 @@
 //! Practice implementation of a hash table.
+//!
+//! The crate is `no_std` and only needs `alloc`. The default `std` feature
+//! adds the APIs depending on the standard library, like the [`frozen`]
+//! on-disk layout.
+//!
+//! [`frozen`]: frozen/index.html
 
+#![no_std]
+
+extern crate alloc;
+#[cfg(any(test, feature = "std"))]
+extern crate std;
+
+pub mod array;
+pub mod cache;
+mod chaining;
+pub mod codec;
+mod cuckoo;
+pub mod expiry;
+pub mod filter;
+#[cfg(feature = "std")]
+pub mod frozen;
+#[cfg(all(any(test, feature = "fuzzing"), feature = "std"))]
+pub mod fuzzing;
 pub mod hash;
+mod hopscotch;
+pub mod invariants;
 pub mod iter;
+pub mod observer;
+pub mod perfect;
+pub mod persistent;
+pub mod raw;
 
+#[cfg(feature = "serde")]
+mod serde_impl;
+pub mod stats;
+pub mod strings;
+
+use self::chaining::Chains;
+use self::cuckoo::Cuckoo;
+use self::filter::{filter_hash, Filter, Membership};
 use self::hash::*;
+use self::hopscotch::Hops;
 use self::iter::*;
+use self::observer::{IndexObserver, NoObserver, Resizes};
+use self::raw::{GlobalStorage, RawStorage, RawTable};
+use self::stats::Counters;
 
-use std::borrow::Borrow;
-use std::cell::{Ref, RefCell, RefMut};
-use std::fmt;
-use std::hash::{BuildHasher, Hash};
+use alloc::vec;
+use core::borrow::Borrow;
+use core::cell::{Ref, RefCell, RefMut};
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
 
 
 const DEFAULT_MAX_LOAD: f64 = 0.7;
 const DEFAULT_GROWTH_POLICY: f64 = 2.0;
-const DEFAULT_PROBING: fn(usize, usize) -> usize = |hash, i| hash + i + i*i;
+const DEFAULT_PROBING: Probing = Probing::Quadratic;
+const DEFAULT_BACKEND: Backend = Backend::OpenAddressing;
+const DEFAULT_SMALL_CAPACITY: usize = 8;
+const MAX_SMALL_CAPACITY: usize = 64; // lookups scan every entry
+
+const SECOND_HASH_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
 
 const DEFAULT_INITIAL_CAPACITY: usize = 1; // not handling zero sized
 
@@ -26,6 +73,301 @@ pub type Bucket<K, V> = Option<RefCell<(K, V)>>;
 type Find<'a, K, V> = (Option<&'a RefCell<(K, V)>>, Option<usize>);
 
 
+/// Named open addressing probing strategies.
+///
+/// The built-in strategies are dispatched statically inside the lookup loop,
+/// can be compared, printed and serialized by name (with the `serde` feature),
+/// while `Custom` wraps an arbitrary probing function.
+///
+/// # Example
+///
+/// ```
+/// use index::Probing;
+///
+/// assert_eq!(Probing::Linear.probe(10, 0, 3), 13);
+/// assert_eq!(Probing::Quadratic.probe(10, 0, 3), 22);
+/// assert_eq!(Probing::Triangular.probe(10, 0, 3), 16);
+/// assert_eq!(Probing::DoubleHashing.probe(10, 5, 3), 25);
+/// assert_eq!(Probing::Custom(|hash, i| hash + 2*i).probe(10, 0, 3), 16);
+/// assert_eq!(Probing::CustomDouble(|hash, hash2, i| hash + hash2*i*i).probe(10, 5, 3), 55);
+///
+/// assert_eq!(Probing::Triangular, Probing::Triangular);
+/// assert_eq!(format!("{:?}", Probing::Custom(|hash, _| hash)), "Custom(..)");
+/// ```
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Probing {
+    /// Linear probing: `hash + i`
+    Linear,
+
+    /// Quadratic probing: `hash + i + i*i`
+    Quadratic,
+
+    /// Triangular probing: `hash + i*(i+1)/2`, which visits every
+    /// bucket when the capacity is a power of two.
+    Triangular,
+
+    /// Double hashing: `hash + i*hash2`, where `hash2` is an odd value computed
+    /// by a second, independent hash of the key. Keys sharing the same home bucket
+    /// follow different probe sequences.
+    DoubleHashing,
+
+    /// User defined probing function of the hash and the probe count.
+    /// Cannot be serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(fn(hash: usize, i: usize) -> usize),
+
+    /// User defined probing function of the hash, the odd second hash and the probe count.
+    /// Cannot be serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    CustomDouble(fn(hash: usize, hash2: usize, i: usize) -> usize),
+}
+
+impl Probing {
+    /// Returns the `i`-th position (before reduction modulo the capacity)
+    /// of the probe sequence starting from `hash`. The second hash `hash2`
+    /// is only used by the double hashing strategies.
+    #[inline]
+    pub fn probe(self, hash: usize, hash2: usize, i: usize) -> usize {
+        match self {
+            Probing::Linear => linear(hash, hash2, i),
+            Probing::Quadratic => quadratic(hash, hash2, i),
+            Probing::Triangular => triangular(hash, hash2, i),
+            Probing::DoubleHashing => double_hashing(hash, hash2, i),
+            Probing::Custom(f) => f(hash, i),
+            Probing::CustomDouble(f) => f(hash, hash2, i),
+        }
+    }
+
+    /// Returns `true` if the strategy needs a second hash of the keys.
+    pub fn uses_second_hash(self) -> bool {
+        matches!(self, Probing::DoubleHashing | Probing::CustomDouble(_))
+    }
+
+    /// Returns `true` if the probe sequence starting from `hash` (and `hash2`)
+    /// visits every bucket of a table of the specified capacity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Probing;
+    ///
+    /// assert!(Probing::Linear.covers(0, 0, 10));
+    /// assert!(Probing::Triangular.covers(7, 0, 64));
+    /// assert!(Probing::DoubleHashing.covers(7, 13, 64));
+    /// assert!(!Probing::Quadratic.covers(7, 0, 64)); // i + i*i is always even
+    /// ```
+    pub fn covers(self, hash: usize, hash2: usize, capacity: usize) -> bool {
+        let mut visited = vec![false; capacity];
+        for i in 0..capacity {
+            visited[self.probe(hash, hash2, i) % capacity] = true;
+        }
+        visited.into_iter().all(|v| v)
+    }
+}
+
+#[inline(always)]
+fn linear(hash: usize, _: usize, i: usize) -> usize {
+    hash.wrapping_add(i)
+}
+
+#[inline(always)]
+fn quadratic(hash: usize, _: usize, i: usize) -> usize {
+    hash.wrapping_add(i).wrapping_add(i.wrapping_mul(i))
+}
+
+#[inline(always)]
+fn triangular(hash: usize, _: usize, i: usize) -> usize {
+    hash.wrapping_add(i.wrapping_mul(i.wrapping_add(1)) / 2)
+}
+
+#[inline(always)]
+fn double_hashing(hash: usize, hash2: usize, i: usize) -> usize {
+    hash.wrapping_add(i.wrapping_mul(hash2))
+}
+
+/// Computes the hashes needed by a probe sequence: the hash of the key,
+/// and an odd second hash if the probing strategy uses one (`0` otherwise).
+pub(crate) fn probe_hashes<Q, S>(hasher_builder: &S, probe: Probing, key: &Q) -> (usize, usize)
+where
+    Q: Hash + ?Sized,
+    S: BuildHasher,
+{
+    (make_hash(hasher_builder, key) as usize, second_hash(hasher_builder, probe, key))
+}
+
+/// Computes the odd second hash of a key if the probing strategy uses one (`0` otherwise).
+pub(crate) fn second_hash<Q, S>(hasher_builder: &S, probe: Probing, key: &Q) -> usize
+where
+    Q: Hash + ?Sized,
+    S: BuildHasher,
+{
+    if probe.uses_second_hash() {
+        make_seeded_hash(hasher_builder, SECOND_HASH_SEED, key) as usize | 1
+    } else {
+        0
+    }
+}
+
+/// Searches the open addressing buckets of `table` for an entry according to specified
+/// hashes and discriminating closure, returning the result and the number of probes.
+///
+/// The probing strategy is matched once here so that the built-in strategies
+/// are inlined in the probing loop of `find_in_with`.
+pub(crate) fn find_in<K, V, F>(table: &[Bucket<K, V>], probe: Probing, (hash, hash2): (usize, usize), f: F) -> (Find<'_, K, V>, usize)
+where
+    F: Fn(Ref<(K, V)>) -> bool,
+{
+    match probe {
+        Probing::Linear => find_in_with(table, hash, hash2, f, linear),
+        Probing::Quadratic => find_in_with(table, hash, hash2, f, quadratic),
+        Probing::Triangular => find_in_with(table, hash, hash2, f, triangular),
+        Probing::DoubleHashing => find_in_with(table, hash, hash2, f, double_hashing),
+        Probing::Custom(probe) => find_in_with(table, hash, hash2, f, |h, _, i| probe(h, i)),
+        Probing::CustomDouble(probe) => find_in_with(table, hash, hash2, f, probe),
+    }
+}
+
+/// Probing loop of `find_in`, generic over the probing function.
+#[inline(always)]
+fn find_in_with<K, V, F, P>(table: &[Bucket<K, V>], hash: usize, hash2: usize, f: F, probe: P) -> (Find<'_, K, V>, usize)
+where
+    F: Fn(Ref<(K, V)>) -> bool,
+    P: Fn(usize, usize, usize) -> usize,
+{
+    let capacity = table.len();
+
+    for i in 0..capacity {
+        let probe = probe(hash, hash2, i) % capacity;
+
+        match &table[probe] {
+            Some(pair) if f(pair.borrow()) => return ((Some(pair), Some(probe)), i + 1), // found matching bucket
+            None => return ((None, Some(probe)), i + 1), // found empty bucket
+            Some(_) => continue,
+        }
+    }
+
+    ((None, None), capacity) // found nothing
+}
+
+impl PartialEq for Probing {
+    fn eq(&self, other: &Probing) -> bool {
+        match (self, other) {
+            (Probing::Linear, Probing::Linear) => true,
+            (Probing::Quadratic, Probing::Quadratic) => true,
+            (Probing::Triangular, Probing::Triangular) => true,
+            (Probing::DoubleHashing, Probing::DoubleHashing) => true,
+            (Probing::Custom(f), Probing::Custom(g)) => *f as usize == *g as usize,
+            (Probing::CustomDouble(f), Probing::CustomDouble(g)) => *f as usize == *g as usize,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for Probing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Probing::Linear => write!(f, "Linear"),
+            Probing::Quadratic => write!(f, "Quadratic"),
+            Probing::Triangular => write!(f, "Triangular"),
+            Probing::DoubleHashing => write!(f, "DoubleHashing"),
+            Probing::Custom(_) => write!(f, "Custom(..)"),
+            Probing::CustomDouble(_) => write!(f, "CustomDouble(..)"),
+        }
+    }
+}
+
+/// Storage strategies of an [`Index`] hash table.
+///
+/// All backends share the same public API, the choice only affects
+/// performance and the meaningful range of [`Parameters`].
+///
+/// [`Index`]: struct.Index.html
+/// [`Parameters`]: struct.Parameters.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Backend {
+    /// Open addressing, resolving collisions with the [`Probing`] strategy
+    /// of the `Parameters`. Requires a maximum load below `1.0`.
+    ///
+    /// [`Probing`]: enum.Probing.html
+    OpenAddressing,
+
+    /// Separate chaining, every bucket holding a linked chain of entries.
+    /// Accepts maximum loads above `1.0`, the `probe` parameter is ignored.
+    SeparateChaining,
+
+    /// Cuckoo hashing, every key living in one of two pairs of buckets chosen
+    /// by two hash functions, so that a lookup reads at most four buckets.
+    /// Requires a maximum load below `1.0`, the `probe` parameter is ignored.
+    Cuckoo,
+
+    /// Hopscotch hashing, every key living in a bucket close to its home bucket,
+    /// which keeps a bitmap of the buckets holding its keys. Remains efficient with
+    /// maximum loads around `0.9`, the `probe` parameter is ignored.
+    Hopscotch,
+}
+
+/// Difference on one key between two versions of a table, as yielded by
+/// [`Index::diff`] and [`PersistentIndex::diff`]. `K` and `V` are references
+/// to the entries.
+///
+/// [`Index::diff`]: struct.Index.html#method.diff
+/// [`PersistentIndex::diff`]: persistent/struct.PersistentIndex.html#method.diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change<K, V> {
+    /// The key is only in the new version.
+    Added(K, V),
+
+    /// The key is only in the old version.
+    Removed(K, V),
+
+    /// The key has the old value in the old version and the new value in the new one.
+    Changed(K, V, V),
+}
+
+/// Backend specific state of an `Index`, next to its entries table.
+#[derive(Debug, Clone)]
+enum Storage<A: RawStorage> {
+    /// The first `len` buckets of the table hold the entries, found by comparing keys.
+    Small,
+
+    /// The table holds the buckets.
+    Open,
+
+    /// The table is a slab of entries linked in chains.
+    Chained(Chains<A>),
+
+    /// The table holds the buckets, placed by the seeded hash functions.
+    Cuckoo(Cuckoo),
+
+    /// The table holds the buckets, flagged in the bitmaps of their home buckets.
+    Hopscotch(Hops<A>),
+}
+
+impl<A: RawStorage> Storage<A> {
+    /// Creates the state of `backend` for a table of `capacity` buckets, allocated from `storage`.
+    fn new(backend: Backend, capacity: usize, storage: &A) -> Storage<A> {
+        match backend {
+            Backend::OpenAddressing => Storage::Open,
+            Backend::SeparateChaining => Storage::Chained(Chains::new(capacity, storage)),
+            Backend::Cuckoo => Storage::Cuckoo(Cuckoo::new()),
+            Backend::Hopscotch => Storage::Hopscotch(Hops::new(capacity, storage)),
+        }
+    }
+
+    /// Resets the state for a table of `table_len` buckets which are (or are about to be) all empty.
+    fn reset(&mut self, table_len: usize) {
+        match self {
+            Storage::Small | Storage::Open | Storage::Cuckoo(_) => {}
+            Storage::Chained(chains) => chains.reset(table_len),
+            Storage::Hopscotch(hops) => hops.reset(),
+        }
+    }
+}
+
 /// Parameters needed in the configuration
 /// of an [`Index`] hash table.
 /// 
@@ -33,13 +375,16 @@ type Find<'a, K, V> = (Option<&'a RefCell<(K, V)>>, Option<usize>);
 /// 
 /// ```
 /// use std::collections::hash_map::RandomState;
-/// use index::{Index, Parameters};
+/// use index::{Backend, Index, Parameters, Probing};
 /// 
 /// let params = Parameters {
 ///     max_load: 0.7,
 ///     growth_policy: 2.0,
 ///     hasher_builder: RandomState::new(),
-///     probe: |hash, i| (hash as f64 + (i as f64 / 2.0) + ((i*i) as f64 / 2.0)) as usize,
+///     probe: Probing::Custom(|hash, i| (hash as f64 + (i as f64 / 2.0) + ((i*i) as f64 / 2.0)) as usize),
+///     backend: Backend::OpenAddressing,
+///     small_capacity: 8,
+///     filter: None,
 /// };
 /// 
 /// let mut index = Index::with_capacity_and_parameters(10, params);
@@ -49,6 +394,7 @@ type Find<'a, K, V> = (Option<&'a RefCell<(K, V)>>, Option<usize>);
 /// 
 /// [`Index`]: struct.Index.html
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Parameters<S> {
     /// Maximum load factor accepted before the table is resized. Default is `0.7`.
     pub max_load: f64,
@@ -60,10 +406,76 @@ pub struct Parameters<S> {
     /// 
     /// [`IndexHasherBuilder`]: hash/struct.IndexHasherBuilder.html
     /// [`BuildHasher`]: https://doc.rust-lang.org/std/hash/trait.BuildHasher.html
+    ///
+    /// The hasher builder is not serialized, it is rebuilt with `Default` when deserializing.
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub hasher_builder: S,
 
-    /// Open addressing probing policy. Default is quadratic probing: `hash + i + i*i`
-    pub probe: fn(hash: usize, i: usize) -> usize,
+    /// Open addressing probing policy (see [`Probing`]). Default is quadratic probing: `hash + i + i*i`
+    /// 
+    /// [`Probing`]: enum.Probing.html
+    pub probe: Probing,
+
+    /// Storage strategy (see [`Backend`]). Default is open addressing.
+    /// 
+    /// [`Backend`]: enum.Backend.html
+    pub backend: Backend,
+
+    /// Capacity up to which the entries are stored in the first buckets of the
+    /// table and found by comparing keys, without hashing. A smaller requested
+    /// capacity allocates all these buckets up front, and the table switches to the
+    /// layout of its backend when it grows past them. Default is `8`, `0` always
+    /// uses the backend, and at most `64` is accepted since lookups scan every entry.
+    #[cfg_attr(feature = "serde", serde(default = "default_small_capacity"))]
+    pub small_capacity: usize,
+
+    /// Membership filter answering the lookups of absent keys before any probing
+    /// (see [`Filter`]). Default is `None`.
+    ///
+    /// [`Filter`]: filter/enum.Filter.html
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub filter: Option<Filter>,
+}
+
+#[cfg(feature = "serde")]
+fn default_small_capacity() -> usize {
+    DEFAULT_SMALL_CAPACITY
+}
+
+impl<S: Default> Default for Parameters<S> {
+    fn default() -> Self {
+        Parameters {
+            max_load: DEFAULT_MAX_LOAD,
+            growth_policy: DEFAULT_GROWTH_POLICY,
+            hasher_builder: S::default(),
+            probe: DEFAULT_PROBING,
+            backend: DEFAULT_BACKEND,
+            small_capacity: DEFAULT_SMALL_CAPACITY,
+            filter: None,
+        }
+    }
+}
+
+impl<S> Parameters<S> {
+    /// Panics if the maximum load is not a positive finite number, if the growth
+    /// policy is not a finite number greater than `1`, if the small capacity is
+    /// above `64`, or if the false positive rate of the filter is not in `(0, 1)`.
+    pub(crate) fn check(&self) {
+        assert!(self.max_load > 0.0 && self.max_load.is_finite(), "invalid maximum load: {}", self.max_load);
+        assert!(self.growth_policy > 1.0 && self.growth_policy.is_finite(), "invalid growth policy: {}", self.growth_policy);
+        assert!(self.small_capacity <= MAX_SMALL_CAPACITY, "invalid small capacity: {}", self.small_capacity);
+        if let Some(filter) = self.filter {
+            let rate = filter.false_positive_rate();
+            assert!(rate > 0.0 && rate < 1.0, "invalid false positive rate: {}", rate);
+        }
+    }
+
+    /// Returns the capacity of a table of `capacity` buckets after growing
+    /// according to the growth policy, at least one more bucket (small
+    /// capacities would otherwise round down to themselves).
+    pub(crate) fn grown_capacity(&self, capacity: usize) -> usize {
+        ((capacity as f64 * self.growth_policy) as usize).max(capacity + 1)
+    }
 }
 
 
@@ -71,7 +483,10 @@ pub struct Parameters<S> {
 /// 
 /// The collisions are resolved through open adressing with
 /// quadratic probing (although it is possible to use linear probing or other types
-/// when specifying parameters).
+/// when specifying parameters). Other storage strategies can be selected
+/// through the [`Backend`] parameter, and the operations can be instrumented
+/// with an [`IndexObserver`]. The buckets are allocated through a
+/// [`RawStorage`], from the global allocator by default.
 /// 
 /// # Example
 /// 
@@ -81,7 +496,7 @@ pub struct Parameters<S> {
 /// let mut index = Index::new();
 /// 
 /// assert_eq!(index.len(), 0);
-/// assert_eq!(index.capacity(), 1);
+/// assert_eq!(index.capacity(), 8);
 /// 
 /// index.insert("salutation", "Hello, world!");
 /// index.insert("ferris", "https://www.rustacean.net/more-crabby-things/dancing-ferris.gif");
@@ -93,12 +508,20 @@ pub struct Parameters<S> {
 /// assert_eq!(index.len(), 4);
 /// assert_eq!(index.capacity(), 8);
 /// ```
+/// 
+/// [`Backend`]: enum.Backend.html
+/// [`IndexObserver`]: observer/trait.IndexObserver.html
+/// [`RawStorage`]: raw/trait.RawStorage.html
 #[derive(Clone)]
-pub struct Index<K, V, S = IndexHasherBuilder> {
+pub struct Index<K, V, S = IndexHasherBuilder, O = NoObserver, A: RawStorage = GlobalStorage> {
     params: Parameters<S>,
     capacity: usize,
     len: usize,
-    table: Vec<Bucket<K, V>>,
+    table: RawTable<Bucket<K, V>, A>,
+    storage: Storage<A>,
+    filter: Option<Membership<A>>,
+    counters: Counters,
+    observer: O,
 }
 
 impl<K, V> Index<K, V, IndexHasherBuilder>
@@ -128,19 +551,11 @@ where
     /// let mut index: Index<String, Vec<i32>> = Index::with_capacity(1312);
     /// ```
     pub fn with_capacity(capacity: usize) -> Index<K, V, IndexHasherBuilder> {
-        Index::with_capacity_and_parameters(
-            capacity,
-            Parameters {
-                max_load: DEFAULT_MAX_LOAD,
-                growth_policy: DEFAULT_GROWTH_POLICY,
-                hasher_builder: IndexHasherBuilder {},
-                probe: DEFAULT_PROBING,
-            },
-        )
+        Index::with_capacity_and_parameters(capacity, Parameters::default())
     }
 }
 
-impl<K, V, S> Index<K, V, S> {
+impl<K, V, S, O, A: RawStorage> Index<K, V, S, O, A> {
 
     /// Returns the maximum load factor accepted before the table is resized.
     /// 
@@ -189,7 +604,7 @@ impl<K, V, S> Index<K, V, S> {
         &self.params.hasher_builder
     }
 
-    /// Returns the probing function pointer of the `Index`.
+    /// Returns the probing strategy of the `Index`.
     /// 
     /// # Example
     /// 
@@ -200,12 +615,98 @@ impl<K, V, S> Index<K, V, S> {
     /// 
     /// let p = |h: usize, i: usize| h + i + i*i; // default prober
     /// 
-    /// assert_eq!((index.probe())(45, 2), p(45, 2));
+    /// assert_eq!(index.probe().probe(45, 0, 2), p(45, 2));
     /// ```
-    pub fn probe(&self) -> fn(usize, usize) -> usize {
+    pub fn probe(&self) -> Probing {
         self.params.probe
     }
 
+    /// Returns the storage strategy of the `Index`.
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// use index::{Backend, Index};
+    /// 
+    /// let mut index: Index<String, Vec<i32>> = Index::new();
+    /// 
+    /// assert_eq!(index.backend(), Backend::OpenAddressing); // default backend
+    /// ```
+    pub fn backend(&self) -> Backend {
+        self.params.backend
+    }
+
+    /// Returns the membership filter attached to the `Index`, if any.
+    ///
+    /// See [`Filter`] for details.
+    ///
+    /// [`Filter`]: filter/enum.Filter.html
+    pub fn filter(&self) -> Option<Filter> {
+        self.params.filter
+    }
+
+    /// Returns the observer called back on the operations of the `Index`.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Returns the storage the buckets (and side tables) of the `Index` are allocated from.
+    ///
+    /// See [`RawStorage`] for details.
+    ///
+    /// [`RawStorage`]: raw/trait.RawStorage.html
+    pub fn raw_storage(&self) -> &A {
+        self.table.storage()
+    }
+
+    /// Sets the observer called back on the operations of the `Index`, keeping its entries.
+    ///
+    /// See [`IndexObserver`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::cell::Cell;
+    /// use index::Index;
+    /// use index::observer::IndexObserver;
+    ///
+    /// #[derive(Default)]
+    /// struct Lookups {
+    ///     hits: Cell<usize>,
+    ///     misses: Cell<usize>,
+    /// }
+    ///
+    /// impl IndexObserver for Lookups {
+    ///     fn on_lookup(&self, found: bool) {
+    ///         let counter = if found { &self.hits } else { &self.misses };
+    ///         counter.set(counter.get() + 1);
+    ///     }
+    /// }
+    ///
+    /// let mut index = Index::new().with_observer(Lookups::default());
+    ///
+    /// index.insert("key", "value");
+    /// index.get("key");
+    /// index.get("salutation");
+    ///
+    /// assert_eq!(index.observer().hits.get(), 1);
+    /// assert_eq!(index.observer().misses.get(), 1);
+    /// ```
+    ///
+    /// [`IndexObserver`]: observer/trait.IndexObserver.html
+    pub fn with_observer<P: IndexObserver>(self, observer: P) -> Index<K, V, S, P, A> {
+        Index {
+            params: self.params,
+            capacity: self.capacity,
+            len: self.len,
+            table: self.table,
+            storage: self.storage,
+            filter: self.filter,
+            counters: self.counters,
+            observer,
+        }
+    }
+
 
     /// Returns the capacity of the `Index`.
     /// 
@@ -214,10 +715,14 @@ impl<K, V, S> Index<K, V, S> {
     /// ```
     /// use index::Index;
     /// 
-    /// let mut index: Index<&str, &str> = Index::with_capacity(6);
+    /// let mut index: Index<&str, &str> = Index::with_capacity(10);
     /// 
     /// assert_eq!(index.len(), 0);
-    /// assert_eq!(index.capacity(), 6);
+    /// assert_eq!(index.capacity(), 10);
+    /// 
+    /// // small capacities allocate every bucket of the small mode
+    /// let small: Index<&str, &str> = Index::with_capacity(2);
+    /// assert_eq!(small.capacity(), 8);
     /// ```
     pub fn capacity(&self) -> usize {
         self.capacity
@@ -230,14 +735,14 @@ impl<K, V, S> Index<K, V, S> {
     /// ```
     /// use index::Index;
     /// 
-    /// let mut index: Index<&str, i32> = Index::with_capacity(6);
+    /// let mut index: Index<&str, i32> = Index::with_capacity(10);
     /// 
     /// index.insert("one", 1);
     /// index.insert("two", 2);
     /// index.insert("three", 3);
     /// 
     /// assert_eq!(index.len(), 3);
-    /// assert_eq!(index.capacity(), 6);
+    /// assert_eq!(index.capacity(), 10);
     /// ```
     pub fn len(&self) -> usize {
         self.len
@@ -265,13 +770,13 @@ impl<K, V, S> Index<K, V, S> {
     /// ```
     /// use index::Index;
     /// 
-    /// let mut index: Index<&str, i32> = Index::with_capacity(6);
+    /// let mut index: Index<&str, i32> = Index::with_capacity(12);
     /// 
     /// index.insert("one", 1);
     /// index.insert("two", 2);
     /// index.insert("three", 3);
     /// 
-    /// assert_eq!(index.load(), 0.5);
+    /// assert_eq!(index.load(), 0.25);
     /// ```
     pub fn load(&self) -> f64 {
         (self.len as f64) / (self.capacity as f64)
@@ -301,7 +806,12 @@ impl<K, V, S> Index<K, V, S> {
         for entry in self.table.iter_mut() {
             *entry = Bucket::None;
         }
+        self.storage.reset(self.table.len());
+        if let Some(filter) = &mut self.filter {
+            filter.clear();
+        }
         self.len = 0;
+        self.debug_validate_empty();
     }
 
     /// Returns an iterator over the keys of the `Index`. 
@@ -324,7 +834,7 @@ impl<K, V, S> Index<K, V, S> {
     /// 
     /// assert_eq!(index.len(), index.keys().count());
     /// ```
-    pub fn keys(&self) -> Keys<K, V> {
+    pub fn keys(&self) -> Keys<'_, K, V> {
         Keys::new(&self.table)
     }
 
@@ -348,7 +858,7 @@ impl<K, V, S> Index<K, V, S> {
     /// 
     /// assert_eq!(index.len(), index.values().count());
     /// ```
-    pub fn values(&self) -> Values<K, V> {
+    pub fn values(&self) -> Values<'_, K, V> {
         Values::new(&self.table)
     }
 
@@ -373,7 +883,7 @@ impl<K, V, S> Index<K, V, S> {
     /// assert_eq!(*index.get("ferris").unwrap(), "overwritten!");
     /// 
     /// ```
-    pub fn values_mut(&self) -> ValuesMut<K, V> {
+    pub fn values_mut(&self) -> ValuesMut<'_, K, V> {
         ValuesMut::new(&self.table)
     }
 
@@ -397,7 +907,7 @@ impl<K, V, S> Index<K, V, S> {
     /// 
     /// assert_eq!(index.len(), index.iter().count());
     /// ```
-    pub fn iter(&self) -> Iter<K, V> {
+    pub fn iter(&self) -> Iter<'_, K, V> {
         Iter::new(&self.table)
     }
 
@@ -421,7 +931,7 @@ impl<K, V, S> Index<K, V, S> {
     /// 
     /// assert_eq!(*index.get("ferris").unwrap(), "ferris");
     /// ```
-    pub fn iter_mut(&self) -> IterMut<K, V> {
+    pub fn iter_mut(&self) -> IterMut<'_, K, V> {
         IterMut::new(&self.table)
     }
 
@@ -444,8 +954,18 @@ impl<K, V, S> Index<K, V, S> {
     /// assert_eq!(v.len(), 3);
     /// assert!(v.contains(&("salutation", "Hello, world!")));
     /// ```
-    pub fn drain(&mut self) -> Drain<K, V> {
-        Drain::new(&mut self.table, &mut self.len)
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        self.drain_observed().0
+    }
+
+    /// Drains the `Index` while lending its observer, to report the resizes
+    /// of the table the entries are moved to.
+    fn drain_observed(&mut self) -> (Drain<'_, K, V>, &O) {
+        self.storage.reset(self.table.len()); // the drain always empties the whole table
+        if let Some(filter) = &mut self.filter {
+            filter.clear();
+        }
+        (Drain::new(&mut self.table, &mut self.len), &self.observer)
     }
 }
 
@@ -464,13 +984,16 @@ where
     /// 
     /// ```
     /// use std::collections::hash_map::RandomState;
-    /// use index::{Index, Parameters};
+    /// use index::{Backend, Index, Parameters, Probing};
     /// 
     /// let params = Parameters {
     ///     max_load: 0.7,
     ///     growth_policy: 2.0,
     ///     hasher_builder: RandomState::new(),
-    ///     probe: |hash, i| (hash as f64 + (i as f64 / 2.0) + ((i*i) as f64 / 2.0)) as usize,
+    ///     probe: Probing::Custom(|hash, i| (hash as f64 + (i as f64 / 2.0) + ((i*i) as f64 / 2.0)) as usize),
+    ///     backend: Backend::OpenAddressing,
+    ///     small_capacity: 8,
+    ///     filter: None,
     /// };
     /// 
     /// let mut index = Index::with_capacity_and_parameters(10, params);
@@ -478,25 +1001,85 @@ where
     /// index.insert("key", "value");
     /// ```
     /// 
+    /// # Panics
+    /// 
+    /// Panics if the maximum load is not a positive finite number, or if the
+    /// growth policy is not a finite number greater than `1`, since the table
+    /// would grow without bounds. Also panics if the small capacity is above `64`,
+    /// or if the false positive rate of the filter is not in `(0, 1)`.
+    /// 
     /// [`Parameters`]: struct.Parameters.html
     pub fn with_capacity_and_parameters(capacity: usize, params: Parameters<S>) -> Index<K, V, S> {
-        
+        Index::with_capacity_and_parameters_in(capacity, params, GlobalStorage)
+    }
+}
+
+impl<K, V, S, A> Index<K, V, S, NoObserver, A>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+    A: RawStorage,
+{
+    /// Creates an empty `Index` with specified capacity and parameters,
+    /// allocating its buckets from `storage`.
+    ///
+    /// See [`RawStorage`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::{Index, Parameters};
+    /// use index::hash::IndexHasherBuilder;
+    /// use index::raw::CountingStorage;
+    ///
+    /// let storage = CountingStorage::new();
+    /// let params: Parameters<IndexHasherBuilder> = Parameters::default();
+    /// let mut index = Index::with_capacity_and_parameters_in(10, params, storage.clone());
+    ///
+    /// index.insert("key", "value");
+    ///
+    /// assert_eq!(storage.bytes(), 10 * std::mem::size_of::<index::Bucket<&str, &str>>());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics on invalid parameters, like [`with_capacity_and_parameters`].
+    ///
+    /// [`RawStorage`]: raw/trait.RawStorage.html
+    /// [`with_capacity_and_parameters`]: #method.with_capacity_and_parameters
+    pub fn with_capacity_and_parameters_in(capacity: usize, params: Parameters<S>, storage: A) -> Index<K, V, S, NoObserver, A> {
+        params.check();
+
         let capacity = if capacity == 0 { DEFAULT_INITIAL_CAPACITY } else { capacity };
+        let small = capacity <= params.small_capacity;
+        let capacity = if small { params.small_capacity } else { capacity }; // the small buckets are allocated once
         
         let mut index = Index {
+            storage: if small { Storage::Small } else { Storage::new(params.backend, capacity, &storage) },
+            filter: match params.filter {
+                Some(filter) if !small => Some(Membership::new(filter, (capacity as f64 * params.max_load) as usize + 1, &storage)),
+                _ => None,
+            },
             params,
             capacity,
             len: 0,
-            table: Vec::with_capacity(capacity),
+            table: RawTable::with_capacity_in(capacity, storage),
+            counters: Counters::default(),
+            observer: NoObserver,
         };
 
-        Self::init_table(&mut index.table, index.capacity);
+        match index.storage {
+            Storage::Small | Storage::Open | Storage::Cuckoo(_) | Storage::Hopscotch(_) => {
+                Self::init_table(&mut index.table, index.capacity)
+            }
+            Storage::Chained(_) => {} // the slab grows with the entries
+        }
 
         index
     }
 
     /// Initializes inner table with empty buckets according to specified capacity.
-    fn init_table(table: &mut Vec<Bucket<K, V>>, capacity: usize) {
+    fn init_table(table: &mut RawTable<Bucket<K, V>, A>, capacity: usize) {
         for _ in 0..capacity {
             table.push(Bucket::None);
         }
@@ -505,53 +1088,89 @@ where
         assert_eq!(capacity, table.len());
         assert_eq!(capacity, table.capacity());
     }
+}
 
+impl<K, V, S, O, A> Index<K, V, S, O, A>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+    O: IndexObserver,
+    A: RawStorage,
+{
     // methods
 
     /// Resizes `Index` with new capacity by allocating a new `Index`
     /// and moving entries from the old one to the new one by using insert to
     /// rehash the entries (if the new capacity is to small, the insert operation will grow
-    /// the new `Index` automatically).
+    /// the new `Index` automatically, reporting that resize to the observer first).
     fn resize(&mut self, new_capacity: usize) {
-        let mut new_index = Self::with_capacity_and_parameters(
+        let (old_capacity, moved) = (self.capacity, self.len);
+
+        let mut new_index = Index::with_capacity_and_parameters_in(
             new_capacity,
             self.params.clone(),
+            self.table.storage().clone(),
         );
+        new_index.counters = self.counters.rebuilt(self.len);
 
-        for (key, value) in self.drain() {
-            new_index.insert(key, value);
+        let (drain, observer) = self.drain_observed();
+        let mut new_index = new_index.with_observer(Resizes(observer));
+        for (key, value) in drain {
+            new_index.insert_pair(key, value);
         }
 
-        *self = new_index;
+        let new_index = new_index.with_observer(NoObserver); // releasing the observer
+        self.replace_table(new_index);
+        self.observer.on_resize(old_capacity, self.capacity, moved);
+        self.debug_validate();
+    }
+
+    /// Takes the table of a rebuilt `Index`, keeping the observer.
+    fn replace_table(&mut self, index: Index<K, V, S, NoObserver, A>) {
+        self.capacity = index.capacity;
+        self.len = index.len;
+        self.table = index.table;
+        self.storage = index.storage;
+        self.filter = index.filter;
+        self.counters = index.counters;
+        self.fill_filter();
+    }
+
+    /// Inserts every key in the empty filter of the `Index`, replacing a full
+    /// filter with a larger one.
+    fn fill_filter(&mut self) {
+        let (table, hasher_builder) = (&self.table, &self.params.hasher_builder);
+
+        if let Some(filter) = &mut self.filter {
+            while Iter::new(table).any(|pair| filter.insert_hash(filter_hash(hasher_builder, &pair.0)).is_err()) {
+                *filter = filter.grown();
+            }
+        }
     }
 
     /// Grows `Index` according to growth policy.
     fn grow(&mut self) {
-        let new_cap = (self.capacity as f64 * self.params.growth_policy) as usize;
+        let new_cap = self.grown_capacity();
         self.resize(new_cap);
     }
 
+    /// Returns the capacity of the `Index` after growing according to growth policy.
+    fn grown_capacity(&self) -> usize {
+        self.params.grown_capacity(self.capacity)
+    }
+
     /// Searches for an entry according to specified hash and discriminating closure.
     /// 
     /// See alias definition of `Find<'a, K, V>` at the top of this file for more details.
-    fn find<F>(&self, hash: usize, f: F) -> Find<K, V>
+    fn find<F>(&self, hashes: (usize, usize), f: F) -> Find<'_, K, V>
     where
         F: Fn(Ref<(K, V)>) -> bool,
     {
-        for i in 0..self.capacity {
-            let probe = (self.params.probe)(hash, i) % self.capacity;
-
-            match &self.table[probe] {
-                Some(pair) if f(pair.borrow()) => return (Some(pair), Some(probe)), // found matching bucket
-                None => return (None, Some(probe)), // found empty bucket
-                Some(_) => continue,
-            }
-        }
-
-        (None, None) // found nothing
+        let (found, probes) = find_in(&self.table, self.params.probe, hashes, f);
+        self.observer.on_probe(probes);
+        found
     }
 
-
     /// Inserts key-value pair in the `Index`.
     /// 
     /// If it encounters an occupied bucket with the same key, it will replace the
@@ -579,7 +1198,7 @@ where
     /// assert_eq!(*index.get("key").unwrap(), "new value");
     /// 
     /// assert_eq!(index.len(), 1);
-    /// assert_eq!(index.capacity(), 2);
+    /// assert_eq!(index.capacity(), 8);
     /// 
     /// index.insert("salutation", "Hello, world!");
     /// index.insert("ferris", "https://www.rustacean.net/more-crabby-things/dancing-ferris.gif");
@@ -589,28 +1208,229 @@ where
     /// assert_eq!(index.capacity(), 8);
     /// ```
     pub fn insert(&mut self, key: K, value: V) -> Bucket<K, V> {
-        let hash = make_hash(&self.params.hasher_builder, &key) as usize;
+        let len = self.len;
+        let (i, old) = self.insert_pair(key, value);
+
+        // the filter may only exist after a resize, and already holds a replaced key
+        if let (Some(filter), None) = (&mut self.filter, &old) {
+            let hash = filter_hash(&self.params.hasher_builder, &self.table[i].as_ref().expect("inserted entry").borrow().0);
+            if filter.insert_hash(hash).is_err() {
+                *filter = filter.grown();
+                self.fill_filter();
+            }
+        }
+        self.observer.on_insert(old.is_some());
+        self.debug_validate_entry(i, len + old.is_none() as usize);
+        old
+    }
 
-        if self.load() >= self.params.max_load {
+    /// Inserts key-value pair with the backend of the `Index`, growing it first if needed,
+    /// and returns the position of the entry along with the replaced bucket.
+    ///
+    /// A small `Index` ignores the maximum load, a linear scan doesn't degrade as it
+    /// fills up, and only grows once every bucket is used.
+    fn insert_pair(&mut self, key: K, value: V) -> (usize, Bucket<K, V>) {
+        if !matches!(self.storage, Storage::Small) && self.load() >= self.params.max_load {
             self.grow();
         }
 
-        match self.find(hash, |p| key.eq(&p.0)) {
+        match self.storage {
+            Storage::Small => self.small_insert(key, value),
+            Storage::Open => self.open_insert(key, value),
+            Storage::Chained(_) => self.chained_insert(key, value),
+            Storage::Cuckoo(_) => self.cuckoo_insert(key, value),
+            Storage::Hopscotch(_) => self.hopscotch_insert(key, value),
+        }
+    }
+
+    /// Inserts key-value pair after the entries of a small `Index`.
+    fn small_insert(&mut self, key: K, value: V) -> (usize, Bucket<K, V>) {
+        if let Some(i) = self.small_find(&key) {
+            return (i, self.table[i].replace(RefCell::new((key, value))));
+        }
+
+        if self.len == self.capacity {
+            self.grow();
+            return self.insert_pair(key, value);
+        }
+
+        self.table[self.len] = Bucket::Some(RefCell::new((key, value)));
+        self.len += 1;
+        (self.len - 1, Bucket::None)
+    }
+
+    /// Returns the position of the entry matching the specified key in a small `Index`.
+    fn small_find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let position = self.small_position(key);
+
+        self.observer.on_probe(position.map_or(self.len, |i| i + 1));
+        position
+    }
+
+    /// Returns the position of the entry matching the specified key in a small `Index`,
+    /// without notifying the observer.
+    fn small_position<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.table[..self.len]
+            .iter()
+            .position(|bucket| matches!(bucket, Some(pair) if key.eq(pair.borrow().0.borrow())))
+    }
+
+    /// Inserts key-value pair in the buckets of an open addressing `Index`.
+    fn open_insert(&mut self, key: K, value: V) -> (usize, Bucket<K, V>) {
+        let hashes = probe_hashes(&self.params.hasher_builder, self.params.probe, &key);
+
+        match self.find(hashes, |p| key.eq(&p.0)) {
             (Some(_), Some(i)) => {
-                std::mem::replace(&mut self.table[i], Bucket::Some(RefCell::new((key, value))))
+                (i, self.table[i].replace(RefCell::new((key, value))))
             }
             (None, Some(i)) => {
+                if i != self.params.probe.probe(hashes.0, hashes.1, 0) % self.capacity {
+                    self.observer.on_collision();
+                }
                 self.table[i] = Bucket::Some(RefCell::new((key, value)));
                 self.len += 1;
-                Bucket::None
+                (i, Bucket::None)
             }
             _ => {
                 self.grow();
-                self.insert(key, value)
+                self.insert_pair(key, value)
+            }
+        }
+    }
+
+    /// Inserts key-value pair in the chains of a separate chaining `Index`.
+    fn chained_insert(&mut self, key: K, value: V) -> (usize, Bucket<K, V>) {
+        let hash = make_hash(&self.params.hasher_builder, &key) as usize;
+
+        let i = match &mut self.storage {
+            Storage::Chained(chains) => match chains.find(&self.table, hash, |p| key.eq(&p.0)) {
+                Some(i) => return (i, self.table[i].replace(RefCell::new((key, value)))),
+                None => {
+                    if chains.chain(hash).next().is_some() {
+                        self.observer.on_collision();
+                    }
+                    chains.push(&mut self.table, hash, (key, value))
+                }
+            },
+            _ => unreachable!("not a separate chaining index"),
+        };
+
+        self.len += 1;
+        (i, Bucket::None)
+    }
+
+    /// Inserts key-value pair in the neighborhood of its home bucket in a hopscotch hashing `Index`.
+    /// 
+    /// If the neighborhood has no room for the entry, the `Index` grows and retries.
+    fn hopscotch_insert(&mut self, key: K, value: V) -> (usize, Bucket<K, V>) {
+        let hash = make_hash(&self.params.hasher_builder, &key) as usize;
+
+        let rejected = match &mut self.storage {
+            Storage::Hopscotch(hops) => match hops.find(&self.table, hash, |p| key.eq(&p.0)) {
+                Some(i) => return (i, self.table[i].replace(RefCell::new((key, value)))),
+                None => {
+                    if hops.neighbors(hash).next().is_some() {
+                        self.observer.on_collision();
+                    }
+                    hops.insert(&mut self.table, hash, (key, value))
+                }
+            },
+            _ => unreachable!("not a hopscotch hashing index"),
+        };
+
+        match rejected {
+            Ok(i) => {
+                self.len += 1;
+                (i, Bucket::None)
+            }
+            Err((key, value)) => {
+                self.grow();
+                self.insert_pair(key, value)
             }
         }
     }
 
+    /// Returns the position in the table of the entry matching the specified key,
+    /// whatever the backend.
+    fn position<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (found, probes) = self.locate(key);
+
+        if let Some(probes) = probes {
+            self.observer.on_probe(probes);
+        }
+        self.observer.on_lookup(found.is_some());
+        found
+    }
+
+    /// Returns the position in the table of the entry matching the specified key, along with
+    /// the number of buckets read by a small or open addressing `Index` (`None` otherwise),
+    /// without notifying the observer.
+    fn locate<Q>(&self, key: &Q) -> (Option<usize>, Option<usize>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(filter) = &self.filter {
+            if !filter.contains_hash(filter_hash(self.hasher(), key)) {
+                return (None, None);
+            }
+        }
+
+        match &self.storage {
+            Storage::Small => {
+                let found = self.small_position(key);
+                (found, Some(found.map_or(self.len, |i| i + 1)))
+            }
+            Storage::Open => {
+                let hashes = probe_hashes(self.hasher(), self.params.probe, key);
+                match find_in(&self.table, self.params.probe, hashes, |p| key.eq(p.0.borrow())) {
+                    ((Some(_), i), probes) => (i, Some(probes)),
+                    (_, probes) => (None, Some(probes)),
+                }
+            }
+            Storage::Chained(chains) => {
+                let hash = make_hash(self.hasher(), key) as usize;
+                (chains.find(&self.table, hash, |p| key.eq(p.0.borrow())), None)
+            }
+            Storage::Cuckoo(_) => (self.cuckoo_find(key), None),
+            Storage::Hopscotch(hops) => {
+                let hash = make_hash(self.hasher(), key) as usize;
+                (hops.find(&self.table, hash, |p| key.eq(p.0.borrow())), None)
+            }
+        }
+    }
+
+    /// Returns the entry matching the specified key, whatever the backend.
+    fn lookup<Q>(&self, key: &Q) -> Option<&RefCell<(K, V)>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.position(key).and_then(|i| self.table[i].as_ref())
+    }
+
+    /// Returns the entry matching the specified key like `lookup`, without notifying
+    /// the observer, for the lookups the user did not ask for (diffs and merges).
+    pub(crate) fn lookup_unobserved<Q>(&self, key: &Q) -> Option<&RefCell<(K, V)>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.locate(key).0.and_then(|i| self.table[i].as_ref())
+    }
+
     // pub fn remove_entry<Q>(&mut self, key: &Q) -> Bucket<K, V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized
     /*
         Problem: removing entry can corrupt lookup integrity
@@ -641,14 +1461,12 @@ where
     /// 
     /// assert_eq!(*index.get("salutation").unwrap(), "Hello, world!");
     /// ```
-    pub fn get<Q>(&self, key: &Q) -> Option<Ref<V>>
+    pub fn get<Q>(&self, key: &Q) -> Option<Ref<'_, V>>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let hash = make_hash(self.hasher(), &key) as usize;
-        self.find(hash, |p| key.borrow().eq(p.0.borrow()))
-            .0
+        self.lookup(key)
             .map(|pair| Ref::map(pair.borrow(), |p| &p.1))
     }
 
@@ -670,14 +1488,12 @@ where
     /// 
     /// assert_eq!(*index.get("salutation").unwrap(), "Hello, rust!");
     /// ```
-    pub fn get_mut<Q>(&self, key: &Q) -> Option<RefMut<V>>
+    pub fn get_mut<Q>(&self, key: &Q) -> Option<RefMut<'_, V>>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let hash = make_hash(self.hasher(), &key) as usize;
-        self.find(hash, |p| key.eq(p.0.borrow()))
-            .0
+        self.lookup(key)
             .map(|pair| RefMut::map(pair.borrow_mut(), |p| &mut p.1))
     }
 
@@ -697,45 +1513,121 @@ where
     /// 
     /// assert_eq!(*index.get_pair("did you know ?").unwrap(), ("did you know ?", "Rust is kinda cool !"));
     /// ```
-    pub fn get_pair<Q>(&self, key: &Q) -> Option<Ref<(K, V)>>
+    pub fn get_pair<Q>(&self, key: &Q) -> Option<Ref<'_, (K, V)>>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let hash = make_hash(self.hasher(), &key) as usize;
-        self.find(hash, |p| key.eq(p.0.borrow()))
-            .0
+        self.lookup(key)
             .map(|pair| pair.borrow())
     }
+
+    /// Returns an iterator over the changes turning the `Index` into `other`:
+    /// the entries of the `Index` missing from `other` or with a different value,
+    /// then the entries of `other` missing from the `Index`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::{Change, Index};
+    ///
+    /// let mut old = Index::new();
+    /// old.insert("king", 1);
+    /// old.insert("fool", 2);
+    ///
+    /// let mut new = Index::new();
+    /// new.insert("king", 3);
+    /// new.insert("crown", 4);
+    ///
+    /// let changes: Vec<_> = old
+    ///     .diff(&new)
+    ///     .map(|change| match change {
+    ///         Change::Added(key, value) => Change::Added(*key, *value),
+    ///         Change::Removed(key, value) => Change::Removed(*key, *value),
+    ///         Change::Changed(key, old, new) => Change::Changed(*key, *old, *new),
+    ///     })
+    ///     .collect();
+    ///
+    /// assert_eq!(changes.len(), 3);
+    /// assert!(changes.contains(&Change::Changed("king", 1, 3)));
+    /// assert!(changes.contains(&Change::Removed("fool", 2)));
+    /// assert_eq!(changes[2], Change::Added("crown", 4));
+    /// ```
+    pub fn diff<'a>(&'a self, other: &'a Index<K, V, S, O, A>) -> Diff<'a, K, V, S, O, A>
+    where
+        V: PartialEq,
+    {
+        Diff::new(self, other)
+    }
+
+    /// Combines the `Index` with `other`, calling `f` with the key, the value of
+    /// the `Index` and the value of `other` for the keys present in both.
+    ///
+    /// The entries of the smaller table are moved into the larger one, whose
+    /// parameters the merged table keeps.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// // word positions in two files
+    /// let mut first = Index::new();
+    /// first.insert("king", vec![(0, 4)]);
+    /// first.insert("fool", vec![(0, 9)]);
+    ///
+    /// let mut second = Index::new();
+    /// second.insert("king", vec![(1, 2)]);
+    ///
+    /// let merged = first.merge_with(second, |_, mut a, b| {
+    ///     a.extend(b);
+    ///     a
+    /// });
+    ///
+    /// assert_eq!(*merged.get("king").unwrap(), [(0, 4), (1, 2)]);
+    /// assert_eq!(merged.len(), 2);
+    /// ```
+    pub fn merge_with<F>(self, other: Index<K, V, S, O, A>, mut f: F) -> Index<K, V, S, O, A>
+    where
+        F: FnMut(&K, V, V) -> V,
+    {
+        let (mut large, mut small, swapped) = if self.len >= other.len {
+            (self, other, false)
+        } else {
+            (other, self, true)
+        };
+
+        for (key, value) in small.drain() {
+            match large.locate(&key).0 {
+                Some(i) => {
+                    // the bucket is only empty while `f` runs, `large` is dropped if it panics
+                    let (key, old) = large.table[i].take().unwrap().into_inner();
+                    let value = if swapped { f(&key, value, old) } else { f(&key, old, value) };
+                    large.table[i] = Bucket::Some(RefCell::new((key, value)));
+                }
+                None => {
+                    large.insert(key, value);
+                }
+            }
+        }
+
+        large.debug_validate();
+        large
+    }
 }
 
-impl<K, V, S> fmt::Debug for Index<K, V, S>
+impl<K, V, S, O, A> fmt::Debug for Index<K, V, S, O, A>
 where
     K: fmt::Debug,
     V: fmt::Debug,
-    S: fmt::Debug,
+    A: RawStorage,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut s = format!(
-            "Index {{\n\tparams: {:?}\t\ncapacity: {:?}\n\tlen: {:?}\n\ttable:\n\t[",
-            self.params, self.capacity, self.len
-        );
-
-        for (i, entry) in self.table.iter().enumerate() {
-            s = format!(
-                "{}\n\t\t{} : {:?},",
-                s,
-                i,
-                if let Some(pair) = entry {
-                    Some(pair.borrow())
-                } else {
-                    None
-                }
-            );
+        let mut map = f.debug_map();
+        for pair in self.iter() {
+            map.entry(&pair.0, &pair.1);
         }
-        s = format!("{}\n\t]\n}}", s);
-
-        write!(f, "{}", s)
+        map.finish()
     }
 }
 
@@ -747,3 +1639,316 @@ where
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::format;
+    use std::fs::File;
+    use std::io::{BufRead, BufReader};
+    use std::string::String;
+    use std::vec::Vec;
+
+    /// Runs `f` with the parameters of every backend, at a maximum load suited to each.
+    pub(crate) fn each_backend<F: FnMut(Parameters<IndexHasherBuilder>)>(mut f: F) {
+        for &(backend, max_load) in &[
+            (Backend::OpenAddressing, 0.7),
+            (Backend::SeparateChaining, 2.0),
+            (Backend::Cuckoo, 0.7),
+            (Backend::Hopscotch, 0.9),
+        ] {
+            f(Parameters { max_load, backend, ..Parameters::default() });
+        }
+    }
+
+    /// Distinct lowercase words of `lear.txt`.
+    pub(crate) fn lear_vocabulary() -> Vec<String> {
+        let mut index: Index<String, ()> = Index::new();
+        let reader = BufReader::new(File::open("lear.txt").unwrap());
+        for line in reader.lines() {
+            for word in line.unwrap().split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+                index.insert(word.to_lowercase(), ());
+            }
+        }
+        index.drain().map(|(k, _)| k).collect()
+    }
+
+    /// Total number of buckets visited to find every key of the `Index`.
+    fn total_probe_length<V>(index: &Index<String, V>) -> usize {
+        index
+            .keys()
+            .map(|key| {
+                let (hash, hash2) = probe_hashes(index.hasher(), index.probe(), key.as_str());
+                (0..index.capacity)
+                    .position(|i| {
+                        let probe = index.probe().probe(hash, hash2, i) % index.capacity;
+                        matches!(&index.table[probe], Some(pair) if pair.borrow().0 == *key)
+                    })
+                    .unwrap()
+                    + 1
+            })
+            .sum()
+    }
+
+    #[test]
+    fn double_hashing_reduces_clustering() {
+        let words = lear_vocabulary();
+
+        // fixed capacities giving loads of about 0.8 and 0.9, no growth allowed
+        for &capacity in &[5000, 4500] {
+            let build = |probe| {
+                let params = Parameters { max_load: 1.0, probe, ..Parameters::default() };
+                let mut index = Index::with_capacity_and_parameters(capacity, params);
+                for word in words.iter() {
+                    index.insert(word.clone(), ());
+                }
+                assert_eq!(index.capacity(), capacity);
+                index
+            };
+
+            let quadratic = total_probe_length(&build(Probing::Quadratic));
+            let double = total_probe_length(&build(Probing::DoubleHashing));
+
+            assert!(double < quadratic, "capacity {}: {} >= {}", capacity, double, quadratic);
+        }
+    }
+
+    #[test]
+    fn second_hash_splits_home_buckets() {
+        let words = lear_vocabulary();
+        let capacity = 4096;
+        let probe = Probing::DoubleHashing;
+
+        // second probed bucket of the keys sharing the home bucket of another key
+        let mut homes: Index<usize, Vec<usize>> = Index::new();
+        for word in words.iter() {
+            let (hash, hash2) = probe_hashes(&IndexHasherBuilder, probe, word.as_str());
+            let home = probe.probe(hash, hash2, 0) % capacity;
+            let second = probe.probe(hash, hash2, 1) % capacity;
+
+            let res = homes.get_mut(&home);
+            match res {
+                Some(mut v) => v.push(second),
+                None => {
+                    drop(res);
+                    homes.insert(home, vec![second]);
+                }
+            }
+        }
+
+        let (mut shared, mut diverging) = (0, 0);
+        for seconds in homes.values().filter(|v| v.len() > 1) {
+            shared += 1;
+            if seconds.iter().any(|s| *s != seconds[0]) {
+                diverging += 1;
+            }
+        }
+
+        assert!(shared > 0);
+        assert_eq!(shared, diverging);
+    }
+
+    #[test]
+    fn separate_chaining() {
+        let words = lear_vocabulary();
+
+        let params: Parameters<IndexHasherBuilder> = Parameters { max_load: 2.0, backend: Backend::SeparateChaining, ..Parameters::default() };
+        let mut index = Index::with_capacity_and_parameters(1, params);
+        for (i, word) in words.iter().enumerate() {
+            assert!(index.insert(word.clone(), i).is_none());
+        }
+
+        assert_eq!(index.len(), words.len());
+        assert!(index.load() > 1.0);
+        assert_eq!(index.iter().count(), words.len());
+
+        for (i, word) in words.iter().enumerate() {
+            assert_eq!(*index.get(word.as_str()).unwrap(), i);
+            *index.get_mut(word.as_str()).unwrap() += 1;
+        }
+        assert_eq!(index.insert(words[0].clone(), 0).unwrap().into_inner().1, 1);
+        assert!(index.get("rustacean").is_none());
+
+        // partial drain empties the table, and the slab is reused afterwards
+        index.drain().take(10).count();
+        assert!(index.is_empty());
+        assert!(index.get(words[20].as_str()).is_none());
+
+        let slab = index.table.len();
+        for (i, word) in words.iter().take(100).enumerate() {
+            index.insert(word.clone(), i);
+        }
+        assert_eq!(index.len(), 100);
+        assert_eq!(index.table.len(), slab);
+        assert_eq!(*index.get(words[42].as_str()).unwrap(), 42);
+    }
+
+    #[test]
+    fn chaining_keys_sharing_low_bits() {
+        let params: Parameters<IndexHasherBuilder> = Parameters { backend: Backend::SeparateChaining, ..Parameters::default() };
+
+        // every byte of the keys is 0 or 128, so their raw hashes agree on the low bits
+        let keys: Vec<u32> = (0..16u32).map(|i| (0..4).map(|b| (i >> b & 1) << (8 * b + 7)).sum()).collect();
+        let mut index = Index::with_capacity_and_parameters(256, params);
+        for &key in &keys {
+            index.insert(key, key);
+        }
+
+        let stats = index.stats();
+        assert_eq!(stats.capacity, 256);
+        assert!(stats.longest_cluster <= 3, "{}", stats);
+        for key in &keys {
+            assert_eq!(*index.get(key).unwrap(), *key);
+        }
+    }
+
+    #[test]
+    fn debug_as_map() {
+        let mut index = Index::new();
+        assert_eq!(format!("{:?}", index), "{}");
+
+        index.insert("fool", 1);
+        assert_eq!(format!("{:?}", index), r#"{"fool": 1}"#);
+        assert_eq!(format!("{:#?}", index), "{\n    \"fool\": 1,\n}");
+
+        index.insert("crown", 7);
+        let debug = format!("{:?}", index);
+        assert!(debug == r#"{"fool": 1, "crown": 7}"# || debug == r#"{"crown": 7, "fool": 1}"#);
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        for &(max_load, growth_policy) in &[(0.0, 2.0), (-1.0, 2.0), (f64::NAN, 2.0), (0.7, 1.0), (0.7, f64::INFINITY)] {
+            let params: Parameters<IndexHasherBuilder> = Parameters { max_load, growth_policy, ..Parameters::default() };
+            let built = std::panic::catch_unwind(|| Index::<u16, u32>::with_capacity_and_parameters(1, params));
+            assert!(built.is_err());
+        }
+
+        for &(small_capacity, valid) in &[(MAX_SMALL_CAPACITY, true), (MAX_SMALL_CAPACITY + 1, false), (usize::MAX, false)] {
+            let params: Parameters<IndexHasherBuilder> = Parameters { small_capacity, ..Parameters::default() };
+            let built = std::panic::catch_unwind(|| Index::<u16, u32>::with_capacity_and_parameters(1, params));
+            assert_eq!(built.is_ok(), valid);
+        }
+    }
+
+    /// Hasher builder counting the hashers it builds.
+    #[derive(Clone, Default)]
+    struct CountingHasherBuilder(std::rc::Rc<core::cell::Cell<usize>>);
+
+    impl BuildHasher for CountingHasherBuilder {
+        type Hasher = IndexHasher;
+
+        fn build_hasher(&self) -> IndexHasher {
+            self.0.set(self.0.get() + 1);
+            IndexHasherBuilder.build_hasher()
+        }
+    }
+
+    #[test]
+    fn small_table_without_hashing() {
+        let words = lear_vocabulary();
+
+        for &backend in &[Backend::OpenAddressing, Backend::SeparateChaining, Backend::Cuckoo, Backend::Hopscotch] {
+            let hashes = CountingHasherBuilder::default();
+            let filter = Some(Filter::Bloom { false_positive_rate: 0.01 }); // only built past the small mode
+            let params = Parameters { backend, hasher_builder: hashes.clone(), filter, ..Parameters::default() };
+            let mut index: Index<String, usize, _> = Index::with_capacity_and_parameters(1, params);
+            assert_eq!(index.capacity(), DEFAULT_SMALL_CAPACITY);
+
+            // every small bucket is used, whatever the maximum load
+            let inserted = DEFAULT_SMALL_CAPACITY;
+            for (i, word) in words.iter().enumerate().take(inserted) {
+                index.insert(word.clone(), i);
+            }
+            assert!(matches!(index.storage, Storage::Small));
+            assert_eq!(index.capacity(), DEFAULT_SMALL_CAPACITY);
+            assert_eq!(index.len(), DEFAULT_SMALL_CAPACITY);
+            assert_eq!(index.stats().resizes, 0);
+            assert!(words[..inserted].iter().enumerate().all(|(i, word)| *index.get(word).unwrap() == i));
+            assert!(index.get(&words[inserted]).is_none());
+            assert_eq!(hashes.0.get(), 0);
+
+            for (i, word) in words.iter().enumerate().take(100).skip(inserted) {
+                index.insert(word.clone(), i);
+            }
+            assert!(!matches!(index.storage, Storage::Small));
+            assert!(hashes.0.get() > 0);
+            assert_eq!(index.backend(), backend);
+            assert!(words[..100].iter().enumerate().all(|(i, word)| *index.get(word).unwrap() == i));
+        }
+    }
+
+    #[test]
+    fn sparse_iteration() {
+        // the iterators skip empty buckets in a loop, not by recursing
+        let mut index: Index<u32, u32> = Index::with_capacity(300_000);
+        index.insert(7, 49);
+
+        assert_eq!(index.iter().count(), 1);
+        assert_eq!(index.iter_mut().count(), 1);
+        assert_eq!(index.drain().collect::<Vec<_>>(), vec![(7, 49)]);
+        assert_eq!(index.iter().count(), 0);
+    }
+
+    #[test]
+    fn diff_and_merge() {
+        let words = lear_vocabulary();
+        let (first, second) = (&words[..3000], &words[2000..]);
+
+        each_backend(|params| {
+            let build = |words: &[String], offset: usize| {
+                let mut index: Index<String, Vec<usize>> = Index::with_capacity_and_parameters(1, params.clone());
+                for (i, word) in words.iter().enumerate() {
+                    index.insert(word.clone(), vec![i + offset]);
+                }
+                index
+            };
+
+            // the shared words keep their positions, except every tenth
+            let old = build(first, 0);
+            let new = build(second, 2000);
+            for word in words[2000..3000].iter().step_by(10) {
+                new.get_mut(word).unwrap()[0] %= 10;
+            }
+
+            let (mut added, mut removed, mut changed) = (0, 0, 0);
+            for change in old.diff(&new) {
+                match change {
+                    Change::Added(key, value) => {
+                        assert!(old.get(&*key).is_none() && value[0] >= 3000);
+                        added += 1;
+                    }
+                    Change::Removed(key, value) => {
+                        assert!(new.get(&*key).is_none() && value[0] < 2000);
+                        removed += 1;
+                    }
+                    Change::Changed(_, old_value, new_value) => {
+                        assert_eq!(old_value[0] % 10, new_value[0]);
+                        changed += 1;
+                    }
+                }
+            }
+            assert_eq!((added, removed, changed), (words.len() - 3000, 2000, 100));
+            assert_eq!(new.diff(&new).count(), 0);
+
+            // merging either way keeps the values of the receiver first
+            let merged = build(second, 2000).merge_with(build(first, 0), |_, mut a, b| {
+                a.extend(b);
+                a
+            });
+            assert_eq!(merged.len(), words.len());
+            assert_eq!(merged.backend(), params.backend);
+            assert!(second[..1000].iter().all(|word| merged.get(word).unwrap().len() == 2));
+            assert_eq!(*merged.get(&words[2500]).unwrap(), [2500, 2500]);
+
+            let merged = build(&first[..10], 100).merge_with(build(first, 0), |_, mut a, b| {
+                a.extend(b);
+                a
+            });
+            assert_eq!(merged.len(), 3000);
+            assert_eq!(*merged.get(&words[3]).unwrap(), [103, 3]);
+            assert!(merged.validate_invariants().is_ok());
+        });
+    }
+}
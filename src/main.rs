@@ -1,7 +1,7 @@
 //! This main is temporary, and is just meant to test the Index
 //! The index lib will be used in a larger project.
 
-use index::*;
+use index::strings::{Interner, StrIndex, Symbol};
 
 use std::fs::File;
 use std::io::{BufRead, BufReader};
@@ -16,9 +16,11 @@ fn test_index() {
 
     let start = std::time::SystemTime::now();
 
-    let mut index: Index<String, Vec<(usize, String)>> = Index::new(); // to see if growing correctly
+    let mut index: StrIndex<Vec<(usize, Symbol)>> = StrIndex::new(); // to see if growing correctly
+    let mut files = Interner::new();
 
     let filename = "lear.txt";
+    let file_id = files.intern(filename);
     let file = File::open(filename)
         .unwrap_or_else(|_| panic!("Error while opening file: `{}`", filename));
     let reader = BufReader::new(file);
@@ -32,7 +34,7 @@ fn test_index() {
         for word in split {
             if !word.is_empty() {
                 let word = word.to_lowercase();
-                let location = (i + 1, filename.to_string());
+                let location = (i + 1, file_id);
 
                 let res = index.get_mut(&word);
                 match res {
@@ -41,7 +43,7 @@ fn test_index() {
                     }
                     None => {
                         drop(res);
-                        index.insert(word, vec![location]);
+                        index.insert(&word, vec![location]);
                     }
                 }
             }
@@ -57,7 +59,7 @@ fn test_index() {
     println!("QUERY: {:?}", query);
 
     if let Some(v) = index.get(query) {
-        println!("RESPONSE: the word {:?} appears {} times in \"lear.txt\"", query, v.len());
+        println!("RESPONSE: the word {:?} appears {} times in {:?}", query, v.len(), files.resolve(v[0].1));
     } else {
         println!("RESPONSE: the word {:?} doesn't appear in \"lear.txt\"", query);
     }
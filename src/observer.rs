@@ -0,0 +1,253 @@
+//! Module implementing instrumentation hooks of an [`Index`] hash table.
+//!
+//! An [`IndexObserver`] set on an `Index` (see [`Index::with_observer`]) is
+//! called back on insertions, lookups, probe sequences, collisions and resizes,
+//! to feed the behavior of the table into metrics. Every callback does nothing
+//! by default, and an `Index` without observer uses [`NoObserver`], the calls of
+//! which are optimized out.
+//!
+//! [`Index`]: ../struct.Index.html
+//! [`Index::with_observer`]: ../struct.Index.html#method.with_observer
+//! [`IndexObserver`]: trait.IndexObserver.html
+//! [`NoObserver`]: struct.NoObserver.html
+
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+
+/// Callbacks on the operations of an [`Index`].
+///
+/// The callbacks take `&self` since lookups only borrow the `Index`,
+/// observers count with `Cell`s or atomics.
+///
+/// # Example
+///
+/// ```
+/// use std::cell::Cell;
+/// use std::rc::Rc;
+/// use index::Index;
+/// use index::observer::IndexObserver;
+///
+/// #[derive(Default)]
+/// struct Resizes(Cell<usize>);
+///
+/// impl IndexObserver for Resizes {
+///     fn on_resize(&self, _old_cap: usize, _new_cap: usize, _moved: usize) {
+///         self.0.set(self.0.get() + 1);
+///     }
+/// }
+///
+/// let resizes = Rc::new(Resizes::default());
+/// let mut index = Index::new().with_observer(resizes.clone());
+///
+/// for i in 0..100 {
+///     index.insert(i, i);
+/// }
+///
+/// assert_eq!(resizes.0.get(), 5);
+/// ```
+///
+/// [`Index`]: ../struct.Index.html
+pub trait IndexObserver {
+    /// Called after an insertion, `replaced` telling if the key was already present.
+    fn on_insert(&self, _replaced: bool) {}
+
+    /// Called after a lookup (`get`, `get_mut` or `get_pair`).
+    fn on_lookup(&self, _found: bool) {}
+
+    /// Called after walking an open addressing probe sequence (or the
+    /// entries of a small table), with the number of buckets read.
+    fn on_probe(&self, _len: usize) {}
+
+    /// Called when the home bucket (or chain, pair or neighborhood)
+    /// of a newly inserted key already holds another key.
+    fn on_collision(&self) {}
+
+    /// Called after the table was rebuilt from `old_cap` to `new_cap` buckets,
+    /// moving `moved` entries. Rebuilds with new seeds keep the same capacity.
+    ///
+    /// When the new table has to be rebuilt again while the entries are moved
+    /// to it, that nested rebuild is reported first, and `new_cap` is the final
+    /// capacity: every rebuild counted by the stats of the `Index` is reported.
+    fn on_resize(&self, _old_cap: usize, _new_cap: usize, _moved: usize) {}
+}
+
+/// Observer of an `Index` without instrumentation, the default one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoObserver;
+
+impl IndexObserver for NoObserver {}
+
+macro_rules! impl_observer_pointer {
+    ($($pointer:ty),*) => {$(
+        impl<T: IndexObserver + ?Sized> IndexObserver for $pointer {
+            fn on_insert(&self, replaced: bool) {
+                (**self).on_insert(replaced)
+            }
+
+            fn on_lookup(&self, found: bool) {
+                (**self).on_lookup(found)
+            }
+
+            fn on_probe(&self, len: usize) {
+                (**self).on_probe(len)
+            }
+
+            fn on_collision(&self) {
+                (**self).on_collision()
+            }
+
+            fn on_resize(&self, old_cap: usize, new_cap: usize, moved: usize) {
+                (**self).on_resize(old_cap, new_cap, moved)
+            }
+        }
+    )*};
+}
+
+impl_observer_pointer!(&T, Box<T>, Rc<T>, Arc<T>);
+
+/// Observer of the table a rebuild moves the entries to, only reporting
+/// the nested rebuilds of that table to the observer of the `Index`.
+pub(crate) struct Resizes<'a>(pub(crate) &'a dyn IndexObserver);
+
+impl IndexObserver for Resizes<'_> {
+    fn on_resize(&self, old_cap: usize, new_cap: usize, moved: usize) {
+        self.0.on_resize(old_cap, new_cap, moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{each_backend, lear_vocabulary};
+    use crate::hash::IndexHasherBuilder;
+    use crate::{Backend, Index, Parameters, Probing};
+
+    use std::cell::Cell;
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct Counting {
+        inserts: Cell<usize>,
+        replaced: Cell<usize>,
+        lookups: Cell<usize>,
+        found: Cell<usize>,
+        probes: Cell<usize>,
+        probed: Cell<usize>,
+        collisions: Cell<usize>,
+        resizes: Cell<Vec<(usize, usize, usize)>>,
+    }
+
+    fn incr(counter: &Cell<usize>, n: usize) {
+        counter.set(counter.get() + n);
+    }
+
+    impl IndexObserver for Counting {
+        fn on_insert(&self, replaced: bool) {
+            incr(&self.inserts, 1);
+            incr(&self.replaced, replaced as usize);
+        }
+
+        fn on_lookup(&self, found: bool) {
+            incr(&self.lookups, 1);
+            incr(&self.found, found as usize);
+        }
+
+        fn on_probe(&self, len: usize) {
+            incr(&self.probes, 1);
+            incr(&self.probed, len);
+        }
+
+        fn on_collision(&self) {
+            incr(&self.collisions, 1);
+        }
+
+        fn on_resize(&self, old_cap: usize, new_cap: usize, moved: usize) {
+            let mut resizes = self.resizes.take();
+            resizes.push((old_cap, new_cap, moved));
+            self.resizes.set(resizes);
+        }
+    }
+
+    #[test]
+    fn every_backend() {
+        let words = lear_vocabulary();
+
+        each_backend(|params| {
+            let backend = params.backend;
+            let params = Parameters { small_capacity: 0, ..params };
+            let counting = Rc::new(Counting::default());
+            let mut index = Index::with_capacity_and_parameters(1, params).with_observer(counting.clone());
+
+            for (i, word) in words.iter().enumerate() {
+                index.insert(word.clone(), i);
+            }
+            index.insert(words[0].clone(), 0);
+            for word in words.iter().take(100) {
+                index.get(word.as_str());
+            }
+            index.get("rustacean");
+
+            assert_eq!(counting.inserts.get(), words.len() + 1);
+            assert_eq!(counting.replaced.get(), 1);
+            assert_eq!((counting.lookups.get(), counting.found.get()), (101, 100));
+            assert!(counting.collisions.get() > 0);
+
+            let resizes = counting.resizes.take();
+            assert_eq!(resizes.len(), index.stats().resizes);
+            assert_eq!(resizes.last().unwrap().1, index.capacity());
+            assert_eq!(resizes.iter().map(|r| r.2).sum::<usize>(), index.stats().rehashed);
+
+            if backend == Backend::OpenAddressing {
+                // probing on every insertion and lookup, the resizes excepted
+                assert_eq!(counting.probes.get(), words.len() + 1 + 101);
+                assert!(counting.probed.get() >= counting.probes.get());
+            } else {
+                assert_eq!(counting.probes.get(), 0);
+            }
+        });
+    }
+
+    #[test]
+    fn nested_resizes() {
+        // probing a single bucket, so that moving the entries to a larger table can fail again
+        let probe = Probing::Custom(|hash, _| hash);
+        let params: Parameters<IndexHasherBuilder> = Parameters { probe, growth_policy: 1.5, small_capacity: 0, ..Parameters::default() };
+        let counting = Rc::new(Counting::default());
+        let mut index = Index::with_capacity_and_parameters(1, params).with_observer(counting.clone());
+
+        for i in 0..40u32 {
+            index.insert(i, i);
+        }
+
+        let resizes = counting.resizes.take();
+        let stats = index.stats();
+        assert!(resizes.windows(2).any(|w| w[1].0 < w[0].0), "no nested resize in {:?}", resizes);
+        assert_eq!(resizes.len(), stats.resizes);
+        assert_eq!(resizes.iter().map(|r| r.2).sum::<usize>(), stats.rehashed);
+        assert_eq!(resizes.last().unwrap().1, index.capacity());
+        assert!(counting.probes.get() < 40 + stats.rehashed); // the moved entries are not reported
+    }
+
+    #[test]
+    fn diff_and_merge_unobserved() {
+        let words = lear_vocabulary();
+        let (old, new) = (Rc::new(Counting::default()), Rc::new(Counting::default()));
+
+        let mut old_index = Index::new().with_observer(old.clone());
+        let mut new_index = Index::new().with_observer(new.clone());
+        for (i, word) in words.iter().enumerate() {
+            old_index.insert(word.clone(), i);
+            new_index.insert(word.clone(), if i % 2 == 0 { i } else { 0 });
+        }
+        let probes = (old.probes.get(), new.probes.get());
+
+        assert_eq!(old_index.diff(&new_index).count(), words.len() / 2);
+        assert_eq!((old.lookups.get(), new.lookups.get()), (0, 0));
+        assert_eq!((old.probes.get(), new.probes.get()), probes);
+
+        let merged = old_index.merge_with(new_index, |_, a, b| a.max(b));
+        assert_eq!(merged.len(), words.len());
+        assert_eq!((old.lookups.get(), new.lookups.get()), (0, 0));
+    }
+}
@@ -0,0 +1,389 @@
+//! Module implementing [`PerfectIndex`], a read-only hash table built over
+//! a static key set with a minimal perfect hash function.
+//!
+//! The hash function follows the CHD algorithm (compress, hash and displace):
+//! the keys are split in buckets of about `LAMBDA` keys by a first hash, and
+//! every bucket is assigned a pair of displacements `(d0, d1)`, so that the
+//! slot `(f1 + d0 * f2 + d1) % n` of its keys, where `f1` and `f2` are two
+//! more hashes of the key, is free. The buckets are displaced by decreasing
+//! size, while most slots are still free.
+//!
+//! The `n` entries fill exactly `n` slots, and a lookup hashes the key once,
+//! reads the displacements of its bucket and compares the single entry of its
+//! slot, so that unknown keys are rejected.
+//!
+//! [`PerfectIndex`]: struct.PerfectIndex.html
+
+use super::codec::{Decode, DecodeError, Encode};
+use super::hash::*;
+use super::Index;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::convert::TryFrom;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::iter::FromIterator;
+
+/// Average number of keys in a bucket of displacements.
+const LAMBDA: usize = 4;
+
+/// Number of seeds tried before giving up on a key set.
+const MAX_SEEDS: u64 = 64;
+
+const SEED_STEP: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Displacements `(d0, d1)` of a bucket.
+type Displacement = (u32, u32);
+
+/// Read-only hash table over a static key set, without empty slots
+/// and answering every lookup with a single probe.
+///
+/// # Example
+///
+/// ```
+/// use index::Index;
+/// use index::perfect::PerfectIndex;
+///
+/// let mut vocabulary = Index::new();
+///
+/// for (line, word) in ["the", "king", "the", "fool"].iter().enumerate() {
+///     vocabulary.insert(word.to_string(), line);
+/// }
+///
+/// let perfect = PerfectIndex::build(vocabulary.drain());
+///
+/// assert_eq!(perfect.len(), 3);
+/// assert_eq!(perfect.get("the"), Some(&2));
+/// assert_eq!(perfect.get("crown"), None);
+/// ```
+#[derive(Clone)]
+pub struct PerfectIndex<K, V, S = IndexHasherBuilder> {
+    hasher_builder: S,
+    seed: u64,
+    displacements: Vec<Displacement>, // by bucket
+    entries: Vec<(K, V)>, // by slot
+}
+
+/// Splits the hash of a key into its bucket and its `f1` and `f2` hashes.
+fn split(hash: u64, buckets: usize, len: usize) -> (usize, u64, u64) {
+    let (h1, h2) = (mix(hash), mix(hash ^ SEED_STEP));
+    ((h1 % buckets as u64) as usize, (h1 >> 32) % len as u64, h2 % len as u64)
+}
+
+/// Returns the slot of a key with hashes `f1` and `f2`, displaced by `(d0, d1)`.
+fn slot((f1, f2): (u64, u64), (d0, d1): Displacement, len: usize) -> usize {
+    ((f1 + d0 as u64 * f2 + d1 as u64) % len as u64) as usize
+}
+
+/// Assigns displacements to the buckets, so that the keys of hashes `hashes`
+/// fill every slot, or returns `None` if a bucket could not be displaced.
+fn displace(hashes: &[u64], buckets: usize) -> Option<(Vec<Displacement>, Vec<usize>)> {
+    let len = hashes.len();
+
+    let mut keys_of: Vec<Vec<(usize, (u64, u64))>> = (0..buckets).map(|_| Vec::new()).collect();
+    for (key, &hash) in hashes.iter().enumerate() {
+        let (bucket, f1, f2) = split(hash, buckets, len);
+        keys_of[bucket].push((key, (f1, f2)));
+    }
+
+    let mut order: Vec<usize> = (0..buckets).collect();
+    order.sort_by_key(|&bucket| core::cmp::Reverse(keys_of[bucket].len()));
+
+    let mut displacements = vec![(0, 0); buckets];
+    let mut slots = vec![usize::MAX; len]; // key of every slot
+    let mut candidate = Vec::with_capacity(LAMBDA);
+
+    for bucket in order {
+        let keys = &keys_of[bucket];
+        if keys.is_empty() {
+            break; // only empty buckets left
+        }
+        if (1..keys.len()).any(|i| keys[..i].iter().any(|key| key.1 == keys[i].1)) {
+            return None; // keys never separated by displacements
+        }
+
+        let found = (0..len as u32).flat_map(|d0| (0..len as u32).map(move |d1| (d0, d1))).find(|&d| {
+            candidate.clear();
+            for key in keys {
+                let s = slot(key.1, d, len);
+                if slots[s] != usize::MAX || candidate.contains(&s) {
+                    return false;
+                }
+                candidate.push(s);
+            }
+            true
+        })?;
+
+        for (key, &s) in keys.iter().zip(&candidate) {
+            slots[s] = key.0;
+        }
+        displacements[bucket] = found;
+    }
+
+    Some((displacements, slots))
+}
+
+impl<K, V> PerfectIndex<K, V, IndexHasherBuilder>
+where
+    K: Hash + Eq,
+{
+    /// Builds a `PerfectIndex` over the entries of `iter` with the default hasher
+    /// builder. For duplicate keys, the last value is kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than `u32::MAX` entries.
+    pub fn build<I: IntoIterator<Item = (K, V)>>(iter: I) -> PerfectIndex<K, V, IndexHasherBuilder> {
+        PerfectIndex::build_with_hasher(iter, IndexHasherBuilder)
+    }
+}
+
+impl<K, V, S> PerfectIndex<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Builds a `PerfectIndex` over the entries of `iter` with the specified hasher
+    /// builder, hashing the keys with seeds until one leads to a perfect hash
+    /// function. For duplicate keys, the last value is kept.
+    ///
+    /// The hasher builder must hash the same way in every process for the
+    /// encoded `PerfectIndex` to be decoded elsewhere.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than `u32::MAX` entries, or if no seed leads
+    /// to a perfect hash function (with a hasher ignoring its input).
+    pub fn build_with_hasher<I: IntoIterator<Item = (K, V)>>(iter: I, hasher_builder: S) -> PerfectIndex<K, V, S> {
+        let mut unique: Index<K, V> = Index::new();
+        for (key, value) in iter {
+            unique.insert(key, value);
+        }
+        let entries: Vec<(K, V)> = unique.drain().collect();
+
+        assert!(u32::try_from(entries.len()).is_ok(), "too many entries for a PerfectIndex");
+        if entries.is_empty() {
+            return PerfectIndex { hasher_builder, seed: 0, displacements: Vec::new(), entries };
+        }
+
+        let buckets = entries.len().div_ceil(LAMBDA);
+
+        for seed in (0..MAX_SEEDS).map(|i| i.wrapping_mul(SEED_STEP)) {
+            let hashes: Vec<u64> = entries.iter().map(|(key, _)| make_seeded_hash(&hasher_builder, seed, key)).collect();
+
+            if let Some((displacements, slots)) = displace(&hashes, buckets) {
+                let mut entries: Vec<Option<(K, V)>> = entries.into_iter().map(Some).collect();
+                let entries = slots.iter().map(|&key| entries[key].take().unwrap()).collect();
+
+                return PerfectIndex { hasher_builder, seed, displacements, entries };
+            }
+        }
+
+        panic!("no perfect hash function found for the keys of the PerfectIndex");
+    }
+
+    /// Returns the slot of the specified key, the only one which may hold it.
+    fn slot<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + ?Sized,
+    {
+        let len = self.entries.len();
+        let (bucket, f1, f2) = split(make_seeded_hash(&self.hasher_builder, self.seed, key), self.displacements.len(), len);
+        slot((f1, f2), self.displacements[bucket], len)
+    }
+
+    /// Returns a reference to the value associated with the specified key
+    /// if the lookup found a match, else it returns `None`.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        let (k, v) = &self.entries[self.slot(key)];
+        if key.eq(k.borrow()) {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the `PerfectIndex` contains the specified key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+}
+
+impl<K, V, S> PerfectIndex<K, V, S> {
+    /// Returns the number of elements in the `PerfectIndex`, which is also its number of slots.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the `PerfectIndex` contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns a reference to the hasher builder used in the `PerfectIndex`.
+    pub fn hasher(&self) -> &S {
+        &self.hasher_builder
+    }
+
+    /// Returns an iterator over the entries of the `PerfectIndex`, in the order of their slots.
+    pub fn iter(&self) -> core::slice::Iter<'_, (K, V)> {
+        self.entries.iter()
+    }
+
+    /// Returns an iterator over the keys of the `PerfectIndex`.
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator over the values of the `PerfectIndex`.
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.entries.iter().map(|(_, value)| value)
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for PerfectIndex<K, V, IndexHasherBuilder>
+where
+    K: Hash + Eq,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        PerfectIndex::build(iter)
+    }
+}
+
+impl<K, V, S> fmt::Debug for PerfectIndex<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.entries.iter().map(|(k, v)| (k, v))).finish()
+    }
+}
+
+/// Writes the seed, the displacements of the buckets and the entries by slot.
+///
+/// # Example
+///
+/// ```
+/// use index::codec;
+/// use index::perfect::PerfectIndex;
+///
+/// let perfect: PerfectIndex<String, u32> = vec![(String::from("fool"), 1), (String::from("crown"), 7)]
+///     .into_iter()
+///     .collect();
+///
+/// let bytes = codec::to_bytes(&perfect);
+/// let decoded: PerfectIndex<String, u32> = codec::from_bytes(&bytes).unwrap();
+///
+/// assert_eq!(decoded.get("crown"), Some(&7));
+/// ```
+impl<K, V, S> Encode for PerfectIndex<K, V, S>
+where
+    K: Encode,
+    V: Encode,
+{
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.seed.encode(buf);
+        self.displacements.encode(buf);
+        self.entries.encode(buf);
+    }
+}
+
+/// Reads a `PerfectIndex` back with the default hasher builder of `S`,
+/// checking that every key lives in its slot.
+impl<K, V, S> Decode for PerfectIndex<K, V, S>
+where
+    K: Hash + Eq + Decode,
+    V: Decode,
+    S: BuildHasher + Default,
+{
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let index = PerfectIndex {
+            hasher_builder: S::default(),
+            seed: u64::decode(input)?,
+            displacements: Decode::decode(input)?,
+            entries: Decode::decode(input)?,
+        };
+
+        if index.entries.is_empty() {
+            return Ok(index);
+        }
+        if index.displacements.is_empty() || u32::try_from(index.entries.len()).is_err() {
+            return Err(DecodeError::Invalid("perfect hash displacements"));
+        }
+        if index.entries.iter().enumerate().any(|(i, (key, _))| index.slot(key) != i) {
+            return Err(DecodeError::Invalid("perfect hash slot"));
+        }
+
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{from_bytes, to_bytes};
+    use crate::tests::lear_vocabulary;
+
+    use std::string::{String, ToString};
+
+    #[test]
+    fn lear_vocabulary_single_probe() {
+        let words = lear_vocabulary();
+        let perfect: PerfectIndex<String, usize> = words.iter().cloned().zip(0..).collect();
+
+        assert_eq!(perfect.len(), words.len());
+        assert!(words.iter().enumerate().all(|(i, word)| perfect.get(word) == Some(&i)));
+
+        // the slot read by `get` is the one holding the key, every key having its own slot
+        assert!(words.iter().all(|word| perfect.entries[perfect.slot(word.as_str())].0 == *word));
+
+        // unknown keys land in the slot of another key, and are rejected by comparing it
+        assert!(words.iter().all(|word| !perfect.contains_key(&word.to_uppercase())));
+        assert!(!perfect.contains_key(""));
+
+        let decoded: PerfectIndex<String, usize> = from_bytes(&to_bytes(&perfect)).unwrap();
+        assert!(words.iter().enumerate().all(|(i, word)| decoded.get(word) == Some(&i)));
+    }
+
+    #[test]
+    fn small_and_duplicate_key_sets() {
+        let empty: PerfectIndex<u32, u32> = PerfectIndex::build(None);
+        assert!(empty.is_empty());
+        assert_eq!(empty.get(&1), None);
+        assert!(from_bytes::<PerfectIndex<u32, u32>>(&to_bytes(&empty)).unwrap().is_empty());
+
+        let single = PerfectIndex::build(Some(("lear", 1)));
+        assert_eq!(single.get("lear"), Some(&1));
+        assert_eq!(single.get("fool"), None);
+
+        let duplicates = PerfectIndex::build((0..100u32).map(|i| (i % 10, i)));
+        assert_eq!(duplicates.len(), 10);
+        assert!((0..10).all(|i| duplicates.get(&i) == Some(&(i + 90))));
+    }
+
+    #[test]
+    fn reject_misplaced_entries() {
+        let perfect: PerfectIndex<String, u32> = (0..50u32).map(|i| (i.to_string(), i)).collect();
+
+        let mut swapped = perfect.clone();
+        swapped.entries.swap(0, 1);
+
+        assert_eq!(from_bytes::<PerfectIndex<String, u32>>(&to_bytes(&swapped)).unwrap_err(), DecodeError::Invalid("perfect hash slot"));
+    }
+}
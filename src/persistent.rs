@@ -0,0 +1,646 @@
+//! Module implementing [`PersistentIndex`], an immutable hash map whose
+//! versions share their structure.
+//!
+//! The `PersistentIndex` is a hash array mapped trie: every level of the
+//! trie consumes 5 bits of the hash of the keys (from [`make_hash`]), and
+//! branches only store their present children, flagged in a 32 bits bitmap.
+//! Inserting or removing a key copies the O(log32 n) branches on its path
+//! and shares every other node, behind `Arc`s, with the previous version.
+//! Keys with the same 64 bits hash are kept together in a collision node.
+//!
+//! A branch left with a single entry by a removal is replaced by the entry,
+//! so that the shape of a trie only depends on its keys.
+//!
+//! [`PersistentIndex`]: struct.PersistentIndex.html
+//! [`make_hash`]: ../hash/fn.make_hash.html
+
+use super::hash::*;
+use super::Change;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::iter::FromIterator;
+use core::mem;
+use core::slice;
+
+/// Number of hash bits consumed by a level of the trie.
+const BITS: u32 = 5;
+
+const MASK: u64 = (1 << BITS) - 1;
+
+/// Node shared between the versions of a trie.
+type Child<K, V> = Arc<Node<K, V>>;
+
+/// Node of the trie.
+enum Node<K, V> {
+    /// Children flagged in the bitmap, in the order of their bits.
+    Branch(u32, Vec<Child<K, V>>),
+
+    /// Entry, with the hash of its key.
+    Leaf(u64, K, V),
+
+    /// Leaves whose keys have the same hash.
+    Collision(u64, Vec<Child<K, V>>),
+}
+
+impl<K, V> Node<K, V> {
+    fn hash(&self) -> u64 {
+        match self {
+            Node::Leaf(hash, ..) | Node::Collision(hash, _) => *hash,
+            Node::Branch(..) => unreachable!("branches have no hash"),
+        }
+    }
+
+    fn key(&self) -> &K {
+        match self {
+            Node::Leaf(_, key, _) => key,
+            _ => unreachable!("only leaves have a key"),
+        }
+    }
+}
+
+/// Bit of the child holding `hash` in a branch at depth `shift`.
+fn bit(hash: u64, shift: u32) -> u32 {
+    1 << ((hash >> shift) & MASK)
+}
+
+/// Position of the child flagged by `bit` in the children of a branch.
+fn position(bitmap: u32, bit: u32) -> usize {
+    (bitmap & (bit - 1)).count_ones() as usize
+}
+
+/// Returns the leaf of `key` in the subtree of `node`, at depth `shift`.
+fn lookup<'a, K, V, Q>(mut node: &'a Node<K, V>, mut shift: u32, hash: u64, key: &Q) -> Option<&'a Node<K, V>>
+where
+    K: Borrow<Q>,
+    Q: Eq + ?Sized,
+{
+    loop {
+        match node {
+            Node::Branch(bitmap, children) => {
+                let bit = bit(hash, shift);
+                if bitmap & bit == 0 {
+                    return None;
+                }
+                node = &children[position(*bitmap, bit)];
+                shift += BITS;
+            }
+            Node::Leaf(h, k, _) => return Some(node).filter(|_| *h == hash && k.borrow() == key),
+            Node::Collision(h, leaves) => {
+                return leaves.iter().map(|leaf| &**leaf).find(|leaf| *h == hash && leaf.key().borrow() == key);
+            }
+        }
+    }
+}
+
+/// Inserts `leaf` in the branch `node` at depth `shift`, returning the new
+/// branch and the replaced leaf, if any.
+fn insert<K: Eq, V>(node: &Node<K, V>, shift: u32, leaf: &Child<K, V>) -> (Node<K, V>, Option<Child<K, V>>) {
+    let (bitmap, children) = match node {
+        Node::Branch(bitmap, children) => (*bitmap, children),
+        _ => unreachable!("insertions start from a branch"),
+    };
+
+    let hash = leaf.hash();
+    let bit = bit(hash, shift);
+    let i = position(bitmap, bit);
+    let mut children = children.clone();
+
+    if bitmap & bit == 0 {
+        children.insert(i, leaf.clone());
+        return (Node::Branch(bitmap | bit, children), None);
+    }
+
+    let old = children[i].clone();
+    let replaced = match &*old {
+        Node::Branch(..) => {
+            let (child, replaced) = insert(&old, shift + BITS, leaf);
+            children[i] = Arc::new(child);
+            replaced
+        }
+        _ if old.hash() == hash => {
+            let (child, replaced) = insert_colliding(&old, leaf);
+            children[i] = child;
+            replaced
+        }
+        _ => {
+            children[i] = Arc::new(split(old, leaf.clone(), shift + BITS));
+            None
+        }
+    };
+
+    (Node::Branch(bitmap, children), replaced)
+}
+
+/// Inserts `leaf` next to the leaf or collision node `old` of the same hash,
+/// returning the new node and the replaced leaf, if any.
+fn insert_colliding<K: Eq, V>(old: &Child<K, V>, leaf: &Child<K, V>) -> (Child<K, V>, Option<Child<K, V>>) {
+    let mut leaves = match &**old {
+        Node::Collision(_, leaves) => leaves.clone(),
+        _ => vec![old.clone()],
+    };
+
+    let replaced = match leaves.iter().position(|other| other.key() == leaf.key()) {
+        Some(i) => Some(mem::replace(&mut leaves[i], leaf.clone())),
+        None => {
+            leaves.push(leaf.clone());
+            None
+        }
+    };
+
+    match leaves.len() {
+        1 => (leaves.pop().unwrap(), replaced),
+        _ => (Arc::new(Node::Collision(leaf.hash(), leaves)), replaced),
+    }
+}
+
+/// Returns the branch at depth `shift` holding the two nodes of different hashes.
+fn split<K, V>(a: Child<K, V>, b: Child<K, V>, shift: u32) -> Node<K, V> {
+    let (bit_a, bit_b) = (bit(a.hash(), shift), bit(b.hash(), shift));
+
+    if bit_a == bit_b {
+        Node::Branch(bit_a, vec![Arc::new(split(a, b, shift + BITS))])
+    } else if bit_a < bit_b {
+        Node::Branch(bit_a | bit_b, vec![a, b])
+    } else {
+        Node::Branch(bit_a | bit_b, vec![b, a])
+    }
+}
+
+/// Removes the leaf of `key` from the subtree of `node` at depth `shift`,
+/// returning the new subtree (`None` once empty), or `None` if the key is missing.
+fn remove<K, V, Q>(node: &Child<K, V>, shift: u32, hash: u64, key: &Q) -> Option<Option<Child<K, V>>>
+where
+    K: Borrow<Q>,
+    Q: Eq + ?Sized,
+{
+    match &**node {
+        Node::Leaf(h, k, _) => Some(None).filter(|_| *h == hash && k.borrow() == key),
+        Node::Collision(h, leaves) => {
+            let i = leaves.iter().position(|leaf| *h == hash && leaf.key().borrow() == key)?;
+            let mut leaves = leaves.clone();
+            leaves.remove(i);
+
+            match leaves.len() {
+                1 => Some(leaves.pop()),
+                _ => Some(Some(Arc::new(Node::Collision(*h, leaves)))),
+            }
+        }
+        Node::Branch(bitmap, children) => {
+            let bit = bit(hash, shift);
+            if bitmap & bit == 0 {
+                return None;
+            }
+
+            let i = position(*bitmap, bit);
+            let child = remove(&children[i], shift + BITS, hash, key)?;
+            let mut children = children.clone();
+            let bitmap = match child {
+                Some(child) => {
+                    children[i] = child;
+                    *bitmap
+                }
+                None => {
+                    children.remove(i);
+                    bitmap & !bit
+                }
+            };
+
+            // below the root, a branch left with a single leaf is replaced by it
+            match children.len() {
+                0 if shift > 0 => Some(None),
+                1 if shift > 0 && !matches!(*children[0], Node::Branch(..)) => Some(children.pop()),
+                _ => Some(Some(Arc::new(Node::Branch(bitmap, children)))),
+            }
+        }
+    }
+}
+
+/// Appends the changes from the subtree `old` to the subtree `new`, both at depth `shift`.
+fn diff<'a, K: Eq, V: PartialEq>(old: &'a Child<K, V>, new: &'a Child<K, V>, shift: u32, changes: &mut Vec<Change<&'a K, &'a V>>) {
+    if Arc::ptr_eq(old, new) {
+        return; // shared by both versions
+    }
+
+    if let (Node::Branch(old_bitmap, old_children), Node::Branch(new_bitmap, new_children)) = (&**old, &**new) {
+        let mut bits = old_bitmap | new_bitmap;
+        while bits != 0 {
+            let bit = bits & bits.wrapping_neg();
+            bits &= bits - 1;
+
+            let old_child = Some(bit).filter(|bit| old_bitmap & bit != 0).map(|bit| &old_children[position(*old_bitmap, bit)]);
+            let new_child = Some(bit).filter(|bit| new_bitmap & bit != 0).map(|bit| &new_children[position(*new_bitmap, bit)]);
+
+            match (old_child, new_child) {
+                (Some(old_child), Some(new_child)) => diff(old_child, new_child, shift + BITS, changes),
+                (Some(old_child), None) => changes.extend(Leaves::new(old_child).map(|(_, k, v)| Change::Removed(k, v))),
+                (None, Some(new_child)) => changes.extend(Leaves::new(new_child).map(|(_, k, v)| Change::Added(k, v))),
+                (None, None) => unreachable!(),
+            }
+        }
+        return;
+    }
+
+    // one side holds the leaves of a single hash, look them up on the other
+    for (hash, key, value) in Leaves::new(old) {
+        match lookup(new, shift, hash, key) {
+            None => changes.push(Change::Removed(key, value)),
+            Some(Node::Leaf(_, _, new_value)) if new_value != value => changes.push(Change::Changed(key, value, new_value)),
+            Some(_) => {}
+        }
+    }
+    for (hash, key, value) in Leaves::new(new) {
+        if lookup(old, shift, hash, key).is_none() {
+            changes.push(Change::Added(key, value));
+        }
+    }
+}
+
+/// Immutable hash map, whose `insert` and `remove` return a new version
+/// sharing all but O(log32 n) nodes with the previous one.
+///
+/// Cloning a `PersistentIndex` only clones an `Arc`, and versions can be
+/// compared with [`diff`], which skips the subtrees they share.
+///
+/// # Example
+///
+/// ```
+/// use index::Change;
+/// use index::persistent::PersistentIndex;
+///
+/// let v1: PersistentIndex<&str, u32> = vec![("king", 1), ("fool", 2)].into_iter().collect();
+/// let v2 = v1.insert("crown", 3).remove("fool");
+///
+/// assert_eq!(v1.get("fool"), Some(&2)); // unchanged
+/// assert_eq!(v2.get("crown"), Some(&3));
+/// assert_eq!(v2.len(), 2);
+///
+/// let mut changes = v1.diff(&v2);
+/// changes.sort_by_key(|change| format!("{:?}", change));
+/// assert_eq!(changes, vec![Change::Added(&"crown", &3), Change::Removed(&"fool", &2)]);
+/// ```
+///
+/// [`diff`]: #method.diff
+pub struct PersistentIndex<K, V, S = IndexHasherBuilder> {
+    hasher_builder: S,
+    root: Child<K, V>,
+    len: usize,
+}
+
+impl<K, V> PersistentIndex<K, V, IndexHasherBuilder>
+where
+    K: Hash + Eq,
+{
+    /// Creates an empty `PersistentIndex` with the default hasher builder.
+    pub fn new() -> PersistentIndex<K, V, IndexHasherBuilder> {
+        PersistentIndex::with_hasher(IndexHasherBuilder)
+    }
+}
+
+impl<K, V, S> PersistentIndex<K, V, S> {
+    /// Creates an empty `PersistentIndex` with the specified hasher builder.
+    pub fn with_hasher(hasher_builder: S) -> PersistentIndex<K, V, S> {
+        PersistentIndex { hasher_builder, root: Arc::new(Node::Branch(0, Vec::new())), len: 0 }
+    }
+
+    /// Returns the number of elements in the `PersistentIndex`.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the `PersistentIndex` contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the hasher builder used in the `PersistentIndex`.
+    pub fn hasher(&self) -> &S {
+        &self.hasher_builder
+    }
+
+    /// Returns `true` if both versions share their whole trie, as a clone
+    /// does. Equal versions built separately share nothing.
+    pub fn ptr_eq(&self, other: &PersistentIndex<K, V, S>) -> bool {
+        Arc::ptr_eq(&self.root, &other.root)
+    }
+
+    /// Returns an iterator over the entries of the `PersistentIndex`, in the
+    /// order of their hashes.
+    pub fn iter(&self) -> PersistentIter<'_, K, V> {
+        PersistentIter { leaves: Leaves::new(&self.root), len: self.len }
+    }
+}
+
+impl<K, V, S> PersistentIndex<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    /// Returns a reference to the value associated with the specified key
+    /// if the lookup found a match, else it returns `None`.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match lookup(&self.root, 0, make_hash(&self.hasher_builder, key), key) {
+            Some(Node::Leaf(_, _, value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the `PersistentIndex` contains the specified key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns a new version of the `PersistentIndex` with the key-value pair,
+    /// replacing the value of the key if it was present.
+    pub fn insert(&self, key: K, value: V) -> PersistentIndex<K, V, S> {
+        let leaf = Arc::new(Node::Leaf(make_hash(&self.hasher_builder, &key), key, value));
+        let (root, replaced) = insert(&self.root, 0, &leaf);
+
+        PersistentIndex {
+            hasher_builder: self.hasher_builder.clone(),
+            root: Arc::new(root),
+            len: self.len + replaced.is_none() as usize,
+        }
+    }
+
+    /// Returns a new version of the `PersistentIndex` without the specified key,
+    /// sharing the whole trie if the key is missing.
+    pub fn remove<Q>(&self, key: &Q) -> PersistentIndex<K, V, S>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match remove(&self.root, 0, make_hash(&self.hasher_builder, key), key) {
+            Some(root) => PersistentIndex {
+                hasher_builder: self.hasher_builder.clone(),
+                root: root.expect("the root is never removed"),
+                len: self.len - 1,
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// Returns the changes turning this version into `other`, in no particular
+    /// order. The subtrees shared by both versions are skipped, so comparing a
+    /// version with the next ones takes a time proportional to the changes.
+    ///
+    /// Both versions must use the same hasher.
+    pub fn diff<'a>(&'a self, other: &'a PersistentIndex<K, V, S>) -> Vec<Change<&'a K, &'a V>>
+    where
+        V: PartialEq,
+    {
+        let mut changes = Vec::new();
+        diff(&self.root, &other.root, 0, &mut changes);
+        changes
+    }
+}
+
+impl<K, V, S: Clone> Clone for PersistentIndex<K, V, S> {
+    fn clone(&self) -> Self {
+        PersistentIndex { hasher_builder: self.hasher_builder.clone(), root: self.root.clone(), len: self.len }
+    }
+}
+
+impl<K, V> Default for PersistentIndex<K, V, IndexHasherBuilder>
+where
+    K: Hash + Eq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for PersistentIndex<K, V, IndexHasherBuilder>
+where
+    K: Hash + Eq,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        iter.into_iter().fold(PersistentIndex::new(), |index, (key, value)| index.insert(key, value))
+    }
+}
+
+impl<K, V, S> fmt::Debug for PersistentIndex<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Depth first iterator over the leaves of a subtree, with their hashes.
+struct Leaves<'a, K, V> {
+    stack: Vec<slice::Iter<'a, Child<K, V>>>,
+}
+
+impl<'a, K, V> Leaves<'a, K, V> {
+    fn new(node: &'a Child<K, V>) -> Leaves<'a, K, V> {
+        Leaves { stack: vec![slice::from_ref(node).iter()] }
+    }
+}
+
+impl<'a, K, V> Iterator for Leaves<'a, K, V> {
+    type Item = (u64, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = match self.stack.last_mut()?.next() {
+                Some(node) => node,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+
+            match &**node {
+                Node::Leaf(hash, key, value) => return Some((*hash, key, value)),
+                Node::Branch(_, children) | Node::Collision(_, children) => self.stack.push(children.iter()),
+            }
+        }
+    }
+}
+
+/// Iterator over the entries of a [`PersistentIndex`].
+///
+/// [`PersistentIndex`]: struct.PersistentIndex.html
+pub struct PersistentIter<'a, K, V> {
+    leaves: Leaves<'a, K, V>,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for PersistentIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, key, value) = self.leaves.next()?;
+        self.len -= 1;
+        Some((key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for PersistentIter<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::lear_vocabulary;
+    use crate::Index;
+
+    use core::hash::Hasher;
+    use std::format;
+    use std::string::String;
+    use std::vec::Vec;
+
+    /// Hasher builder keeping 2 bits of the hash, so that keys collide.
+    #[derive(Clone, Default)]
+    struct TwoBits;
+
+    impl BuildHasher for TwoBits {
+        type Hasher = TwoBitsHasher;
+
+        fn build_hasher(&self) -> TwoBitsHasher {
+            TwoBitsHasher(IndexHasherBuilder.build_hasher())
+        }
+    }
+
+    struct TwoBitsHasher(IndexHasher);
+
+    impl Hasher for TwoBitsHasher {
+        fn finish(&self) -> u64 {
+            self.0.finish() & 0b11
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.0.write(bytes)
+        }
+    }
+
+    /// Number of nodes of a subtree.
+    fn nodes<K, V>(node: &Node<K, V>) -> usize {
+        match node {
+            Node::Leaf(..) => 1,
+            Node::Branch(_, children) | Node::Collision(_, children) => 1 + children.iter().map(|child| nodes(child)).sum::<usize>(),
+        }
+    }
+
+    fn sorted<K: fmt::Debug, V: fmt::Debug>(changes: Vec<Change<K, V>>) -> Vec<String> {
+        let mut changes: Vec<String> = changes.into_iter().map(|change| format!("{:?}", change)).collect();
+        changes.sort();
+        changes
+    }
+
+    #[test]
+    fn versions_agree_with_index() {
+        let words = lear_vocabulary();
+        let mut index: Index<String, usize> = Index::new();
+        let mut versions = vec![PersistentIndex::new()];
+
+        for (i, word) in words.iter().enumerate() {
+            index.insert(word.clone(), i);
+            let next = versions.last().unwrap().insert(word.clone(), i);
+            if i % 1000 == 0 {
+                versions.push(next);
+            } else {
+                *versions.last_mut().unwrap() = next;
+            }
+        }
+
+        let last = versions.last().unwrap();
+        assert_eq!(last.len(), index.len());
+        assert_eq!(last.iter().count(), index.len());
+        assert!(last.iter().all(|(word, i)| *index.get(word).unwrap() == *i));
+
+        // older versions hold the prefixes of the words
+        for version in &versions {
+            assert!(words.iter().enumerate().all(|(i, word)| version.contains_key(word) == (i < version.len())));
+        }
+
+        // removing every other word, then the rest
+        let odd = words.iter().step_by(2).fold(last.clone(), |version, word| version.remove(word));
+        assert_eq!(odd.len(), words.len() / 2);
+        assert!(words.iter().enumerate().all(|(i, word)| odd.contains_key(word) == (i % 2 == 1)));
+        assert_eq!(last.len(), words.len());
+
+        let empty = words.iter().fold(odd, |version, word| version.remove(word));
+        assert!(empty.is_empty());
+        assert_eq!(nodes(&empty.root), 1);
+        assert!(empty.remove(&words[0]).ptr_eq(&empty));
+    }
+
+    #[test]
+    fn canonical_shape() {
+        let words = lear_vocabulary();
+        let all: PersistentIndex<&str, ()> = words.iter().map(|word| (word.as_str(), ())).collect();
+        let half: PersistentIndex<&str, ()> = words.iter().step_by(2).map(|word| (word.as_str(), ())).collect();
+
+        let removed = words.iter().skip(1).step_by(2).fold(all.clone(), |version, word| version.remove(word.as_str()));
+        assert_eq!(nodes(&removed.root), nodes(&half.root));
+        assert!(all.insert(&words[0], ()).diff(&all).is_empty());
+    }
+
+    #[test]
+    fn colliding_hashes() {
+        let mut version = PersistentIndex::with_hasher(TwoBits);
+        for i in 0..200u32 {
+            version = version.insert(i, i);
+        }
+        assert!(matches!(&*version.root, Node::Branch(_, children) if children.len() == 4));
+        assert!((0..200).all(|i| version.get(&i) == Some(&i)));
+
+        let changed = version.insert(7, 70).remove(&8).insert(200, 200);
+        assert_eq!(changed.len(), 200);
+        assert_eq!(version.get(&7), Some(&7));
+        assert_eq!(
+            sorted(version.diff(&changed)),
+            ["Added(200, 200)", "Changed(7, 7, 70)", "Removed(8, 8)"]
+        );
+
+        let single = (0..200).filter(|&i| i != 3).fold(changed, |version, i| version.remove(&i)).remove(&200);
+        assert_eq!(single.iter().collect::<Vec<_>>(), [(&3, &3)]);
+        assert_eq!(nodes(&single.root), 2);
+    }
+
+    #[test]
+    fn diff_between_batches() {
+        let words = lear_vocabulary();
+        let base: PersistentIndex<String, usize> = words.iter().cloned().zip(0..).collect();
+
+        let next = base.insert(words[1].clone(), 0).insert(String::from("cordelia's"), 1).remove(&words[2]);
+        assert_eq!(
+            sorted(base.diff(&next)),
+            [
+                format!("Added({:?}, 1)", "cordelia's"),
+                format!("Changed({:?}, 1, 0)", words[1]),
+                format!("Removed({:?}, 2)", words[2]),
+            ]
+        );
+        assert_eq!(next.diff(&base).len(), 3);
+        assert!(base.diff(&base.clone()).is_empty());
+
+        // unrelated tries with the same entries
+        let rebuilt: PersistentIndex<String, usize> = words.iter().cloned().zip(0..).collect();
+        assert!(!rebuilt.ptr_eq(&base));
+        assert!(rebuilt.diff(&base).is_empty());
+
+        let empty = PersistentIndex::new();
+        assert_eq!(empty.diff(&base).len(), words.len());
+        assert!(base.diff(&empty).iter().all(|change| matches!(change, Change::Removed(..))));
+    }
+}
@@ -0,0 +1,449 @@
+//! Module implementing the allocation of the buckets
+//! of an [`Index`] hash table.
+//!
+//! The table of an `Index`, along with the chain links, neighborhood bitmaps
+//! and membership filter of its backend, is allocated through a [`RawStorage`],
+//! the last type parameter of the `Index`. [`GlobalStorage`] (the default) allocates
+//! from the global allocator, [`CountingStorage`] keeps count of the bytes
+//! used by a table, and custom implementations can allocate the tables from
+//! arenas or bump allocators (see [`Index::with_capacity_and_parameters_in`]).
+//!
+//! [`Index`]: ../struct.Index.html
+//! [`Index::with_capacity_and_parameters_in`]: ../struct.Index.html#method.with_capacity_and_parameters_in
+//! [`RawStorage`]: trait.RawStorage.html
+//! [`GlobalStorage`]: struct.GlobalStorage.html
+//! [`CountingStorage`]: struct.CountingStorage.html
+
+use alloc::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use alloc::sync::Arc;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Allocator of the buckets, and side tables, of an [`Index`].
+///
+/// The storage is cloned for every table allocated while resizing, so clones
+/// must allocate from (and account into) the same place.
+///
+/// # Safety
+///
+/// `allocate` must return memory fitting `layout`, valid until passed to
+/// `deallocate` by a clone of the storage. Layouts are never zero sized.
+///
+/// # Example
+///
+/// ```
+/// use std::alloc::Layout;
+/// use std::cell::Cell;
+/// use std::ptr::NonNull;
+/// use std::rc::Rc;
+/// use index::{Index, Parameters};
+/// use index::hash::IndexHasherBuilder;
+/// use index::raw::{GlobalStorage, RawStorage};
+///
+/// /// Refuses to allocate tables above a number of bytes.
+/// #[derive(Clone)]
+/// struct Limited {
+///     limit: usize,
+///     refused: Rc<Cell<bool>>,
+/// }
+///
+/// unsafe impl RawStorage for Limited {
+///     fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+///         if layout.size() > self.limit {
+///             self.refused.set(true);
+///             return None;
+///         }
+///         GlobalStorage.allocate(layout)
+///     }
+///
+///     unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+///         GlobalStorage.deallocate(ptr, layout)
+///     }
+/// }
+///
+/// let storage = Limited { limit: 1 << 20, refused: Rc::new(Cell::new(false)) };
+/// let params: Parameters<IndexHasherBuilder> = Parameters::default();
+/// let mut index = Index::with_capacity_and_parameters_in(16, params, storage.clone());
+///
+/// index.insert("key", "value");
+///
+/// assert!(!storage.refused.get());
+/// ```
+///
+/// [`Index`]: ../struct.Index.html
+pub unsafe trait RawStorage: Clone {
+    /// Allocates memory for `layout`, or returns `None` if it is exhausted.
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// Deallocates memory returned by `allocate` for the same `layout`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this storage (or a clone) for `layout`.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+}
+
+/// Storage allocating from the global allocator, used by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GlobalStorage;
+
+unsafe impl RawStorage for GlobalStorage {
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        NonNull::new(unsafe { alloc(layout) })
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        dealloc(ptr.as_ptr(), layout)
+    }
+}
+
+/// Storage counting the bytes allocated through it, delegating to another storage.
+///
+/// Clones share their counters, so the handle kept by the caller reports
+/// the memory of the tables of the `Index` (including its clones), side
+/// tables of the backend and membership filter included.
+///
+/// # Example
+///
+/// ```
+/// use index::{Index, Parameters};
+/// use index::hash::IndexHasherBuilder;
+/// use index::raw::CountingStorage;
+///
+/// let storage = CountingStorage::new();
+/// let params: Parameters<IndexHasherBuilder> = Parameters::default();
+/// let mut index = Index::with_capacity_and_parameters_in(4, params, storage.clone());
+///
+/// let empty = storage.bytes();
+/// assert!(empty > 0);
+///
+/// for i in 0..100 {
+///     index.insert(i, i);
+/// }
+///
+/// assert!(storage.bytes() > empty);
+/// assert!(storage.peak() > storage.bytes()); // old and new table while resizing
+///
+/// drop(index);
+///
+/// assert_eq!(storage.bytes(), 0);
+/// ```
+#[derive(Clone, Default)]
+pub struct CountingStorage<A = GlobalStorage> {
+    inner: A,
+    bytes: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+}
+
+impl CountingStorage {
+    /// Creates a counting storage allocating from the global allocator.
+    pub fn new() -> CountingStorage {
+        CountingStorage::wrap(GlobalStorage)
+    }
+}
+
+impl<A> CountingStorage<A> {
+    /// Creates a counting storage allocating from `inner`.
+    pub fn wrap(inner: A) -> CountingStorage<A> {
+        CountingStorage {
+            inner,
+            bytes: Arc::new(AtomicUsize::new(0)),
+            peak: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns the number of bytes currently allocated.
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the highest number of bytes allocated at once.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+impl<A> fmt::Debug for CountingStorage<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CountingStorage")
+            .field("bytes", &self.bytes())
+            .field("peak", &self.peak())
+            .finish()
+    }
+}
+
+unsafe impl<A: RawStorage> RawStorage for CountingStorage<A> {
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let ptr = self.inner.allocate(layout)?;
+        let bytes = self.bytes.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        self.peak.fetch_max(bytes, Ordering::Relaxed);
+        Some(ptr)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.bytes.fetch_sub(layout.size(), Ordering::Relaxed);
+        self.inner.deallocate(ptr, layout)
+    }
+}
+
+/// Growable array allocated through a `RawStorage`, holding the buckets of an `Index`.
+pub(crate) struct RawTable<T, A: RawStorage> {
+    ptr: NonNull<T>,
+    capacity: usize,
+    len: usize,
+    storage: A,
+    _marker: PhantomData<T>,
+}
+
+// the table owns its elements like a `Vec`
+unsafe impl<T: Send, A: RawStorage + Send> Send for RawTable<T, A> {}
+unsafe impl<T: Sync, A: RawStorage + Sync> Sync for RawTable<T, A> {}
+
+impl<T, A: RawStorage> RawTable<T, A> {
+    /// Creates an empty table with room for exactly `capacity` elements.
+    pub(crate) fn with_capacity_in(capacity: usize, storage: A) -> RawTable<T, A> {
+        let mut table = RawTable {
+            ptr: NonNull::dangling(),
+            capacity: 0,
+            len: 0,
+            storage,
+            _marker: PhantomData,
+        };
+        table.reallocate(capacity);
+        table
+    }
+
+    /// Creates a table of `len` clones of `value`, with room for exactly `len` elements.
+    pub(crate) fn from_elem_in(value: T, len: usize, storage: A) -> RawTable<T, A>
+    where
+        T: Clone,
+    {
+        let mut table = RawTable::with_capacity_in(len, storage);
+        for _ in 0..len {
+            table.push(value.clone());
+        }
+        table
+    }
+
+    /// Returns the storage the table is allocated from.
+    pub(crate) fn storage(&self) -> &A {
+        &self.storage
+    }
+
+    /// Returns the number of elements the table holds without reallocating.
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Appends an element, doubling the allocation when full.
+    pub(crate) fn push(&mut self, value: T) {
+        if self.len == self.capacity {
+            self.reallocate((self.capacity * 2).max(4));
+        }
+        unsafe { ptr::write(self.ptr.as_ptr().add(self.len), value) };
+        self.len += 1;
+    }
+
+    /// Removes the last element and returns it, or `None` if the table is empty.
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(unsafe { ptr::read(self.ptr.as_ptr().add(self.len)) })
+    }
+
+    /// Drops every element, keeping the allocation.
+    pub(crate) fn clear(&mut self) {
+        let elements = &mut **self as *mut [T];
+        self.len = 0; // leaking rather than double dropping if a destructor panics
+        unsafe { ptr::drop_in_place(elements) };
+    }
+
+    fn layout(capacity: usize) -> Layout {
+        Layout::array::<T>(capacity).expect("capacity overflow")
+    }
+
+    /// Moves the elements to a new allocation of `capacity` elements (at least `len`).
+    fn reallocate(&mut self, capacity: usize) {
+        let new_ptr = if capacity == 0 || mem::size_of::<T>() == 0 {
+            NonNull::dangling()
+        } else {
+            let layout = Self::layout(capacity);
+            match self.storage.allocate(layout) {
+                Some(ptr) => ptr.cast(),
+                None => handle_alloc_error(layout),
+            }
+        };
+
+        unsafe {
+            ptr::copy_nonoverlapping(self.ptr.as_ptr(), new_ptr.as_ptr(), self.len);
+            self.deallocate();
+        }
+        self.ptr = new_ptr;
+        self.capacity = capacity;
+    }
+
+    /// Frees the allocation, without dropping the elements.
+    unsafe fn deallocate(&mut self) {
+        if self.capacity != 0 && mem::size_of::<T>() != 0 {
+            self.storage.deallocate(self.ptr.cast(), Self::layout(self.capacity));
+        }
+    }
+}
+
+impl<T, A: RawStorage> Deref for RawTable<T, A> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T, A: RawStorage> DerefMut for RawTable<T, A> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: fmt::Debug, A: RawStorage> fmt::Debug for RawTable<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Clone, A: RawStorage> Clone for RawTable<T, A> {
+    fn clone(&self) -> Self {
+        let mut table = RawTable::with_capacity_in(self.capacity, self.storage.clone());
+        for value in self.iter() {
+            table.push(value.clone());
+        }
+        table
+    }
+}
+
+impl<T, A: RawStorage> Drop for RawTable<T, A> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(&mut **self as *mut [T]);
+            self.deallocate();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{Filter, Membership};
+    use crate::hash::IndexHasherBuilder;
+    use crate::tests::{each_backend, lear_vocabulary};
+    use crate::{Index, Parameters};
+
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::string::{String, ToString};
+    use std::vec;
+    use std::vec::Vec;
+
+    /// Bump allocator over a fixed buffer, never freeing.
+    #[derive(Clone)]
+    struct Bump {
+        buffer: Rc<RefCell<Vec<u64>>>,
+        used: Rc<Cell<usize>>,
+    }
+
+    unsafe impl RawStorage for Bump {
+        fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+            let mut buffer = self.buffer.borrow_mut();
+            let base = buffer.as_mut_ptr() as usize;
+            let start = (base + self.used.get() + layout.align() - 1) & !(layout.align() - 1);
+            let end = start + layout.size();
+            if end > base + buffer.len() * mem::size_of::<u64>() {
+                return None;
+            }
+            self.used.set(end - base);
+            NonNull::new(start as *mut u8)
+        }
+
+        unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {}
+    }
+
+    #[test]
+    fn counts_every_backend() {
+        let words = lear_vocabulary();
+
+        each_backend(|params| {
+            let backend = params.backend;
+            let storage = CountingStorage::new();
+            let mut index = Index::with_capacity_and_parameters_in(1, params, storage.clone());
+
+            for (i, word) in words.iter().enumerate() {
+                index.insert(word.clone(), i);
+            }
+            let bucket = mem::size_of::<crate::Bucket<String, usize>>();
+            assert!(storage.bytes() >= index.len() * bucket, "{:?}", backend);
+
+            let clone = index.clone();
+            assert!(storage.bytes() >= 2 * index.len() * bucket);
+
+            drop(index);
+            drop(clone);
+            assert_eq!(storage.bytes(), 0, "{:?}", backend);
+        });
+    }
+
+    #[test]
+    fn counts_side_tables_exactly() {
+        let bucket = mem::size_of::<crate::Bucket<u64, u64>>();
+        let word = mem::size_of::<usize>();
+
+        each_backend(|params| {
+            let backend = params.backend;
+            let side = match backend {
+                crate::Backend::SeparateChaining => 1000 * 2 * word, // heads and next links
+                crate::Backend::Hopscotch => 1000 * mem::size_of::<u32>(), // bitmaps
+                _ => 0,
+            };
+
+            for &filter in &[None, Some(Filter::Bloom { false_positive_rate: 0.01 }), Some(Filter::Cuckoo { false_positive_rate: 0.01 })] {
+                let storage = CountingStorage::new();
+                let params = Parameters { filter, ..params.clone() };
+                let mut index = Index::with_capacity_and_parameters_in(1000, params, storage.clone());
+                for i in 0..600u64 {
+                    index.insert(i, i);
+                }
+                assert_eq!(index.capacity(), 1000, "{:?}", backend);
+
+                let filter_bytes = match &index.filter {
+                    None => 0,
+                    Some(Membership::Bloom(bloom)) => bloom.bits() / 8,
+                    Some(Membership::Cuckoo(cuckoo)) => cuckoo.capacity() * mem::size_of::<u16>(),
+                };
+                assert_eq!(filter.is_some(), filter_bytes > 0);
+                assert_eq!(storage.bytes(), 1000 * bucket + side + filter_bytes, "{:?} {:?}", backend, filter);
+            }
+        });
+    }
+
+    #[test]
+    fn bump_arena() {
+        let bump = Bump {
+            buffer: Rc::new(RefCell::new(vec![0; 1 << 16])),
+            used: Rc::new(Cell::new(0)),
+        };
+        let params: Parameters<IndexHasherBuilder> = Parameters::default();
+        let mut index = Index::with_capacity_and_parameters_in(8, params, bump.clone());
+
+        for i in 0..1000u32 {
+            index.insert(i, i.to_string());
+        }
+
+        assert!(bump.used.get() > 0);
+        assert!((0..1000u32).all(|i| *index.get(&i).unwrap() == i.to_string()));
+    }
+}
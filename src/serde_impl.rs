@@ -0,0 +1,185 @@
+//! Module implementing `Serialize` and `Deserialize` for an [`Index`]
+//! hash table, available with the `serde` feature.
+//!
+//! The `Index` is represented as a map, like `std::collections::HashMap`,
+//! and only its entries are serialized. Deserialization uses default
+//! [`Parameters`] and pre-sizes the table from the length hint, capped
+//! since the input controls it.
+//!
+//! [`Index`]: ../struct.Index.html
+//! [`Parameters`]: ../struct.Parameters.html
+
+use super::raw::RawStorage;
+use super::{Index, Parameters};
+
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+
+impl<K, V, S, O, A> Serialize for Index<K, V, S, O, A>
+where
+    K: Serialize,
+    V: Serialize,
+    A: RawStorage,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for pair in self.iter() {
+            map.serialize_entry(&pair.0, &pair.1)?;
+        }
+        map.end()
+    }
+}
+
+/// Largest number of entries pre-allocated from a size hint, which the input controls.
+const MAX_PREALLOCATED_ENTRIES: usize = 4096;
+
+/// Returns the smallest capacity holding `len` entries without growing.
+fn capacity_for(len: usize, max_load: f64) -> usize {
+    ((len as f64 / max_load) as usize).saturating_add(1)
+}
+
+struct IndexVisitor<K, V, S> {
+    _marker: PhantomData<(K, V, S)>,
+}
+
+impl<'de, K, V, S> Visitor<'de> for IndexVisitor<K, V, S>
+where
+    K: Deserialize<'de> + Hash + Eq,
+    V: Deserialize<'de>,
+    S: BuildHasher + Clone + Default,
+{
+    type Value = Index<K, V, S>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let params = Parameters::default();
+        let hint = access.size_hint().unwrap_or(0).min(MAX_PREALLOCATED_ENTRIES);
+        let capacity = capacity_for(hint, params.max_load);
+        let mut index = Index::with_capacity_and_parameters(capacity, params);
+
+        while let Some((key, value)) = access.next_entry()? {
+            index.insert(key, value);
+        }
+
+        Ok(index)
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for Index<K, V, S>
+where
+    K: Deserialize<'de> + Hash + Eq,
+    V: Deserialize<'de>,
+    S: BuildHasher + Clone + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(IndexVisitor { _marker: PhantomData })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::filter::Filter;
+    use super::super::{Index, Parameters, Probing};
+    use super::{capacity_for, IndexVisitor, MAX_PREALLOCATED_ENTRIES};
+
+    use serde::de::value::Error;
+    use serde::de::{DeserializeSeed, MapAccess, Visitor};
+
+    use core::marker::PhantomData;
+
+    use std::string::{String, ToString};
+    use std::vec;
+    use std::vec::Vec;
+
+    #[test]
+    fn json_round_trip() {
+        let mut index: Index<String, Vec<usize>> = Index::new();
+        index.insert("fool".into(), vec![1, 4]);
+        index.insert("crown".into(), vec![7]);
+
+        let json = serde_json::to_string(&index).unwrap();
+        let back: Index<String, Vec<usize>> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(back.len(), 2);
+        assert_eq!(*back.get("fool").unwrap(), vec![1, 4]);
+        assert_eq!(*back.get("crown").unwrap(), vec![7]);
+    }
+
+    #[test]
+    fn presized_from_hint() {
+        let mut index: Index<String, u32> = Index::new();
+        for i in 0..100 {
+            index.insert(i.to_string(), i);
+        }
+        assert_eq!(index.capacity(), 256);
+
+        // a `Value` map provides its length as size hint
+        let back: Index<String, u32> = serde_json::from_value(serde_json::to_value(&index).unwrap()).unwrap();
+
+        assert_eq!(back.len(), 100);
+        assert_eq!(back.capacity(), 143);
+    }
+
+    /// A map claiming `usize::MAX` entries without providing any.
+    struct Lying;
+
+    impl<'de> MapAccess<'de> for Lying {
+        type Error = Error;
+
+        fn next_key_seed<T: DeserializeSeed<'de>>(&mut self, _: T) -> Result<Option<T::Value>, Self::Error> {
+            Ok(None)
+        }
+
+        fn next_value_seed<T: DeserializeSeed<'de>>(&mut self, _: T) -> Result<T::Value, Self::Error> {
+            unreachable!()
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            Some(usize::MAX)
+        }
+    }
+
+    #[test]
+    fn cautious_size_hint() {
+        assert_eq!(capacity_for(usize::MAX, 0.7), usize::MAX);
+
+        let visitor: IndexVisitor<String, u32, crate::hash::IndexHasherBuilder> = IndexVisitor { _marker: PhantomData };
+        let index = visitor.visit_map(Lying).unwrap();
+
+        assert!(index.is_empty());
+        assert_eq!(index.capacity(), capacity_for(MAX_PREALLOCATED_ENTRIES, 0.7));
+    }
+
+    #[test]
+    fn parameters() {
+        let params: Parameters<crate::hash::IndexHasherBuilder> = Parameters::default();
+        let json = serde_json::to_string(&params).unwrap();
+
+        assert_eq!(json, r#"{"max_load":0.7,"growth_policy":2.0,"probe":"quadratic","backend":"open_addressing","small_capacity":8,"filter":null}"#);
+
+        let back: Parameters<crate::hash::IndexHasherBuilder> = serde_json::from_str(&json).unwrap();
+        assert!(matches!(back.probe, Probing::Quadratic));
+
+        // parameters serialized before the small capacity and filter existed
+        let json = r#"{"max_load":0.5,"growth_policy":2.0,"probe":"linear","backend":"open_addressing"}"#;
+        let back: Parameters<crate::hash::IndexHasherBuilder> = serde_json::from_str(json).unwrap();
+        assert_eq!(back.small_capacity, 8);
+        assert_eq!(back.filter, None);
+
+        let filtered = Parameters { filter: Some(Filter::Cuckoo { false_positive_rate: 0.01 }), ..params.clone() };
+        let json = serde_json::to_string(&filtered).unwrap();
+        assert!(json.ends_with(r#""filter":{"cuckoo":{"false_positive_rate":0.01}}}"#));
+        let back: Parameters<crate::hash::IndexHasherBuilder> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.filter, filtered.filter);
+
+        let custom = Parameters { probe: Probing::Custom(|h, i| h + 3*i), ..params };
+        assert!(serde_json::to_string(&custom).is_err());
+    }
+}
@@ -0,0 +1,439 @@
+//! Module implementing table statistics and probe length
+//! diagnostics of an [`Index`] hash table.
+//!
+//! The probe length of a lookup is the number of entries (or buckets)
+//! it reads: the position in the probe sequence for open addressing,
+//! in the chain for separate chaining, among the buckets of the two pairs
+//! for cuckoo hashing and among the flagged buckets for hopscotch hashing.
+//!
+//! [`Index`]: ../struct.Index.html
+
+use super::hash::*;
+use super::observer::IndexObserver;
+use super::raw::RawStorage;
+use super::{probe_hashes, Index, Storage};
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+
+/// Counters of the work done by an `Index` over its lifetime, carried over when it resizes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Counters {
+    /// Number of rebuilds of the table.
+    pub(crate) resizes: usize,
+
+    /// Number of entries moved by the rebuilds.
+    pub(crate) rehashed: usize,
+}
+
+impl Counters {
+    /// Counters of a new table rebuilt from one with these counters and `len` entries.
+    pub(crate) fn rebuilt(&self, len: usize) -> Counters {
+        Counters {
+            resizes: self.resizes + 1,
+            rehashed: self.rehashed + len,
+        }
+    }
+}
+
+/// Snapshot of the layout and history of an [`Index`], returned by [`Index::stats`].
+///
+/// # Example
+///
+/// ```
+/// use index::Index;
+///
+/// let mut index = Index::new();
+///
+/// for i in 0..100 {
+///     index.insert(i, i * i);
+/// }
+///
+/// let stats = index.stats();
+///
+/// assert_eq!(stats.len, 100);
+/// assert_eq!(stats.probe_lengths.iter().sum::<usize>(), 100);
+/// assert!(stats.average_probe_length >= 1.0);
+/// assert_eq!(stats.resizes, 5); // from 8 to 256 buckets
+/// ```
+///
+/// [`Index`]: ../struct.Index.html
+/// [`Index::stats`]: ../struct.Index.html#method.stats
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStats {
+    /// Number of entries.
+    pub len: usize,
+
+    /// Number of buckets.
+    pub capacity: usize,
+
+    /// Load factor.
+    pub load: f64,
+
+    /// Histogram of the probe lengths of the present keys:
+    /// `probe_lengths[n]` is the number of keys found after `n` reads (`probe_lengths[0]` is always `0`).
+    pub probe_lengths: Vec<usize>,
+
+    /// Average probe length of the present keys (`0` when empty).
+    pub average_probe_length: f64,
+
+    /// Maximum probe length of the present keys.
+    pub max_probe_length: usize,
+
+    /// Number of keys that a lookup cannot find any more, because they were
+    /// changed through [`Index::iter_mut`]. They are left out of the probe lengths.
+    ///
+    /// [`Index::iter_mut`]: ../struct.Index.html#method.iter_mut
+    pub unreachable: usize,
+
+    /// Expected probe length of a missing key, averaged over every home bucket.
+    pub expected_miss_length: f64,
+
+    /// Size of the longest cluster: the longest run of consecutive occupied buckets,
+    /// or the longest chain for separate chaining.
+    pub longest_cluster: usize,
+
+    /// Number of times the table was rebuilt, when growing or reseeding.
+    pub resizes: usize,
+
+    /// Total number of entries moved by the rebuilds.
+    pub rehashed: usize,
+}
+
+impl fmt::Display for TableStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "entries: {} / {} buckets (load {:.3})", self.len, self.capacity, self.load)?;
+        if self.unreachable > 0 {
+            writeln!(f, "unreachable keys: {}", self.unreachable)?;
+        }
+        writeln!(
+            f,
+            "probe length: avg {:.3}, max {}, expected miss {:.3}",
+            self.average_probe_length, self.max_probe_length, self.expected_miss_length
+        )?;
+        writeln!(f, "longest cluster: {}", self.longest_cluster)?;
+        writeln!(f, "resizes: {} ({} entries rehashed)", self.resizes, self.rehashed)?;
+        for (n, count) in self.probe_lengths.iter().enumerate().skip(1) {
+            writeln!(f, "{:>4} | {}", n, count)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V, S, O, A> Index<K, V, S, O, A>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+    O: IndexObserver,
+    A: RawStorage,
+{
+    /// Walks the table and returns statistics on its layout and history,
+    /// to check how the hasher and probing behave on the data.
+    ///
+    /// See [`TableStats`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Index;
+    ///
+    /// let mut index = Index::with_capacity(16);
+    ///
+    /// index.insert("salutation", "Hello, world!");
+    /// index.insert("ferris", "https://www.rustacean.net/more-crabby-things/dancing-ferris.gif");
+    ///
+    /// let stats = index.stats();
+    ///
+    /// assert_eq!(stats.len, 2);
+    /// assert_eq!(stats.resizes, 0);
+    /// println!("{}", stats);
+    /// ```
+    ///
+    /// [`TableStats`]: stats/struct.TableStats.html
+    pub fn stats(&self) -> TableStats {
+        let mut probe_lengths = vec![0];
+        let mut unreachable = 0;
+
+        for pair in self.iter() {
+            let length = match self.probe_length(&pair.0) {
+                Some(length) => length,
+                None => {
+                    unreachable += 1;
+                    continue;
+                }
+            };
+            if probe_lengths.len() <= length {
+                probe_lengths.resize(length + 1, 0);
+            }
+            probe_lengths[length] += 1;
+        }
+
+        let total: usize = probe_lengths.iter().enumerate().map(|(n, count)| n * count).sum();
+        let reachable = self.len - unreachable;
+
+        TableStats {
+            len: self.len,
+            capacity: self.capacity,
+            load: self.load(),
+            average_probe_length: if reachable == 0 { 0.0 } else { total as f64 / reachable as f64 },
+            max_probe_length: probe_lengths.len() - 1,
+            probe_lengths,
+            unreachable,
+            expected_miss_length: self.expected_miss_length(),
+            longest_cluster: self.longest_cluster(),
+            resizes: self.counters.resizes,
+            rehashed: self.counters.rehashed,
+        }
+    }
+
+    /// Renders the occupancy of the table as a compact ASCII map, to spot clusters.
+    ///
+    /// Every bucket is one character, 64 per line after the position of the first one:
+    /// `.` for an empty bucket, otherwise the probe length of the entry, like in
+    /// [`TableStats::probe_lengths`]: the number of reads finding it, `1` in its home
+    /// bucket. Lengths are drawn as `1`-`9` then `a`-`z`, and `#` beyond `35`.
+    /// An entry whose key can no longer be found (see [`TableStats::unreachable`]) is a `!`.
+    /// With separate chaining the buckets are the entries of the slab.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::{Index, Parameters, Probing, Backend};
+    ///
+    /// let params = Parameters {
+    ///     max_load: 0.9,
+    ///     growth_policy: 2.0,
+    ///     hasher_builder: index::hash::IndexHasherBuilder,
+    ///     probe: Probing::Custom(|_, i| i), // every key collides
+    ///     backend: Backend::OpenAddressing,
+    ///     small_capacity: 0,
+    ///     filter: None,
+    /// };
+    ///
+    /// let mut index = Index::with_capacity_and_parameters(8, params);
+    ///
+    /// for key in 0..4 {
+    ///     index.insert(key, ());
+    /// }
+    ///
+    /// assert_eq!(index.layout(), "   0 | 1234....\n");
+    /// ```
+    ///
+    /// [`TableStats::probe_lengths`]: stats/struct.TableStats.html#structfield.probe_lengths
+    /// [`TableStats::unreachable`]: stats/struct.TableStats.html#structfield.unreachable
+    pub fn layout(&self) -> String {
+        const ROW: usize = 64;
+        const DIGITS: &[u8] = b"123456789abcdefghijklmnopqrstuvwxyz"; // from one read
+
+        let mut layout = String::with_capacity(self.table.len() + self.table.len() / ROW * 8 + 8);
+
+        for (row, buckets) in self.table.chunks(ROW).enumerate() {
+            layout.push_str(&format!("{:>4} | ", row * ROW));
+            for bucket in buckets {
+                layout.push(match bucket {
+                    None => '.',
+                    Some(pair) => match self.probe_length(&pair.borrow().0) {
+                        Some(length) => DIGITS.get(length - 1).map_or('#', |&c| c as char),
+                        None => '!',
+                    },
+                });
+            }
+            layout.push('\n');
+        }
+
+        layout
+    }
+
+    /// Number of reads needed to find a present key, or `None` when the key
+    /// is not where its hash leads, after a change through `iter_mut`.
+    fn probe_length(&self, key: &K) -> Option<usize> {
+        let matching = |i: &usize| matches!(&self.table[*i], Some(pair) if pair.borrow().0 == *key);
+
+        let position = match &self.storage {
+            Storage::Small => (0..self.len).position(|i| matching(&i)),
+            Storage::Open => {
+                let probe = self.params.probe;
+                let (hash, hash2) = probe_hashes(self.hasher(), probe, key);
+                (0..self.capacity)
+                    .map(|i| probe.probe(hash, hash2, i) % self.capacity)
+                    .take_while(|&i| self.table[i].is_some()) // a lookup stops at an empty bucket
+                    .position(|i| matching(&i))
+            }
+            Storage::Chained(chains) => {
+                let hash = make_hash(self.hasher(), key) as usize;
+                chains.chain(hash).position(|i| matching(&i))
+            }
+            Storage::Cuckoo(_) => self.cuckoo_buckets(key).position(|i| matching(&i)),
+            Storage::Hopscotch(hops) => {
+                let hash = make_hash(self.hasher(), key) as usize;
+                hops.neighbors(hash).position(|i| matching(&i))
+            }
+        };
+
+        position.map(|n| n + 1)
+    }
+
+    /// Average number of reads of a lookup for a missing key, over every home bucket.
+    fn expected_miss_length(&self) -> f64 {
+        let average = |total: usize| total as f64 / self.capacity as f64;
+
+        match &self.storage {
+            Storage::Small => self.len as f64, // comparing every key
+            Storage::Open => {
+                let probe = self.params.probe;
+                average((0..self.capacity)
+                    .map(|home| {
+                        // any odd second hash, for the strategies using one
+                        let hash2 = home.wrapping_mul(0x9e37_79b9) | 1;
+                        (0..self.capacity)
+                            .map(|i| probe.probe(home, hash2, i) % self.capacity)
+                            .position(|i| self.table[i].is_none())
+                            .map_or(self.capacity, |n| n + 1)
+                    })
+                    .sum())
+            }
+            Storage::Chained(chains) => average((0..self.capacity).map(|home| chains.bucket_chain(home).count()).sum()),
+            Storage::Cuckoo(_) => self.cuckoo_miss_length(),
+            Storage::Hopscotch(hops) => average((0..self.capacity).map(|home| hops.home_neighbors(home).count()).sum()),
+        }
+    }
+
+    /// Size of the longest run of occupied buckets (wrapping around the table),
+    /// or of the longest chain.
+    fn longest_cluster(&self) -> usize {
+        if let Storage::Chained(chains) = &self.storage {
+            return (0..self.capacity).map(|home| chains.bucket_chain(home).count()).max().unwrap_or(0);
+        }
+
+        if self.len == self.capacity {
+            return self.capacity;
+        }
+
+        // starting after an empty bucket, so that no run wraps around the start
+        let start = self.table.iter().position(|bucket| bucket.is_none()).unwrap_or(0);
+        let (mut longest, mut run) = (0, 0);
+        for i in 0..self.capacity {
+            if self.table[(start + i) % self.capacity].is_some() {
+                run += 1;
+                longest = longest.max(run);
+            } else {
+                run = 0;
+            }
+        }
+        longest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hash::IndexHasherBuilder;
+    use crate::tests::{each_backend, lear_vocabulary};
+    use crate::{Backend, Index, Parameters, Probing};
+
+    use std::string::ToString;
+    use std::vec;
+
+    #[test]
+    fn every_backend() {
+        let words = lear_vocabulary();
+
+        each_backend(|params| {
+            let backend = params.backend;
+            let mut index = Index::with_capacity_and_parameters(1, params);
+            for (i, word) in words.iter().enumerate() {
+                index.insert(word.clone(), i);
+            }
+
+            let stats = index.stats();
+
+            assert_eq!(stats.len, words.len());
+            assert_eq!(stats.capacity, index.capacity());
+            assert_eq!(stats.probe_lengths[0], 0);
+            assert_eq!(stats.probe_lengths.iter().sum::<usize>(), words.len());
+            assert_eq!(stats.max_probe_length, stats.probe_lengths.len() - 1);
+            assert!(stats.average_probe_length >= 1.0);
+            assert!(stats.average_probe_length <= stats.max_probe_length as f64);
+            assert!(stats.expected_miss_length > 0.0);
+            assert!(stats.longest_cluster >= 1);
+            assert!(stats.resizes > 0);
+            assert!(stats.rehashed >= stats.resizes - 1);
+
+            if backend == Backend::Cuckoo {
+                assert!(stats.max_probe_length <= 4);
+            }
+        });
+    }
+
+    #[test]
+    fn linear_probing_clusters() {
+        let params: Parameters<IndexHasherBuilder> = Parameters {
+            max_load: 1.0,
+            probe: Probing::Custom(|_, i| i), // every key collides
+            ..Parameters::default()
+        };
+        let mut index = Index::with_capacity_and_parameters(10, params);
+        for i in 0..5 {
+            index.insert(i, i);
+        }
+
+        let stats = index.stats();
+
+        assert_eq!(stats.probe_lengths, vec![0, 1, 1, 1, 1, 1]);
+        assert_eq!(stats.average_probe_length, 3.0);
+        assert_eq!(stats.max_probe_length, 5);
+        assert_eq!(stats.expected_miss_length, 6.0);
+        assert_eq!(stats.longest_cluster, 5);
+        assert_eq!((stats.resizes, stats.rehashed), (0, 0));
+
+        // growing from 10 to 20 buckets moves the 5 entries
+        index.resize(20);
+        assert_eq!((index.stats().resizes, index.stats().rehashed), (1, 5));
+    }
+
+    #[test]
+    fn layout_glyphs() {
+        let params: Parameters<IndexHasherBuilder> = Parameters {
+            max_load: 1.0,
+            probe: Probing::Custom(|_, i| i), // every key collides
+            small_capacity: 0,
+            ..Parameters::default()
+        };
+        let mut index = Index::with_capacity_and_parameters(40, params);
+
+        // an entry in its home bucket is found with one read
+        index.insert(0, 0);
+        assert_eq!(index.stats().probe_lengths, vec![0, 1]);
+        assert_eq!(index.layout(), "   0 | 1.......................................\n");
+
+        for i in 1..37 {
+            index.insert(i, i);
+        }
+        assert_eq!(index.layout(), "   0 | 123456789abcdefghijklmnopqrstuvwxyz##...\n");
+    }
+
+    #[test]
+    fn keys_changed_through_iter_mut() {
+        let words = lear_vocabulary();
+
+        each_backend(|params| {
+            let mut index = Index::with_capacity_and_parameters(1, params);
+            for (i, word) in words.iter().enumerate() {
+                index.insert(word.clone(), i);
+            }
+            for mut entry in index.iter_mut().filter(|entry| entry.1 < 3) {
+                entry.0.push_str("!!");
+            }
+
+            let stats = index.stats();
+
+            assert_eq!(stats.unreachable, 3);
+            assert_eq!(stats.probe_lengths.iter().sum::<usize>(), words.len() - 3);
+            assert!(stats.to_string().contains("unreachable keys: 3"));
+            assert_eq!(index.layout().matches('!').count(), 3);
+        });
+    }
+}
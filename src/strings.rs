@@ -0,0 +1,721 @@
+//! Module implementing [`StrIndex`], a hash table specialized for string
+//! keys, and the [`Interner`] of strings.
+//!
+//! The `StrIndex` copies the bytes of every key in a single arena, so that
+//! inserting a key allocates nothing but the growth of the arena and of the
+//! table, and its buckets only hold the offset, length and hash of the key
+//! next to the value. Lookups take a `&str` and never allocate.
+//!
+//! The `Interner` maps repeated strings, like file names, to compact
+//! [`Symbol`] ids, which are resolved back to the string in the arena. Its
+//! buckets only hold the symbols, the keys are stored once, by symbol.
+//!
+//! Both tables use open addressing, probing with the lookup of the [`Index`]
+//! according to their [`Parameters`], and allocate their buckets through a
+//! [`RawStorage`] like an `Index`.
+//!
+//! [`StrIndex`]: struct.StrIndex.html
+//! [`Interner`]: struct.Interner.html
+//! [`Symbol`]: struct.Symbol.html
+//! [`Index`]: ../struct.Index.html
+//! [`Parameters`]: ../struct.Parameters.html
+//! [`RawStorage`]: ../raw/trait.RawStorage.html
+
+use super::hash::*;
+use super::iter::*;
+use super::raw::{GlobalStorage, RawStorage, RawTable};
+use super::{find_in, probe_hashes, Bucket, Find, Parameters, Probing};
+use super::{DEFAULT_BACKEND, DEFAULT_GROWTH_POLICY, DEFAULT_INITIAL_CAPACITY, DEFAULT_MAX_LOAD, DEFAULT_PROBING, DEFAULT_SMALL_CAPACITY};
+
+use alloc::string::String;
+use core::cell::{Ref, RefCell, RefMut};
+use core::convert::TryFrom;
+use core::fmt;
+use core::hash::BuildHasher;
+use core::mem;
+use core::ops::Range;
+
+/// Key of a `StrIndex` bucket: the bytes of the key in the arena, and its hash.
+#[derive(Debug, Clone, Copy)]
+pub struct StrKey {
+    offset: usize,
+    len: usize,
+    hash: usize,
+}
+
+impl StrKey {
+    /// Returns the range of the key in the arena.
+    fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.len
+    }
+}
+
+/// Returns `capacity` empty buckets allocated from `storage`.
+fn empty_table<K, V, A: RawStorage>(capacity: usize, storage: A) -> RawTable<Bucket<K, V>, A> {
+    let mut table = RawTable::with_capacity_in(capacity, storage);
+    for _ in 0..capacity {
+        table.push(Bucket::None);
+    }
+    table
+}
+
+/// Returns the probing hashes of a key stored in the arena, only hashing
+/// its bytes again for the second hash.
+fn stored_hashes<S: BuildHasher>(params: &Parameters<S>, arena: &str, key: &StrKey) -> (usize, usize) {
+    if params.probe.uses_second_hash() {
+        probe_hashes(&params.hasher_builder, params.probe, &arena[key.range()])
+    } else {
+        (key.hash, 0)
+    }
+}
+
+/// Stores the entry in the first empty bucket of the probe sequence of `hashes`,
+/// or gives it back if the sequence reaches none.
+fn place_in<K, V>(table: &mut [Bucket<K, V>], probe: Probing, hashes: (usize, usize), pair: (K, V)) -> Result<(), (K, V)> {
+    match find_in(table, probe, hashes, |_| false).0 {
+        (_, Some(i)) => {
+            table[i] = Bucket::Some(RefCell::new(pair));
+            Ok(())
+        }
+        _ => Err(pair),
+    }
+}
+
+/// Hash table with string keys, stored together in an arena.
+///
+/// The table uses open addressing with the [`Probing`] strategy, maximum load
+/// and growth policy of its [`Parameters`], like an [`Index`]. The backend,
+/// small capacity and filter of the parameters are not used.
+///
+/// The buckets are allocated from the [`RawStorage`] of the table, the last
+/// type parameter, while the arena of the keys is a `String`.
+///
+/// # Example
+///
+/// ```
+/// use index::strings::StrIndex;
+///
+/// let mut index: StrIndex<u32> = StrIndex::new();
+///
+/// for word in "the king the fool the crown".split(' ') {
+///     let count = index.get(word).map_or(0, |count| *count);
+///     index.insert(word, count + 1);
+/// }
+///
+/// assert_eq!(*index.get("the").unwrap(), 3);
+/// assert_eq!(index.len(), 4);
+///
+/// // "the", "king", "fool" and "crown" were copied once
+/// assert_eq!(index.key_bytes(), 16);
+/// ```
+///
+/// [`Probing`]: ../enum.Probing.html
+/// [`Parameters`]: ../struct.Parameters.html
+/// [`Index`]: ../struct.Index.html
+/// [`RawStorage`]: ../raw/trait.RawStorage.html
+#[derive(Clone)]
+pub struct StrIndex<V, S = IndexHasherBuilder, A: RawStorage = GlobalStorage> {
+    params: Parameters<S>,
+    len: usize,
+    arena: String,
+    table: RawTable<Bucket<StrKey, V>, A>,
+}
+
+impl<V> StrIndex<V, IndexHasherBuilder> {
+    /// Creates an empty `StrIndex` with default initial capacity and default parameters.
+    pub fn new() -> StrIndex<V, IndexHasherBuilder> {
+        StrIndex::with_capacity(DEFAULT_INITIAL_CAPACITY)
+    }
+
+    /// Creates an empty `StrIndex` with the specified capacity and default parameters.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::strings::StrIndex;
+    ///
+    /// let index: StrIndex<u32> = StrIndex::with_capacity(64);
+    ///
+    /// assert_eq!(index.capacity(), 64);
+    /// ```
+    pub fn with_capacity(capacity: usize) -> StrIndex<V, IndexHasherBuilder> {
+        StrIndex::with_capacity_and_parameters(capacity, Parameters::default())
+    }
+}
+
+impl<V, S> StrIndex<V, S> {
+    /// Creates an empty `StrIndex` with the specified capacity and parameters.
+    ///
+    /// # Panics
+    ///
+    /// Panics on invalid parameters, like [`Index::with_capacity_and_parameters`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::hash_map::RandomState;
+    /// use index::{Parameters, Probing};
+    /// use index::strings::StrIndex;
+    ///
+    /// let params = Parameters {
+    ///     hasher_builder: RandomState::new(),
+    ///     probe: Probing::DoubleHashing,
+    ///     max_load: 0.9,
+    ///     ..Parameters::default()
+    /// };
+    /// let mut index: StrIndex<&str, _> = StrIndex::with_capacity_and_parameters(8, params);
+    ///
+    /// index.insert("king", "lear");
+    /// assert_eq!(*index.get("king").unwrap(), "lear");
+    /// ```
+    ///
+    /// [`Index::with_capacity_and_parameters`]: ../struct.Index.html#method.with_capacity_and_parameters
+    pub fn with_capacity_and_parameters(capacity: usize, params: Parameters<S>) -> StrIndex<V, S> {
+        StrIndex::with_capacity_and_parameters_in(capacity, params, GlobalStorage)
+    }
+}
+
+impl<V, S, A: RawStorage> StrIndex<V, S, A> {
+    /// Creates an empty `StrIndex` with the specified capacity and parameters,
+    /// allocating its buckets from `storage` (see [`RawStorage`]).
+    ///
+    /// # Panics
+    ///
+    /// Panics on invalid parameters, like [`with_capacity_and_parameters`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::Parameters;
+    /// use index::hash::IndexHasherBuilder;
+    /// use index::raw::CountingStorage;
+    /// use index::strings::StrIndex;
+    ///
+    /// let storage = CountingStorage::new();
+    /// let params: Parameters<IndexHasherBuilder> = Parameters::default();
+    /// let mut index = StrIndex::with_capacity_and_parameters_in(16, params, storage.clone());
+    ///
+    /// index.insert("king", "lear");
+    ///
+    /// assert!(storage.bytes() > 0);
+    /// drop(index);
+    /// assert_eq!(storage.bytes(), 0);
+    /// ```
+    ///
+    /// [`RawStorage`]: ../raw/trait.RawStorage.html
+    /// [`with_capacity_and_parameters`]: #method.with_capacity_and_parameters
+    pub fn with_capacity_and_parameters_in(capacity: usize, params: Parameters<S>, storage: A) -> StrIndex<V, S, A> {
+        params.check();
+
+        StrIndex {
+            params,
+            len: 0,
+            arena: String::new(),
+            table: empty_table(if capacity == 0 { DEFAULT_INITIAL_CAPACITY } else { capacity }, storage),
+        }
+    }
+
+    /// Returns the number of buckets of the `StrIndex`.
+    pub fn capacity(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Returns the number of elements in the `StrIndex`.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the `StrIndex` contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes of the keys stored in the arena.
+    pub fn key_bytes(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// Returns a reference to the hasher builder used in the `StrIndex`.
+    pub fn hasher(&self) -> &S {
+        &self.params.hasher_builder
+    }
+
+    /// Returns the probing strategy of the `StrIndex`.
+    pub fn probe(&self) -> Probing {
+        self.params.probe
+    }
+
+    /// Returns the storage the buckets of the `StrIndex` are allocated from.
+    pub fn raw_storage(&self) -> &A {
+        self.table.storage()
+    }
+
+    /// Returns the current load of the `StrIndex`.
+    pub fn load(&self) -> f64 {
+        self.len as f64 / self.capacity() as f64
+    }
+
+    /// Clears the `StrIndex` and its arena, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        for bucket in self.table.iter_mut() {
+            *bucket = Bucket::None;
+        }
+        self.arena.clear();
+        self.len = 0;
+    }
+
+    /// Returns an iterator over the keys of the `StrIndex`.
+    pub fn keys(&self) -> StrKeys<'_, V> {
+        StrKeys { iter: self.iter() }
+    }
+
+    /// Returns an iterator over the values of the `StrIndex`.
+    pub fn values(&self) -> Values<'_, StrKey, V> {
+        Values::new(&self.table)
+    }
+
+    /// Returns an iterator over the entries of the `StrIndex`.
+    pub fn iter(&self) -> StrIter<'_, V> {
+        StrIter { arena: &self.arena, buckets: self.table.iter() }
+    }
+}
+
+impl<V, S, A> StrIndex<V, S, A>
+where
+    S: BuildHasher,
+    A: RawStorage,
+{
+    /// Searches the buckets for the entry of `key`.
+    fn find(&self, key: &str) -> Find<'_, StrKey, V> {
+        let hashes = probe_hashes(&self.params.hasher_builder, self.params.probe, key);
+        let arena = &self.arena;
+
+        find_in(&self.table, self.params.probe, hashes, |p| p.0.hash == hashes.0 && &arena[p.0.range()] == key).0
+    }
+
+    /// Inserts key-value pair in the `StrIndex`, returning the replaced value
+    /// if the key was present.
+    ///
+    /// The key is copied in the arena unless it was present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use index::strings::StrIndex;
+    ///
+    /// let mut index = StrIndex::new();
+    ///
+    /// assert_eq!(index.insert("fool", 1), None);
+    /// assert_eq!(index.insert("fool", 2), Some(1));
+    /// assert_eq!(index.key_bytes(), 4);
+    /// ```
+    pub fn insert(&mut self, key: &str, value: V) -> Option<V> {
+        if let (Some(pair), _) = self.find(key) {
+            return Some(mem::replace(&mut pair.borrow_mut().1, value));
+        }
+
+        let hash = make_hash(&self.params.hasher_builder, key) as usize;
+        let stored = StrKey { offset: self.arena.len(), len: key.len(), hash };
+        self.arena.push_str(key);
+
+        if self.load() >= self.params.max_load {
+            self.grow();
+        }
+        self.place(stored, value);
+        self.len += 1;
+        None
+    }
+
+    /// Places an entry in an empty bucket, growing the `StrIndex` if its probe sequence reaches none.
+    fn place(&mut self, key: StrKey, value: V) {
+        let hashes = stored_hashes(&self.params, &self.arena, &key);
+
+        if let Err((key, value)) = place_in(&mut self.table, self.params.probe, hashes, (key, value)) {
+            self.grow();
+            self.place(key, value)
+        }
+    }
+
+    /// Grows the `StrIndex` according to its growth policy, moving the entries
+    /// without copying their keys.
+    fn grow(&mut self) {
+        let capacity = self.params.grown_capacity(self.capacity());
+        let storage = self.table.storage().clone();
+        let mut old = mem::replace(&mut self.table, empty_table(capacity, storage));
+
+        for (key, value) in old.iter_mut().filter_map(Option::take).map(RefCell::into_inner) {
+            self.place(key, value);
+        }
+    }
+
+    /// Returns `true` if the `StrIndex` contains the specified key.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.find(key).0.is_some()
+    }
+
+    /// Returns a reference to the value associated with the specified key
+    /// if the lookup found a match, else it returns `None`.
+    pub fn get(&self, key: &str) -> Option<Ref<'_, V>> {
+        self.find(key).0.map(|pair| Ref::map(pair.borrow(), |p| &p.1))
+    }
+
+    /// Returns a mutable reference to the value associated with the specified key
+    /// if the lookup found a match, else it returns `None`.
+    pub fn get_mut(&self, key: &str) -> Option<RefMut<'_, V>> {
+        self.find(key).0.map(|pair| RefMut::map(pair.borrow_mut(), |p| &mut p.1))
+    }
+}
+
+impl<V, S, A> fmt::Debug for StrIndex<V, S, A>
+where
+    V: fmt::Debug,
+    A: RawStorage,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (key, value) in self.iter() {
+            map.entry(&key, &*value);
+        }
+        map.finish()
+    }
+}
+
+impl<V> Default for StrIndex<V, IndexHasherBuilder> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Iterator over the entries of a [`StrIndex`], the keys borrowed from its arena.
+///
+/// [`StrIndex`]: struct.StrIndex.html
+pub struct StrIter<'a, V> {
+    arena: &'a str,
+    buckets: core::slice::Iter<'a, Bucket<StrKey, V>>,
+}
+
+impl<'a, V> Iterator for StrIter<'a, V> {
+    type Item = (&'a str, Ref<'a, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let arena = self.arena;
+        self.buckets.by_ref().flatten().next().map(|pair| {
+            let pair = pair.borrow();
+            (&arena[pair.0.range()], Ref::map(pair, |p| &p.1))
+        })
+    }
+}
+
+/// Iterator over the keys of a [`StrIndex`].
+///
+/// [`StrIndex`]: struct.StrIndex.html
+pub struct StrKeys<'a, V> {
+    iter: StrIter<'a, V>,
+}
+
+impl<'a, V> Iterator for StrKeys<'a, V> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, _)| key)
+    }
+}
+
+/// Compact id of a string in an [`Interner`].
+///
+/// Symbols are numbered from zero in the order the strings were first interned,
+/// and are only meaningful to the `Interner` which returned them.
+///
+/// [`Interner`]: struct.Interner.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Returns the number of the `Symbol`.
+    pub fn id(self) -> u32 {
+        self.0
+    }
+}
+
+/// String interner, returning the same [`Symbol`] for equal strings.
+///
+/// The strings are stored once, in an arena like the keys of a [`StrIndex`],
+/// and the buckets hold the symbols. The table uses open addressing according
+/// to its [`Parameters`], and allocates its buckets and the keys of the symbols
+/// from its [`RawStorage`], like a `StrIndex`.
+///
+/// # Example
+///
+/// ```
+/// use index::strings::Interner;
+///
+/// let mut files = Interner::new();
+///
+/// let lear = files.intern("lear.txt");
+/// let hamlet = files.intern("hamlet.txt");
+///
+/// assert_eq!(files.intern("lear.txt"), lear);
+/// assert_ne!(lear, hamlet);
+/// assert_eq!(files.resolve(hamlet), "hamlet.txt");
+/// assert_eq!(files.len(), 2);
+/// ```
+///
+/// [`Symbol`]: struct.Symbol.html
+/// [`StrIndex`]: struct.StrIndex.html
+/// [`Parameters`]: ../struct.Parameters.html
+/// [`RawStorage`]: ../raw/trait.RawStorage.html
+#[derive(Clone)]
+pub struct Interner<S = IndexHasherBuilder, A: RawStorage = GlobalStorage> {
+    params: Parameters<S>,
+    arena: String,
+    keys: RawTable<StrKey, A>, // by symbol
+    table: RawTable<Bucket<Symbol, ()>, A>,
+}
+
+impl Interner<IndexHasherBuilder> {
+    /// Creates an empty `Interner` with default parameters.
+    pub fn new() -> Interner<IndexHasherBuilder> {
+        Interner::with_parameters(Parameters::default())
+    }
+}
+
+impl<S> Interner<S> {
+    /// Creates an empty `Interner` with the specified hasher builder, and otherwise default parameters.
+    pub fn with_hasher(hasher_builder: S) -> Interner<S> {
+        Interner::with_parameters(Parameters {
+            max_load: DEFAULT_MAX_LOAD,
+            growth_policy: DEFAULT_GROWTH_POLICY,
+            hasher_builder,
+            probe: DEFAULT_PROBING,
+            backend: DEFAULT_BACKEND,
+            small_capacity: DEFAULT_SMALL_CAPACITY,
+            filter: None,
+        })
+    }
+
+    /// Creates an empty `Interner` with the specified parameters.
+    ///
+    /// # Panics
+    ///
+    /// Panics on invalid parameters, like [`Index::with_capacity_and_parameters`].
+    ///
+    /// [`Index::with_capacity_and_parameters`]: ../struct.Index.html#method.with_capacity_and_parameters
+    pub fn with_parameters(params: Parameters<S>) -> Interner<S> {
+        Interner::with_parameters_in(params, GlobalStorage)
+    }
+}
+
+impl<S, A: RawStorage> Interner<S, A> {
+    /// Creates an empty `Interner` with the specified parameters, allocating its
+    /// buckets and the keys of its symbols from `storage` (see [`RawStorage`]).
+    ///
+    /// # Panics
+    ///
+    /// Panics on invalid parameters, like [`with_parameters`].
+    ///
+    /// [`RawStorage`]: ../raw/trait.RawStorage.html
+    /// [`with_parameters`]: #method.with_parameters
+    pub fn with_parameters_in(params: Parameters<S>, storage: A) -> Interner<S, A> {
+        params.check();
+
+        Interner {
+            params,
+            arena: String::new(),
+            keys: RawTable::with_capacity_in(0, storage.clone()),
+            table: empty_table(DEFAULT_INITIAL_CAPACITY, storage),
+        }
+    }
+
+    /// Returns the storage the buckets of the `Interner` are allocated from.
+    pub fn raw_storage(&self) -> &A {
+        self.table.storage()
+    }
+
+    /// Returns the number of distinct strings in the `Interner`.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if the `Interner` contains no strings.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the string of the specified symbol.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the symbol was not returned by this `Interner`.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.arena[self.keys[symbol.0 as usize].range()]
+    }
+
+    /// Returns an iterator over the symbols and strings of the `Interner`, in
+    /// the order of the symbols.
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &str)> + '_ {
+        self.keys.iter().enumerate().map(move |(i, key)| (Symbol(i as u32), &self.arena[key.range()]))
+    }
+}
+
+impl<S, A> Interner<S, A>
+where
+    S: BuildHasher,
+    A: RawStorage,
+{
+    /// Returns the symbol of the specified string, interning it first if needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Interner` already holds `u32::MAX` strings.
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(symbol) = self.get(string) {
+            return symbol;
+        }
+
+        let symbol = Symbol(u32::try_from(self.keys.len()).expect("too many strings in the Interner"));
+        if self.keys.len() as f64 / self.table.len() as f64 >= self.params.max_load {
+            self.grow();
+        }
+
+        let hash = make_hash(&self.params.hasher_builder, string) as usize;
+        self.keys.push(StrKey { offset: self.arena.len(), len: string.len(), hash });
+        self.arena.push_str(string);
+        self.place(symbol);
+        symbol
+    }
+
+    /// Returns the symbol of the specified string if it was interned.
+    pub fn get(&self, string: &str) -> Option<Symbol> {
+        let hashes = probe_hashes(&self.params.hasher_builder, self.params.probe, string);
+        let (keys, arena) = (&self.keys, &self.arena);
+
+        let matching = |symbol: Symbol| {
+            let key = &keys[symbol.0 as usize];
+            key.hash == hashes.0 && &arena[key.range()] == string
+        };
+        let ((found, _), _) = find_in(&self.table, self.params.probe, hashes, |p| matching(p.0));
+        found.map(|pair| pair.borrow().0)
+    }
+
+    /// Places a symbol in an empty bucket, growing the `Interner` if its probe sequence reaches none.
+    fn place(&mut self, symbol: Symbol) {
+        let hashes = stored_hashes(&self.params, &self.arena, &self.keys[symbol.0 as usize]);
+
+        if place_in(&mut self.table, self.params.probe, hashes, (symbol, ())).is_err() {
+            self.grow();
+            self.place(symbol)
+        }
+    }
+
+    /// Grows the `Interner` according to its growth policy.
+    fn grow(&mut self) {
+        let capacity = self.params.grown_capacity(self.table.len());
+        let storage = self.table.storage().clone();
+        let mut old = mem::replace(&mut self.table, empty_table(capacity, storage));
+
+        for (symbol, ()) in old.iter_mut().filter_map(Option::take).map(RefCell::into_inner) {
+            self.place(symbol);
+        }
+    }
+}
+
+impl<S, A: RawStorage> fmt::Debug for Interner<S, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter().map(|(symbol, string)| (symbol.0, string))).finish()
+    }
+}
+
+impl Default for Interner<IndexHasherBuilder> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::CountingStorage;
+    use crate::tests::lear_vocabulary;
+    use crate::Index;
+
+    use std::format;
+
+    #[test]
+    fn agrees_with_index() {
+        let words = lear_vocabulary();
+
+        for &probe in &[Probing::Linear, Probing::Quadratic, Probing::DoubleHashing] {
+            let params: Parameters<IndexHasherBuilder> = Parameters { probe, ..Parameters::default() };
+            let mut strings = StrIndex::with_capacity_and_parameters(1, params);
+            let mut index = Index::new();
+
+            for (i, word) in words.iter().enumerate() {
+                assert_eq!(strings.insert(word, i), None);
+                index.insert(word.as_str(), i);
+            }
+            for (i, word) in words.iter().enumerate().step_by(3) {
+                assert_eq!(strings.insert(word, i * 2), Some(i));
+                index.insert(word.as_str(), i * 2);
+            }
+
+            assert_eq!(strings.len(), index.len());
+            assert_eq!(strings.key_bytes(), words.iter().map(|word| word.len()).sum::<usize>());
+            assert!(strings.iter().all(|(key, value)| *index.get(key).unwrap() == *value));
+            assert!(words.iter().all(|word| *strings.get(word).unwrap() == *index.get(word.as_str()).unwrap()));
+            assert!(!strings.contains_key("cordelia's"));
+        }
+    }
+
+    #[test]
+    fn interned_file_names() {
+        let mut interner = Interner::new();
+        let names = ["lear.txt", "hamlet.txt", "", "lear.txt", "macbeth.txt", "hamlet.txt"];
+
+        let symbols: std::vec::Vec<Symbol> = names.iter().map(|name| interner.intern(name)).collect();
+
+        assert_eq!(symbols.iter().map(|symbol| symbol.id()).collect::<std::vec::Vec<_>>(), [0, 1, 2, 0, 3, 1]);
+        assert_eq!(interner.len(), 4);
+        assert!(names.iter().zip(&symbols).all(|(name, symbol)| interner.resolve(*symbol) == *name));
+        assert_eq!(interner.get("othello.txt"), None);
+        assert_eq!(format!("{:?}", interner), r#"{0: "lear.txt", 1: "hamlet.txt", 2: "", 3: "macbeth.txt"}"#);
+    }
+
+    #[test]
+    fn interner_follows_parameters() {
+        let words = lear_vocabulary();
+
+        for &probe in &[Probing::Linear, Probing::Quadratic, Probing::DoubleHashing] {
+            let params: Parameters<IndexHasherBuilder> = Parameters { probe, max_load: 0.5, ..Parameters::default() };
+            let mut interner = Interner::with_parameters(params);
+
+            for (i, word) in words.iter().enumerate() {
+                assert_eq!(interner.intern(word).id(), i as u32);
+            }
+
+            assert!(interner.len() as f64 / interner.table.len() as f64 <= 0.5);
+            assert!(words.iter().enumerate().all(|(i, word)| interner.get(word).map(Symbol::id) == Some(i as u32)));
+            assert!(words.iter().enumerate().all(|(i, word)| interner.resolve(Symbol(i as u32)) == word));
+        }
+    }
+
+    #[test]
+    fn counted_storage() {
+        let words = lear_vocabulary();
+        let storage = CountingStorage::new();
+
+        let params: Parameters<IndexHasherBuilder> = Parameters::default();
+        let mut strings = StrIndex::with_capacity_and_parameters_in(1, params.clone(), storage.clone());
+        let mut interner = Interner::with_parameters_in(params, storage.clone());
+        for (i, word) in words.iter().enumerate() {
+            strings.insert(word, i);
+            interner.intern(word);
+        }
+
+        let buckets = strings.capacity() * mem::size_of::<Bucket<StrKey, usize>>();
+        let symbols = interner.table.len() * mem::size_of::<Bucket<Symbol, ()>>() + interner.keys.capacity() * mem::size_of::<StrKey>();
+        assert_eq!(storage.bytes(), buckets + symbols);
+
+        drop((strings, interner));
+        assert_eq!(storage.bytes(), 0);
+    }
+}
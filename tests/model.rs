@@ -0,0 +1,371 @@
+//! Model-based testing of the `Index` against `std::collections::HashMap`.
+//!
+//! Random sequences of operations, drawn from a deterministic seed, are
+//! applied to both tables under many configurations, checking after every
+//! step that they agree. A failing sequence is shrunk to a minimal one
+//! before being reported.
+//!
+//! The seed and the number of cases can be changed with the
+//! `INDEX_MODEL_SEED` and `INDEX_MODEL_CASES` environment variables.
+
+use index::filter::Filter;
+use index::hash::IndexHasherBuilder;
+use index::{Backend, Index, Parameters, Probing};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+const DEFAULT_SEED: u64 = 0x1ea2_c0de;
+const DEFAULT_CASES: usize = 300;
+const OPS_PER_CASE: usize = 200;
+
+/// Keys are drawn from a small range, for frequent hits and collisions.
+const KEYS: u16 = 64;
+
+/// `Probing::Custom` function stepping over 3 buckets at a time.
+fn probe_odd_steps(hash: usize, i: usize) -> usize {
+    hash.wrapping_add(3 * i)
+}
+
+/// `Probing::Custom` function with cubic steps.
+fn probe_cubic(hash: usize, i: usize) -> usize {
+    hash.wrapping_add(i.wrapping_mul(i).wrapping_mul(i))
+}
+
+/// `Probing::CustomDouble` function adding quadratic steps to double hashing.
+fn probe_double_squares(hash: usize, hash2: usize, i: usize) -> usize {
+    hash.wrapping_add(hash2.wrapping_mul(i)).wrapping_add(i.wrapping_mul(i))
+}
+
+/// Xorshift64* pseudo-random generator.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Insert(u16, u32),
+    Get(u16),
+    GetMut(u16, u32),
+    Clear,
+    /// Drains the specified number of entries, then drops the `Drain`.
+    Drain(usize),
+}
+
+impl Op {
+    fn random(rng: &mut Rng) -> Op {
+        let key = rng.below(KEYS as usize) as u16;
+        match rng.below(100) {
+            0..=44 => Op::Insert(key, rng.below(1000) as u32),
+            45..=74 => Op::Get(key),
+            75..=94 => Op::GetMut(key, rng.below(1000) as u32),
+            95..=97 => Op::Clear,
+            _ => Op::Drain(rng.below(8)),
+        }
+    }
+
+    /// Simpler variants of the operation, tried when shrinking.
+    fn simplifications(self) -> Vec<Op> {
+        let mut simpler = Vec::new();
+        match self {
+            Op::Insert(k, v) => {
+                if v != 0 {
+                    simpler.push(Op::Insert(k, 0));
+                }
+                if k != 0 {
+                    simpler.push(Op::Insert(0, v));
+                    simpler.push(Op::Insert(k / 2, v));
+                }
+            }
+            Op::Get(k) if k != 0 => simpler.extend(&[Op::Get(0), Op::Get(k / 2)]),
+            Op::GetMut(k, v) => {
+                simpler.push(Op::Get(k));
+                if k != 0 {
+                    simpler.push(Op::GetMut(k / 2, v));
+                }
+            }
+            Op::Drain(n) if n != 0 => simpler.extend(&[Op::Clear, Op::Drain(n / 2)]),
+            _ => {}
+        }
+        simpler
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Config {
+    capacity: usize,
+    max_load: f64,
+    growth_policy: f64,
+    probe: Probing,
+    backend: Backend,
+    small_capacity: usize,
+    filter: Option<Filter>,
+}
+
+impl Config {
+    fn random(rng: &mut Rng) -> Config {
+        let backend = rng.pick(&[Backend::OpenAddressing, Backend::SeparateChaining, Backend::Cuckoo, Backend::Hopscotch]);
+        let max_load = match backend {
+            Backend::SeparateChaining => rng.pick(&[0.5, 1.0, 2.0, 4.0]),
+            _ => rng.pick(&[0.3, 0.5, 0.7, 0.9, 0.99]),
+        };
+
+        Config {
+            capacity: rng.pick(&[0, 1, 2, 3, 5, 8, 13, 64, 100]),
+            max_load,
+            growth_policy: rng.pick(&[1.01, 1.3, 1.5, 2.0, 3.0]),
+            probe: rng.pick(&[
+                Probing::Linear,
+                Probing::Quadratic,
+                Probing::Triangular,
+                Probing::DoubleHashing,
+                Probing::Custom(probe_cubic),
+                Probing::Custom(probe_odd_steps),
+                Probing::CustomDouble(probe_double_squares),
+            ]),
+            backend,
+            small_capacity: rng.pick(&[0, 1, 8, 32]),
+            filter: rng.pick(&[
+                None,
+                Some(Filter::Bloom { false_positive_rate: 0.1 }),
+                Some(Filter::Cuckoo { false_positive_rate: 0.1 }),
+            ]),
+        }
+    }
+
+    fn index(&self) -> Index<u16, u32> {
+        let params: Parameters<IndexHasherBuilder> = Parameters {
+            max_load: self.max_load,
+            growth_policy: self.growth_policy,
+            probe: self.probe,
+            backend: self.backend,
+            small_capacity: self.small_capacity,
+            filter: self.filter,
+            ..Parameters::default()
+        };
+        Index::with_capacity_and_parameters(self.capacity, params)
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "capacity {}, max_load {}, growth_policy {}, {:?} probing, {:?}, small_capacity {}, {:?} filter",
+            self.capacity, self.max_load, self.growth_policy, self.probe, self.backend, self.small_capacity, self.filter
+        )
+    }
+}
+
+/// Returns an error if the `Index` and the model hold different entries, looking up
+/// every key, and a few absent ones.
+fn check(index: &Index<u16, u32>, model: &HashMap<u16, u32>) -> Result<(), String> {
+    if index.len() != model.len() || index.is_empty() != model.is_empty() {
+        return Err(format!("len is {}, expected {}", index.len(), model.len()));
+    }
+
+    let mut entries: Vec<(u16, u32)> = index.iter().map(|pair| *pair).collect();
+    entries.sort_unstable();
+    let mut expected: Vec<(u16, u32)> = model.iter().map(|(&k, &v)| (k, v)).collect();
+    expected.sort_unstable();
+    if entries != expected {
+        return Err(format!("iter yielded {:?}, expected {:?}", entries, expected));
+    }
+
+    for k in 0..2 * KEYS {
+        if index.get(&k).map(|v| *v).as_ref() != model.get(&k) {
+            return Err(format!("get({}) disagrees with iter", k));
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies `op` to both tables, and returns an error if their results differ.
+fn apply(index: &mut Index<u16, u32>, model: &mut HashMap<u16, u32>, op: Op) -> Result<(), String> {
+    match op {
+        Op::Insert(k, v) => {
+            let old = index.insert(k, v).map(|pair| pair.into_inner());
+            let expected = model.insert(k, v).map(|old| (k, old));
+            if old != expected {
+                return Err(format!("insert returned {:?}, expected {:?}", old, expected));
+            }
+        }
+        Op::Get(k) => {
+            let found = index.get(&k).map(|v| *v);
+            if found.as_ref() != model.get(&k) {
+                return Err(format!("get returned {:?}, expected {:?}", found, model.get(&k)));
+            }
+        }
+        Op::GetMut(k, v) => {
+            let found = index.get_mut(&k).map(|mut value| std::mem::replace(&mut *value, v));
+            let expected = model.get_mut(&k).map(|value| std::mem::replace(value, v));
+            if found != expected {
+                return Err(format!("get_mut returned {:?}, expected {:?}", found, expected));
+            }
+        }
+        Op::Clear => {
+            index.clear();
+            model.clear();
+        }
+        Op::Drain(n) => {
+            let drained: Vec<(u16, u32)> = index.drain().take(n).collect();
+            if drained.len() != n.min(model.len()) {
+                return Err(format!("drain yielded {} entries, expected {}", drained.len(), n.min(model.len())));
+            }
+            for (k, v) in drained {
+                if model.get(&k) != Some(&v) {
+                    return Err(format!("drain yielded ({}, {}), expected {:?}", k, v, model.get(&k)));
+                }
+            }
+            model.clear(); // dropping the drain empties the table
+        }
+    }
+
+    check(index, model)
+}
+
+/// Runs the operations on a fresh `Index`, returning the step and description of the first
+/// disagreement (panics included).
+fn run(config: &Config, ops: &[Op]) -> Result<(), String> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut index = config.index();
+        let mut model = HashMap::new();
+        for (step, &op) in ops.iter().enumerate() {
+            apply(&mut index, &mut model, op).map_err(|e| format!("step {} ({:?}): {}", step, op, e))?;
+        }
+        Ok(())
+    }));
+
+    result.unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_default();
+        Err(format!("panicked: {}", message))
+    })
+}
+
+/// Shrinks a failing sequence of operations, removing chunks of operations
+/// then simplifying the remaining ones, as long as `fails` keeps failing.
+fn shrink<F: Fn(&[Op]) -> bool>(mut ops: Vec<Op>, fails: F) -> Vec<Op> {
+    loop {
+        let mut shrunk = false;
+
+        let mut chunk = ops.len() / 2;
+        while chunk > 0 {
+            let mut start = 0;
+            while start + chunk <= ops.len() {
+                let candidate: Vec<Op> = ops[..start].iter().chain(&ops[start + chunk..]).cloned().collect();
+                if fails(&candidate) {
+                    ops = candidate;
+                    shrunk = true;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+
+        for i in 0..ops.len() {
+            for simpler in ops[i].simplifications() {
+                let mut candidate = ops.clone();
+                candidate[i] = simpler;
+                if fails(&candidate) {
+                    ops = candidate;
+                    shrunk = true;
+                    break;
+                }
+            }
+        }
+
+        if !shrunk {
+            return ops;
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+#[test]
+fn agrees_with_hash_map() {
+    let seed = env_or("INDEX_MODEL_SEED", DEFAULT_SEED);
+    let cases = env_or("INDEX_MODEL_CASES", DEFAULT_CASES);
+    let mut rng = Rng::new(seed);
+
+    // silencing the panics caught while running and shrinking
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut failure = None;
+    for case in 0..cases {
+        let config = Config::random(&mut rng);
+        let ops: Vec<Op> = (0..OPS_PER_CASE).map(|_| Op::random(&mut rng)).collect();
+
+        if run(&config, &ops).is_err() {
+            let minimal = shrink(ops, |ops| run(&config, ops).is_err());
+            let error = run(&config, &minimal).unwrap_err();
+            failure = Some(format!(
+                "case {} of seed {:#x} failed\nconfig: {:?}\nminimal sequence: {:?}\nerror: {}",
+                case, seed, config, minimal, error
+            ));
+            break;
+        }
+    }
+
+    panic::set_hook(hook);
+    if let Some(failure) = failure {
+        panic!("{}", failure);
+    }
+}
+
+#[test]
+fn shrinks_to_minimal_sequence() {
+    // failing when key 5 is read after being inserted with a value above 10
+    let fails = |ops: &[Op]| {
+        let mut inserted = false;
+        ops.iter().any(|&op| match op {
+            Op::Insert(5, v) if v > 10 => {
+                inserted = true;
+                false
+            }
+            Op::Get(5) => inserted,
+            _ => false,
+        })
+    };
+
+    let mut rng = Rng::new(42);
+    let mut ops: Vec<Op> = (0..OPS_PER_CASE).map(|_| Op::random(&mut rng)).collect();
+    ops.insert(17, Op::Insert(5, 500));
+    ops.insert(150, Op::Get(5));
+    assert!(fails(&ops));
+
+    let minimal = shrink(ops, fails);
+
+    assert_eq!(minimal.len(), 2);
+    assert!(matches!(minimal[0], Op::Insert(5, v) if v > 10));
+    assert_eq!(minimal[1], Op::Get(5));
+}